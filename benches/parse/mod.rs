@@ -47,3 +47,11 @@ fn parse_small_else_template(b: &mut Bencher) {
     "{{#p}}{{^}}{{/p}}".parse::<Template>().ok();
   })
 }
+
+#[bench]
+fn parse_unclosed_expressions(b: &mut Bencher) {
+  let template: String = (0..2000).map(|i| format!("a {{{{ b{} ", i)).collect();
+  b.iter(|| {
+    template.parse::<Template>().ok();
+  })
+}
@@ -0,0 +1,72 @@
+//! Compile time checked templates for [rumblebars](https://nicolas-cherel.github.io/rumblebars).
+//!
+//! `hbs!` parses an inline template while compiling, a syntax error fails the
+//! build instead of an `unwrap()` at runtime. The macro expands to an expression
+//! building the parsed `Template` at runtime, without parsing it again : as
+//! templates own their entries, it can't be used in `const` or `static` position.
+//!
+//! ```
+//! extern crate rustc_serialize as serialize;
+//! extern crate rumblebars;
+//! #[macro_use] extern crate rumblebars_macros;
+//! # fn main() {
+//! use serialize::json::Json;
+//!
+//! let template = hbs!("{{#each names}}hello {{this}}! {{/each}}");
+//! let data = Json::from_str(r#"{"names": ["you", "them"]}"#).unwrap();
+//!
+//! assert_eq!(template.eval_to_string(&data).unwrap(), "hello you! hello them! ");
+//! # }
+//! ```
+//!
+//! Parse errors are reported on the template literal :
+//!
+//! ```compile_fail
+//! extern crate rumblebars;
+//! #[macro_use] extern crate rumblebars_macros;
+//! # fn main() {
+//! let template = hbs!("{{#if a}}{{/each}}");
+//! # }
+//! ```
+
+extern crate proc_macro;
+extern crate rumblebars;
+extern crate syn;
+
+use proc_macro::{TokenStream, TokenTree, Group, Span};
+
+/// Parses a template string literal at compile time, and expands to an expression building the `rumblebars::Template`.
+#[proc_macro]
+pub fn hbs(input: TokenStream) -> TokenStream {
+  let literal = match syn::parse::<syn::LitStr>(input) {
+    Ok(literal) => literal,
+    Err(err) => return compile_error("hbs! expects a single template string literal", err.span().unwrap()),
+  };
+  let span = literal.span().unwrap();
+
+  match rumblebars::parse(&literal.value()) {
+    Ok(template) => respan(rumblebars::template_expression(&template).parse().unwrap(), span),
+    Err(err) => compile_error(&format!("invalid template, {}", err), span),
+  }
+}
+
+fn compile_error(message: &str, span: Span) -> TokenStream {
+  respan(format!("compile_error!({:?})", message).parse().unwrap(), span)
+}
+
+// generated tokens point to the template literal in compiler messages
+fn respan(stream: TokenStream, span: Span) -> TokenStream {
+  stream.into_iter().map(|token| {
+    match token {
+      TokenTree::Group(group) => {
+        let mut respanned = Group::new(group.delimiter(), respan(group.stream(), span));
+        respanned.set_span(span);
+        TokenTree::Group(respanned)
+      },
+      mut token => {
+        token.set_span(span);
+        token
+      },
+    }
+  }).collect()
+}
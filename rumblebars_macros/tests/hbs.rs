@@ -0,0 +1,63 @@
+extern crate rustc_serialize as serialize;
+extern crate rumblebars;
+#[macro_use] extern crate rumblebars_macros;
+
+use serialize::json::Json;
+use rumblebars::{EvalContext, Template, parse};
+
+macro_rules! forwarded {
+  ($template:expr) => (hbs!($template))
+}
+
+fn rendered(template: &Template, data: &Json) -> String {
+  let mut context = EvalContext::new();
+  context.register_partial("p".to_string(), parse("<{{name}}>").unwrap());
+
+  let mut out = Vec::new();
+  template.eval(data, &mut out, &context).unwrap();
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn hbs_builds_parsed_template() {
+  let sources = [
+    "hello {{name}}",
+    "{{#each list as |item i|}}{{i}}: {{{item}}}\n{{else}}none{{/each}}",
+    "{{#if (eq a 1)}}{{> p this}}{{else if b}}{{~b~}}{{/if}}",
+    "{{log \"a\" 1.5 true null key=-2 other=path.to}}",
+    "  {{> p}}\n{{{{raw}}}}{{x}}{{{{/raw}}}}{{* deco}}",
+  ];
+  let templates = [
+    hbs!("hello {{name}}"),
+    hbs!("{{#each list as |item i|}}{{i}}: {{{item}}}\n{{else}}none{{/each}}"),
+    hbs!("{{#if (eq a 1)}}{{> p this}}{{else if b}}{{~b~}}{{/if}}"),
+    hbs!(r#"{{log "a" 1.5 true null key=-2 other=path.to}}"#),
+    hbs!("  {{> p}}\n{{{{raw}}}}{{x}}{{{{/raw}}}}{{* deco}}"),
+  ];
+
+  for (source, template) in sources.iter().zip(templates.iter()) {
+    assert_eq!(format!("{:?}", template.entries), format!("{:?}", parse(source).unwrap().entries));
+  }
+}
+
+#[test]
+fn hbs_literal_escapes() {
+  assert_eq!(
+    format!("{:?}", hbs!("{{a}}\n\t\"b\" \\ \x41\u{e9}\u{1_F600} c\
+                          d").entries),
+    format!("{:?}", parse("{{a}}\n\t\"b\" \\ A\u{e9}\u{1F600} cd").unwrap().entries)
+  );
+  assert_eq!(
+    format!("{:?}", hbs!(r#"{{a}} "b" \n"#).entries),
+    format!("{:?}", parse("{{a}} \"b\" \\n").unwrap().entries)
+  );
+}
+
+#[test]
+fn hbs_renders() {
+  let data = Json::from_str(r#"{"name": "<you>", "list": ["a", "<b>"]}"#).unwrap();
+
+  assert_eq!(rendered(&hbs!("hello {{name}}, {{> p}}"), &data), "hello &lt;you&gt;, <&lt;you&gt;>");
+  assert_eq!(rendered(&hbs!("{{#each list}}{{@index}}{{{.}}} {{/each}}"), &data), "0a 1<b> ");
+  assert_eq!(rendered(&forwarded!("{{#if name}}\\{{name}}{{/if}}"), &data), "{{name}}");
+}
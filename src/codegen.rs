@@ -0,0 +1,678 @@
+use std::fmt;
+
+use parse::Template;
+use parse::Entries;
+use parse::HBEntry;
+use parse::HBExpression;
+use parse::HBValHolder;
+use eval::EvalContext;
+
+/// Reasons for a template not to be compiled to Rust.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum CodegenError {
+  /// partial that renders itself, it can't be inlined
+  RecursivePartial(String),
+}
+
+impl fmt::Display for CodegenError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CodegenError::RecursivePartial(ref name) => write!(f, "recursive partial ‘{}’ can't be compiled", name),
+    }
+  }
+}
+
+/// Generates the source of a Rust function rendering the template like `eval()`.
+///
+/// Helpers and partials are resolved from `eval_context` at generation time :
+/// partials are inlined, and `if`, `unless`, `with` and `each` are compiled to
+/// their builtin behaviour unless they were overridden. Other helpers, sub
+/// expressions, decorators, partial blocks and partials with hash options or
+/// computed names are rendered through `eval()` by the generated code, from
+/// entries built once per thread.
+///
+/// The generated function is declared as
+/// `pub fn name(data: &HBData, out: &mut io::Write, eval_context: &EvalContext) -> HBEvalResult`,
+/// it is meant to be written from a build script, used with `include!`, and
+/// called with an evaluation context holding the same helpers and partials as
+/// `eval_context`.
+///
+/// ```
+/// use rumblebars::EvalContext;
+///
+/// let template = rumblebars::parse("{{#each items}}<b>{{name}}</b>{{/each}}").unwrap();
+/// let code = rumblebars::compile_to_rust(&template, "render_items", &EvalContext::new()).unwrap();
+///
+/// assert!(code.contains("pub fn render_items(data: &::rumblebars::HBData, out: &mut ::std::io::Write, eval_context: &::rumblebars::EvalContext)"));
+/// ```
+pub fn compile_to_rust(template: &Template, fn_name: &str, eval_context: &EvalContext) -> Result<String, CodegenError> {
+  let mut generator = Generator {
+    eval_context: eval_context,
+    code: String::new(),
+    level: 1,
+    vars: 0,
+    partials: vec![],
+  };
+
+  let scope = Scope { context: "c0".to_string(), stack: "s0".to_string(), globals: "g0".to_string(), params: "p0".to_string(), indent: None };
+  try!(generator.entries(&template.entries, &scope));
+
+  Ok(format!(
+"#[allow(unused_variables, unused_mut)]
+pub fn {}(data: &::rumblebars::HBData, out: &mut ::std::io::Write, eval_context: &::rumblebars::EvalContext) -> ::rumblebars::HBEvalResult {{
+  use ::rumblebars::codegen_runtime as rt;
+  let mut html_safe = ::rumblebars::HTMLSafeWriter::new(out);
+  let out = &mut ::rumblebars::SafeWriting::Safe(&mut html_safe);
+  let g0 = rt::globals(data, &eval_context.level);
+  let p0 = rt::block_params();
+  let s0 = vec![data];
+  let c0 = data;
+{}  Ok(())
+}}
+", fn_name, generator.code))
+}
+
+/// Generates a Rust expression building the template, without parsing it.
+///
+/// This is what the `hbs!` macro of the `rumblebars_macros` crate expands to,
+/// the expression only uses public items of `::rumblebars`.
+///
+/// ```
+/// let template = rumblebars::parse("{{#if a}}b{{/if}}").unwrap();
+/// let code = rumblebars::template_expression(&template);
+///
+/// assert!(code.starts_with("::rumblebars::Template { entries: vec![Box::new(::rumblebars::HBEntry::Eval("));
+/// ```
+pub fn template_expression(template: &Template) -> String {
+  format!("::rumblebars::Template {{ entries: {} }}", entries_expression(&template.entries))
+}
+
+fn entries_expression(entries: &[Box<HBEntry>]) -> String {
+  let entries: Vec<_> = entries.iter().map(|entry| {
+    match **entry {
+      HBEntry::Raw(ref raw) => format!("Box::new(::rumblebars::HBEntry::Raw({:?}.to_string()))", raw),
+      HBEntry::Eval(ref exp) => format!("Box::new(::rumblebars::HBEntry::Eval({}))", expression_expression(exp)),
+      HBEntry::Partial(ref exp) => format!("Box::new(::rumblebars::HBEntry::Partial({}))", expression_expression(exp)),
+    }
+  }).collect();
+
+  format!("vec![{}]", entries.join(", "))
+}
+
+fn expression_expression(exp: &HBExpression) -> String {
+  let options: Vec<_> = exp.options.iter().map(|&(ref name, ref value)| {
+    format!("({:?}.to_string(), {})", name, value_expression(value))
+  }).collect();
+  let block = |block: &Option<Box<Entries>>| match *block {
+    Some(ref entries) => format!("Some(Box::new({}))", entries_expression(entries)),
+    None => "None".to_string(),
+  };
+  let render_options = &exp.render_options;
+
+  format!(
+    "::rumblebars::HBExpression {{ base: {}, params: vec![{}], options: vec![{}], block_params: {}, \
+     render_options: ::rumblebars::RenderOptions {{ escape: {}, inverse: {}, indent: {}, no_leading_whitespace: {}, \
+     no_trailing_whitespace: {}, raw_block: {}, decorator: {} }}, block: {}, else_block: {} }}",
+    strings_expression(&exp.base),
+    exp.params.iter().map(value_expression).collect::<Vec<_>>().join(", "),
+    options.join(", "),
+    strings_expression(&exp.block_params),
+    render_options.escape,
+    render_options.inverse,
+    render_options.indent.as_ref().map(|i| format!("Some({:?}.to_string())", i)).unwrap_or("None".to_string()),
+    render_options.no_leading_whitespace,
+    render_options.no_trailing_whitespace,
+    render_options.raw_block,
+    render_options.decorator,
+    block(&exp.block),
+    block(&exp.else_block),
+  )
+}
+
+fn value_expression(value: &HBValHolder) -> String {
+  match *value {
+    HBValHolder::String(ref s) => format!("::rumblebars::HBValHolder::String({:?}.to_string())", s),
+    HBValHolder::Path(ref path) => format!("::rumblebars::HBValHolder::Path({})", strings_expression(path)),
+    HBValHolder::Literal(_, ref s) => {
+      format!("::rumblebars::HBValHolder::Literal(::rumblebars::codegen_runtime::literal({:?}), {:?}.to_string())", s, s)
+    },
+    HBValHolder::SubExpression(ref exp) => format!("::rumblebars::HBValHolder::SubExpression({})", expression_expression(exp)),
+  }
+}
+
+fn strings_expression(strings: &[String]) -> String {
+  let strings: Vec<_> = strings.iter().map(|s| format!("{:?}.to_string()", s)).collect();
+  format!("vec![{}]", strings.join(", "))
+}
+
+// names of the variables holding the evaluation state
+struct Scope {
+  context: String,
+  stack: String,
+  globals: String,
+  params: String,
+  // partials indentation, along with the variable telling if output is at the start of a line
+  indent: Option<(String, String)>,
+}
+
+impl Scope {
+  fn with_context(&self, context: &str) -> Scope {
+    Scope { context: context.to_string(), stack: self.stack.clone(), globals: self.globals.clone(), params: self.params.clone(), indent: self.indent.clone() }
+  }
+}
+
+struct Generator<'a> {
+  eval_context: &'a EvalContext,
+  code: String,
+  level: usize,
+  vars: usize,
+  // partials being inlined, to detect recursion
+  partials: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+  fn line(&mut self, line: &str) {
+    for _ in 0..self.level {
+      self.code.push_str("  ");
+    }
+    self.code.push_str(line);
+    self.code.push('\n');
+  }
+
+  fn var(&mut self, prefix: &str) -> String {
+    self.vars += 1;
+    format!("{}{}", prefix, self.vars)
+  }
+
+  fn nested(&mut self, open: &str, entries: &Entries, scope: &Scope, close: &str) -> Result<(), CodegenError> {
+    self.line(open);
+    self.level += 1;
+    try!(self.entries(entries, scope));
+    self.level -= 1;
+    self.line(close);
+    Ok(())
+  }
+
+  fn entries(&mut self, entries: &Entries, scope: &Scope) -> Result<(), CodegenError> {
+    // decorators change the environment of their whole block
+    if entries.iter().any(|e| match **e { HBEntry::Eval(ref exp) => exp.render_options.decorator, _ => false }) {
+      self.runtime(entries, scope);
+      return Ok(());
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+      try!(match **entry {
+        HBEntry::Raw(ref raw) => {
+          self.raw(raw, scope);
+          Ok(())
+        },
+        HBEntry::Partial(ref exp) => self.partial(exp, &entries[index..index + 1], scope),
+        HBEntry::Eval(ref exp) => match (exp.base.len(), exp.block.is_some()) {
+          (1, _) if self.builtin(&exp.base[0]) => self.helper(exp, scope),
+          (1, _) if self.eval_context.has_helper_with_name(&exp.base[0]) => {
+            self.runtime(&entries[index..index + 1], scope);
+            Ok(())
+          },
+          (_, false) => {
+            self.value(exp, scope);
+            Ok(())
+          },
+          (_, true) => self.section(exp, scope),
+        },
+      });
+    }
+
+    Ok(())
+  }
+
+  // builtin helpers are compiled, unless they were overridden
+  fn builtin(&self, name: &str) -> bool {
+    ["if", "unless", "with", "each"].contains(&name) && self.eval_context.helper_with_name(name).map(|h| h.is_builtin()).unwrap_or(false)
+  }
+
+  // entries rendered through `eval()` when the generated code runs, they are built once per
+  // thread rather than on each render
+  fn runtime(&mut self, entries: &[Box<HBEntry>], scope: &Scope) {
+    let var = self.var("E");
+    let indent = match scope.indent {
+      Some((ref indent, ref line_start)) => format!("Some({:?}), &mut {}", indent, line_start),
+      None => "None, &mut true".to_string(),
+    };
+
+    self.line(&format!("thread_local!(static {}: ::rumblebars::Entries = {});", var, entries_expression(entries)));
+    self.line(&format!("try!({}.with(|e| rt::eval(e, {}, &{}, &{}, &{}, out, eval_context, {})));", var, scope.context, scope.stack, scope.globals, scope.params, indent));
+  }
+
+  fn raw(&mut self, raw: &str, scope: &Scope) {
+    match scope.indent {
+      _ if raw.is_empty() => (),
+      Some((ref indent, ref line_start)) => self.line(&format!("try!(rt::write_indented_raw(out, {:?}, {:?}, &mut {}));", raw, indent, line_start)),
+      None => self.line(&format!("try!(rt::write_raw(out, {:?}));", raw)),
+    }
+  }
+
+  fn lookup(&self, path: &[String], scope: &Scope, compat: bool) -> String {
+    format!("rt::lookup({}, &{:?}, &{}, &{}, &{}, {})", scope.context, path, scope.stack, scope.globals, scope.params, compat)
+  }
+
+  fn value(&mut self, exp: &HBExpression, scope: &Scope) {
+    let lookup = self.lookup(&exp.base, scope, self.eval_context.compat);
+    let escape = exp.render_options.escape;
+    match scope.indent {
+      Some((ref indent, ref line_start)) => self.line(&format!("try!(rt::write_indented_value(out, {}, {}, {:?}, &mut {}));", lookup, escape, indent, line_start)),
+      None => self.line(&format!("try!(rt::write_value(out, {}, {}));", lookup, escape)),
+    }
+  }
+
+  // declares a variable holding a helper param value, and returns its name
+  fn param(&mut self, value: &HBValHolder, scope: &Scope) -> String {
+    let var = self.var("v");
+
+    match *value {
+      HBValHolder::String(ref s) => {
+        self.line(&format!("let {}: &::rumblebars::HBData = &{:?}.to_string();", var, s));
+      },
+      HBValHolder::Path(ref path) => {
+        let lookup = self.lookup(path, scope, false);
+        self.line(&format!("let {} = rt::param({});", var, lookup));
+      },
+      HBValHolder::Literal(_, ref s) => {
+        let lookup = self.lookup(&[s.clone()], scope, false);
+        self.line(&format!("let {}_literal = rt::literal({:?});", var, s));
+        self.line(&format!("let {} = {}.unwrap_or(&{}_literal);", var, lookup, var));
+      },
+      HBValHolder::SubExpression(ref exp) => {
+        let expression = var.to_uppercase();
+        self.line(&format!("thread_local!(static {}: ::rumblebars::HBExpression = {});", expression, expression_expression(exp)));
+        self.line(&format!("let {}_value = try!({}.with(|x| rt::sub_expression(x, {}, &{}, &{}, &{}, eval_context)));",
+          var, expression, scope.context, scope.stack, scope.globals, scope.params));
+        self.line(&format!("let {}: &::rumblebars::HBData = &{}_value;", var, var));
+      },
+    }
+
+    var
+  }
+
+  fn section(&mut self, exp: &HBExpression, scope: &Scope) -> Result<(), CodegenError> {
+    let block = exp.block.as_ref().unwrap();
+
+    // raw blocks without helper are rendered as is
+    if exp.render_options.raw_block {
+      return self.entries(block, scope);
+    }
+
+    let lookup = self.lookup(&exp.base, scope, self.eval_context.compat);
+    let (context, stack) = (self.var("c"), self.var("s"));
+
+    self.line(&format!("match rt::section({}, {}) {{", lookup, exp.render_options.inverse));
+    self.level += 1;
+    self.line("rt::Section::Block(contexts) => {");
+    self.level += 1;
+    self.line(&format!("let {} = rt::push(&{}, {});", stack, scope.stack, scope.context));
+    let block_scope = Scope { context: context.clone(), stack: stack, globals: scope.globals.clone(), params: scope.params.clone(), indent: scope.indent.clone() };
+    try!(self.nested(&format!("for {} in contexts {{", context), block, &block_scope, "}"));
+    self.level -= 1;
+    self.line("},");
+    match exp.else_block {
+      Some(ref else_block) => try!(self.nested("rt::Section::Else => {", else_block, scope, "},")),
+      None => self.line("rt::Section::Else => (),"),
+    }
+    self.level -= 1;
+    self.line("}");
+
+    Ok(())
+  }
+
+  fn helper(&mut self, exp: &HBExpression, scope: &Scope) -> Result<(), CodegenError> {
+    let name = &exp.base[0];
+
+    // without block builtin helpers render nothing
+    let block: &Entries = match exp.block {
+      Some(ref block) => block,
+      None => return Ok(()),
+    };
+    let empty = vec![];
+    let else_block: &Entries = exp.else_block.as_ref().map(|b| &**b).unwrap_or(&empty);
+
+    // helpers blocks are rendered without indentation, within the same context stack
+    let helper_scope = Scope { context: scope.context.clone(), stack: scope.stack.clone(), globals: scope.globals.clone(), params: scope.params.clone(), indent: None };
+
+    match &name[..] {
+      "if" | "unless" => {
+        // condition lookup follows compat mode, unlike other params
+        let condition = match exp.params.first() {
+          Some(&HBValHolder::Path(ref path)) => format!("rt::truthy({})", self.lookup(path, scope, self.eval_context.compat)),
+          Some(p) => format!("rt::truthy(Some({}))", self.param(p, scope)),
+          None => "false".to_string(),
+        };
+        let (then_block, otherwise) = if (name == "if") != exp.render_options.inverse { (block, else_block) } else { (else_block, block) };
+
+        try!(self.branches(&condition, then_block, &helper_scope, None, otherwise, &helper_scope));
+      },
+      "with" => {
+        match (exp.params.first(), exp.params.len()) {
+          (Some(p), 1) => {
+            let context = self.param(p, scope);
+            let mut with_scope = helper_scope.with_context(&context);
+            let bind = if exp.block_params.is_empty() {
+              None
+            } else {
+              with_scope.params = self.var("p");
+              Some(format!("let {} = rt::bind(&{}, &{:?}, &[{}]);", with_scope.params, scope.params, exp.block_params, context))
+            };
+
+            try!(self.branches(&format!("rt::truthy(Some({}))", context), block, &with_scope, bind, else_block, &helper_scope));
+          },
+          _ => try!(self.entries(else_block, &helper_scope)),
+        }
+      },
+      _ => {
+        let each_context = match exp.params.first() {
+          Some(p) => format!("Some({})", self.param(p, scope)),
+          None => "None".to_string(),
+        };
+        let (item, context, globals) = (self.var("i"), self.var("c"), self.var("g"));
+        let params = if exp.block_params.is_empty() { scope.params.clone() } else { self.var("p") };
+        let item_scope = Scope { context: context.clone(), stack: scope.stack.clone(), globals: globals.clone(), params: params.clone(), indent: None };
+
+        self.line(&format!("match rt::each({}, {}) {{", scope.context, each_context));
+        self.level += 1;
+        self.line(&format!("rt::Each::Items(items) => for {} in items.iter() {{", item));
+        self.level += 1;
+        self.line(&format!("let {} = {}.context;", context, item));
+        self.line(&format!("let {} = {}.globals(&{});", globals, item, scope.globals));
+        if !exp.block_params.is_empty() {
+          self.line(&format!("let {} = {}.block_params(&{}, &{:?});", params, item, scope.params, exp.block_params));
+        }
+        try!(self.entries(block, &item_scope));
+        self.level -= 1;
+        self.line("},");
+        if else_block.is_empty() {
+          self.line("rt::Each::Else => (),");
+        } else {
+          try!(self.nested("rt::Each::Else => {", else_block, &helper_scope, "},"));
+        }
+        try!(self.nested("rt::Each::Once => {", block, &helper_scope, "},"));
+        self.level -= 1;
+        self.line("}");
+      },
+    }
+
+    Ok(())
+  }
+
+  // if/else statement, the else branch is left out when empty
+  fn branches(&mut self, condition: &str, then: &Entries, then_scope: &Scope, then_prelude: Option<String>, otherwise: &Entries, otherwise_scope: &Scope) -> Result<(), CodegenError> {
+    match (then.is_empty(), otherwise.is_empty()) {
+      (true, true) => (),
+      (true, false) => try!(self.nested(&format!("if !{} {{", condition), otherwise, otherwise_scope, "}")),
+      (false, _) => {
+        self.line(&format!("if {} {{", condition));
+        self.level += 1;
+        if let Some(prelude) = then_prelude {
+          self.line(&prelude);
+        }
+        try!(self.entries(then, then_scope));
+        self.level -= 1;
+        if otherwise.is_empty() {
+          self.line("}");
+        } else {
+          try!(self.nested("} else {", otherwise, otherwise_scope, "}"));
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  fn partial(&mut self, exp: &HBExpression, entry: &[Box<HBEntry>], scope: &Scope) -> Result<(), CodegenError> {
+    // partial blocks, hash options, and computed names or contexts are left to the runtime
+    let computed = exp.params.iter().any(|p| match *p { HBValHolder::SubExpression(_) => true, _ => false });
+    if exp.block.is_some() || !exp.options.is_empty() || computed {
+      self.runtime(entry, scope);
+      return Ok(());
+    }
+
+    let name = match exp.base.len() {
+      0 => return Ok(()),
+      _ => exp.base.join("/"),
+    };
+
+    // a missing partial renders nothing
+    let partial = match self.eval_context.partial_with_name(&name) {
+      Some(partial) => partial,
+      None => return Ok(()),
+    };
+
+    if self.partials.contains(&name) {
+      return Err(CodegenError::RecursivePartial(name));
+    }
+
+    let context = match exp.params.first() {
+      Some(&HBValHolder::Path(ref path)) => {
+        let context = self.var("c");
+        let lookup = self.lookup(path, scope, self.eval_context.compat);
+        self.line(&format!("let {} = {}.unwrap_or({});", context, lookup, scope.context));
+        context
+      },
+      _ => scope.context.clone(),
+    };
+
+    // partials don't see block params of the blocks rendering them
+    let mut partial_scope = scope.with_context(&context);
+    partial_scope.params = "p0".to_string();
+    partial_scope.indent = match (&scope.indent, &exp.render_options.indent) {
+      (&Some((ref i, ref line_start)), &Some(ref j)) => Some((format!("{}{}", i, j), line_start.clone())),
+      (&Some(ref i), &None) => Some(i.clone()),
+      // standalone partials start a line, nested ones are tracked along with their parent lines
+      (&None, &Some(ref j)) => {
+        let line_start = self.var("l");
+        self.line(&format!("let mut {} = true;", line_start));
+        Some((j.clone(), line_start))
+      },
+      (&None, &None) => None,
+    };
+
+    self.partials.push(name);
+    try!(self.nested("{", &partial.entries, &partial_scope, "}"));
+    self.partials.pop();
+
+    Ok(())
+  }
+}
+
+/// Functions used by generated code, see `compile_to_rust()`.
+pub mod runtime {
+  use std::io;
+  use std::io::Write;
+  use std::collections::HashMap;
+  use serialize::json::{Json, ToJson};
+
+  use eval::{HBData, HBNodeType, HBEvalResult, SafeWriting, IndentWriter, EvalContext, Helper};
+  use eval::{value_for_key_path_in_context, eval_from_state};
+  use parse::{Entries, HBExpression, HBValHolder, literal_value};
+
+  lazy_static! {
+    static ref FALSY: Json = Json::Null;
+  }
+
+  pub fn globals<'a>(data: &'a HBData, level: &'a String) -> HashMap<&'a str, &'a HBData> {
+    let mut globals = HashMap::new();
+    globals.insert("@root", data);
+    globals.insert("@level", level as &HBData);
+    globals
+  }
+
+  pub fn block_params<'a>() -> HashMap<&'a str, &'a HBData> {
+    HashMap::new()
+  }
+
+  pub fn lookup<'a, S: AsRef<str>>(data: &'a HBData, path: &[S], stack: &[&'a HBData], globals: &HashMap<&str, &'a HBData>, block_params: &HashMap<&str, &'a HBData>, compat: bool) -> Option<&'a HBData> {
+    value_for_key_path_in_context(data, path, stack, globals, block_params, compat)
+  }
+
+  /// helper param value, missing values are falsy
+  pub fn param<'a>(value: Option<&'a HBData>) -> &'a HBData {
+    value.unwrap_or(&*FALSY)
+  }
+
+  pub fn literal(source: &str) -> Json {
+    match literal_value(source.to_string()) {
+      HBValHolder::Literal(value, _) => value,
+      _ => Json::Null,
+    }
+  }
+
+  pub fn truthy(value: Option<&HBData>) -> bool {
+    value.map(|v| v.as_bool()).unwrap_or(false)
+  }
+
+  pub fn push<'a>(stack: &[&'a HBData], context: &'a HBData) -> Vec<&'a HBData> {
+    let mut pushed = stack.to_vec();
+    pushed.push(context);
+    pushed
+  }
+
+  pub fn bind<'a>(block_params: &HashMap<&'a str, &'a HBData>, names: &'a [&'a str], values: &[&'a HBData]) -> HashMap<&'a str, &'a HBData> {
+    let mut bound = block_params.clone();
+    for (name, value) in names.iter().zip(values.iter()) {
+      bound.insert(*name, *value);
+    }
+    bound
+  }
+
+  /// renders entries like `eval()` does, from the current evaluation state
+  pub fn eval<'a>(entries: &'a Entries, context: &'a HBData, stack: &[&'a HBData], globals: &HashMap<&str, &'a HBData>, block_params: &HashMap<&str, &'a HBData>, out: &mut SafeWriting, eval_context: &'a EvalContext, indent: Option<&str>, line_start: &mut bool) -> HBEvalResult {
+    eval_from_state(entries, context, out, eval_context, globals, block_params, &stack.to_vec(), indent.map(|i| i.to_string()), line_start)
+  }
+
+  pub fn sub_expression<'a>(exp: &'a HBExpression, context: &'a HBData, stack: &[&'a HBData], globals: &HashMap<&str, &'a HBData>, block_params: &HashMap<&str, &'a HBData>, eval_context: &'a EvalContext) -> io::Result<Json> {
+    Helper::eval_sub_expression(exp, context, &stack.to_vec(), globals, block_params, eval_context)
+  }
+
+  pub fn write_raw(out: &mut SafeWriting, raw: &str) -> HBEvalResult {
+    out.into_unsafe().write_all(raw.as_bytes())
+  }
+
+  pub fn write_indented_raw(out: &mut SafeWriting, raw: &str, indent: &str, line_start: &mut bool) -> HBEvalResult {
+    IndentWriter::with_indent(Some(indent.to_string()), true, line_start, &mut out.into_unsafe(), &|w| w.write_all(raw.as_bytes()))
+  }
+
+  pub fn write_value(out: &mut SafeWriting, value: Option<&HBData>, escape: bool) -> HBEvalResult {
+    write_value_with_indent(out, value, escape, None, &mut false)
+  }
+
+  pub fn write_indented_value(out: &mut SafeWriting, value: Option<&HBData>, escape: bool, indent: &str, line_start: &mut bool) -> HBEvalResult {
+    write_value_with_indent(out, value, escape, Some(indent.to_string()), line_start)
+  }
+
+  fn write_value_with_indent(out: &mut SafeWriting, value: Option<&HBData>, escape: bool, indent: Option<String>, line_start: &mut bool) -> HBEvalResult {
+    let value = match value.map(|v| (v, v.typed_node())) {
+      Some((v, HBNodeType::Leaf(_))) | Some((v, HBNodeType::Array(_))) => v,
+      _ => return Ok(()),
+    };
+
+    if escape {
+      IndentWriter::with_indent(indent, false, line_start, out, &|w| value.write_value(w))
+    } else {
+      IndentWriter::with_indent(indent, false, line_start, &mut out.into_unsafe(), &|w| value.write_value(w))
+    }
+  }
+
+  /// contexts a section block is rendered with
+  pub enum Section<'a> {
+    Block(Vec<&'a HBData>),
+    Else,
+  }
+
+  pub fn section<'a>(value: Option<&'a HBData>, inverse: bool) -> Section<'a> {
+    let value = param(value);
+
+    let contexts = match value.typed_node() {
+      HBNodeType::Array(_) => {
+        let (len, _) = value.values().size_hint();
+        match (len == 0, inverse) {
+          (true, true) => vec![&*FALSY as &HBData],
+          (true, false) | (false, true) => vec![],
+          (false, false) => value.values().collect(),
+        }
+      },
+      _ if value.as_bool() != inverse => vec![value],
+      _ => vec![],
+    };
+
+    if contexts.is_empty() { Section::Else } else { Section::Block(contexts) }
+  }
+
+  /// `each` helper iteration, with the data it binds
+  pub struct Iteration<'a> {
+    pub context: &'a HBData,
+    key: Option<String>,
+    index: Json,
+    first: Json,
+    last: Json,
+  }
+
+  impl<'a> Iteration<'a> {
+    pub fn globals<'b>(&'b self, globals: &HashMap<&'b str, &'b HBData>) -> HashMap<&'b str, &'b HBData> {
+      let mut bound = globals.clone();
+      bound.insert(if self.key.is_some() { "@key" } else { "@index" }, self.position());
+      bound.insert("@first", &self.first);
+      bound.insert("@last", &self.last);
+      bound
+    }
+
+    pub fn block_params<'b>(&'b self, block_params: &HashMap<&'b str, &'b HBData>, names: &'b [&'b str]) -> HashMap<&'b str, &'b HBData> {
+      bind(block_params, names, &[self.context, self.position()])
+    }
+
+    // key of object items, index of array items
+    fn position(&self) -> &HBData {
+      match self.key {
+        Some(ref key) => key,
+        None => &self.index,
+      }
+    }
+  }
+
+  pub enum Each<'a> {
+    Items(Vec<Iteration<'a>>),
+    Else,
+    Once,
+  }
+
+  // same as the `each` builtin helper
+  pub fn each<'a>(context: &'a HBData, param: Option<&'a HBData>) -> Each<'a> {
+    let use_context = param.unwrap_or(context);
+
+    match use_context.typed_node() {
+      HBNodeType::Array(_) => {
+        let (len, _) = use_context.values().size_hint();
+        if len == 0 {
+          return Each::Else;
+        }
+        Each::Items(use_context.values().enumerate().map(|(index, item)| Iteration {
+          context: item,
+          key: None,
+          index: index.to_json(),
+          first: (index == 0).to_json(),
+          last: (index == len - 1).to_json(),
+        }).collect())
+      },
+      HBNodeType::Branch(_) => {
+        let (len, _) = use_context.keys().size_hint();
+        if len == 0 {
+          return Each::Else;
+        }
+        Each::Items(use_context.keys().enumerate().filter_map(|(index, key)| use_context.get_key(key).map(|item| Iteration {
+          context: item,
+          key: Some(key.to_string()),
+          index: Json::Null,
+          first: (index == 0).to_json(),
+          last: (index == len - 1).to_json(),
+        })).collect())
+      },
+      HBNodeType::Leaf(_) | HBNodeType::Null => Each::Once,
+    }
+  }
+}
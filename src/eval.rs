@@ -4,20 +4,21 @@ use serialize::json::Json;
 use std::collections::HashMap;
 use std::vec::Vec;
 use std::default::Default;
-use regex::Regex;
 
 use parse::Template;
 use parse::Entries;
 use parse::HBEntry;
 use parse::HBExpression;
 use parse::HBValHolder;
+use parse::RenderOptions;
 
 
-fn value_for_key_path_in_context<'a>(
+pub fn value_for_key_path_in_context<'a, S: AsRef<str>>(
   data: &'a HBData,
-  key_path: &Vec<String>,
-  context_stack: &Vec<&'a HBData>,
+  key_path: &[S],
+  context_stack: &[&'a HBData],
   global_data: &HashMap<&str, &'a HBData>,
+  block_param_values: &HashMap<&str, &'a HBData>,
   compat: bool,
 ) ->  Option<&'a (HBData + 'a)>
 {
@@ -25,7 +26,7 @@ fn value_for_key_path_in_context<'a>(
   let mut stack_index = 0;
   let mut first_key = true;
 
-  for key in key_path.iter().map(|k| &k[..]) {
+  for (index, key) in key_path.iter().map(|k| k.as_ref()).enumerate() {
     match key {
       "."  => {continue},
       ".." => {
@@ -38,6 +39,7 @@ fn value_for_key_path_in_context<'a>(
 
         continue;
       },
+      // @data
       _ if key.starts_with("@") => {
         match global_data.get(key) {
           Some(&val) => {
@@ -47,6 +49,16 @@ fn value_for_key_path_in_context<'a>(
           None => (),
         };
       },
+      // block params of enclosing blocks shadow context data
+      _ if index == 0 => {
+        match block_param_values.get(key) {
+          Some(&val) => {
+            ctxt = Some(val);
+            continue;
+          },
+          None => (),
+        };
+      },
       _ => (),
     }
 
@@ -90,56 +102,47 @@ pub enum HBNodeType<T> {
   Null,
 }
 
-struct IndentWriter<'a> {
+/// Indents partials content : indentation is written at the start of each line of
+/// template text, values are only indented where they start a line.
+pub struct IndentWriter<'a> {
   w: &'a mut (io::Write+'a),
   indent: Option<String>,
+  text: bool,
+  line_start: bool,
 }
 
 impl <'a> IndentWriter<'a> {
-  fn with_indent(s: Option<String>, out: &mut SafeWriting, funkt: &Fn(&mut SafeWriting) -> io::Result<()>) -> io::Result<()> {
-    let mut indenter = IndentWriter {w: out, indent: s};
-    let mut safe = SafeWriting::Unsafe(&mut indenter);
-    funkt(&mut safe)
+  /// `line_start` tells if the output is at the start of a template text line, and is
+  /// updated with the written content
+  pub fn with_indent(s: Option<String>, text: bool, line_start: &mut bool, out: &mut SafeWriting, funkt: &Fn(&mut SafeWriting) -> io::Result<()>) -> io::Result<()> {
+    let mut indenter = IndentWriter {w: out, indent: s, text: text, line_start: *line_start};
+    let r = funkt(&mut SafeWriting::Unsafe(&mut indenter));
+    *line_start = indenter.line_start;
+    r
   }
 }
 
 
 impl <'a> io::Write for IndentWriter<'a> {
   fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-    match self.indent {
-      None => self.w.write(buf),
-      Some(ref indent_str) => {
-        let mut r = Ok(0);
-        let ref mut writer = self.w;
-        let as_utf8 = unsafe { ::std::str::from_utf8_unchecked(buf) };
-        let mut chars = as_utf8.char_indices();
-        let mut i = chars.next().unwrap_or((0, ' ')).0; // init with first char
-        while i < as_utf8.len() {
-          match chars.next().or(Some((as_utf8.len(), ' '))) {
-            Some((next, _)) => {
-              r = match &as_utf8[i..next] {
-                "\n"  => {
-                  writer.write("\n".as_bytes()).and_then(|_| {
-                    writer.write(&indent_str.as_bytes())
-                  })
-                },
-                chr => {
-                  writer.write(chr.as_bytes())
-                }
-              };
-
-              i = next;
-
-              if r.is_err() {
-                break;
-              }
-            },
-            None => break,
+    match *self {
+      IndentWriter { ref mut w, indent: Some(ref indent_str), text, ref mut line_start } => {
+        // indentation is written before the first byte of a line, so that
+        // there is no dangling indentation after the last line ending
+        let mut rest = buf;
+        while !rest.is_empty() {
+          let len = rest.iter().position(|&b| b == b'\n').map(|i| i + 1).unwrap_or(rest.len());
+          if *line_start {
+            try!(w.write_all(indent_str.as_bytes()));
           }
+          try!(w.write_all(&rest[..len]));
+          *line_start = text && rest[len - 1] == b'\n';
+          rest = &rest[len..];
         }
 
-        r.and(Ok(buf.len()))
+        Ok(buf.len())
       },
+      IndentWriter { ref mut w, indent: None, .. } => w.write(buf),
     }
   }
 
@@ -305,6 +308,31 @@ pub trait HBData  {
   fn values<'a>(&'a self) -> HBValuesIter<'a>;
 
   fn iter<'a>(&'a self)   -> HBIter<'a>;
+
+  /// data as a json value, sub expressions results are kept this way
+  fn json_value(&self) -> Json {
+    match self.typed_node() {
+      HBNodeType::Branch(_) => Json::Object(self.iter().map(|(k, v)| (k.to_string(), v.json_value())).collect()),
+      HBNodeType::Array(_)  => Json::Array(self.values().map(|v| v.json_value()).collect()),
+      HBNodeType::Null      => Json::Null,
+      HBNodeType::Leaf(_)   => {
+        let s = rendered(self);
+        // falsy scalars are typed back when they can be (eg `0` or `false`)
+        match s.parse::<Json>() {
+          Ok(j) if !self.as_bool() && !j.as_bool() => j,
+          _ if !self.as_bool() => Json::String(String::new()),
+          _ => Json::String(s),
+        }
+      },
+    }
+  }
+}
+
+// unescaped output of data
+fn rendered<D: HBData + ?Sized>(data: &D) -> String {
+  let mut buf: Vec<u8> = Vec::new();
+  data.write_value(&mut SafeWriting::Unsafe(&mut buf)).ok();
+  String::from_utf8_lossy(&buf).into_owned()
 }
 
 
@@ -398,6 +426,10 @@ impl HBData for Json {
       Box::new(o.into_iter().map(|(s, j)| (&s[..], j as &HBData))) as HBIter<'a>
     ).unwrap_or(Box::new(None.into_iter()))
   }
+
+  fn json_value(&self) -> Json {
+    self.clone()
+  }
 }
 
 impl HBData for String {
@@ -415,6 +447,7 @@ impl HBData for String {
   fn keys<'a>(&'a self) -> HBKeysIter<'a> { Box::new(None.into_iter()) }
   fn values<'a>(&'a self) -> HBValuesIter<'a> { Box::new(None.into_iter()) }
   fn iter<'a>(&'a self) -> HBIter<'a> { Box::new(None.into_iter()) }
+  fn json_value(&self) -> Json { Json::String(self.clone()) }
 }
 
 impl<'b> HBData for &'b str {
@@ -432,11 +465,13 @@ impl<'b> HBData for &'b str {
   fn keys<'a>(&'a self) -> HBKeysIter<'a> { Box::new(None.into_iter()) }
   fn values<'a>(&'a self) -> HBValuesIter<'a> { Box::new(None.into_iter()) }
   fn iter<'a>(&'a self) -> HBIter<'a> { Box::new(None.into_iter()) }
+  fn json_value(&self) -> Json { Json::String(self.to_string()) }
 }
 
 struct FallbackToOptions<'a> {
   data: &'a (HBData + 'a),
   options: HashMap<&'a str, &'a (HBData+'a)>,
+  sub_expressions: HashMap<&'a str, Json>,
 }
 
 impl <'a> HBData for FallbackToOptions<'a> {
@@ -452,7 +487,7 @@ impl <'a> HBData for FallbackToOptions<'a> {
     match self.data.get_key(key) {
       v @ Some(_) => v,
       None => {
-        self.options.get(key).map(|&v| v)
+        self.options.get(key).map(|&v| v).or(self.sub_expressions.get(key).map(|s| s as &HBData))
       }
     }
   }
@@ -462,7 +497,7 @@ impl <'a> HBData for FallbackToOptions<'a> {
   }
 
   fn keys<'b>(&'b self) -> HBKeysIter<'b> {
-    Box::new(self.data.keys().chain(self.options.keys().map(|&s| s))) as HBKeysIter<'b>
+    Box::new(self.data.keys().chain(self.options.keys().map(|&s| s)).chain(self.sub_expressions.keys().map(|&s| s))) as HBKeysIter<'b>
   }
 
   fn values<'b>(&'b self) -> HBValuesIter<'b> {
@@ -470,7 +505,9 @@ impl <'a> HBData for FallbackToOptions<'a> {
   }
 
   fn iter<'b>(&'b self) -> HBIter<'b> {
-    Box::new(self.data.iter().chain(self.options.iter().map(|(&s, &v)| (s, v)))) as HBIter<'b>
+    Box::new(self.data.iter()
+      .chain(self.options.iter().map(|(&s, &v)| (s, v)))
+      .chain(self.sub_expressions.iter().map(|(&s, v)| (s, v as &HBData)))) as HBIter<'b>
   }
 }
 
@@ -478,10 +515,50 @@ pub type HelperFunction = Box<Fn(&[&HBData], &HelperOptions, &mut SafeWriting, &
 
 pub struct Helper {
   helper_func: HelperFunction,
+  builtin: bool,
 }
 
 pub type HelperOptionsByName<'a> = HashMap<&'a String, &'a (HBData + 'a)>;
 
+pub type DecoratorFunction = Box<Fn(&[&HBData], &HelperOptions, &mut BlockEnvironment, &EvalContext) -> HBEvalResult>;
+
+/// Changes made by decorators (eg `{{* decorator}}`) to the evaluation environment
+/// of their enclosing block : partials and data (available with @key) added for its content
+pub struct BlockEnvironment {
+  partials: Vec<(String, Template)>,
+  data: Vec<(String, Box<HBData>)>,
+}
+
+impl BlockEnvironment {
+  fn new() -> BlockEnvironment {
+    BlockEnvironment { partials: Vec::new(), data: Vec::new() }
+  }
+
+  /// makes a partial available to the enclosing block, it has precedence over registered ones
+  pub fn register_partial(&mut self, name: String, t: Template) {
+    self.partials.push((name, t));
+  }
+
+  /// makes data available to the enclosing block as `@name`
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use rumblebars::EvalContext;
+  ///
+  /// let mut context = EvalContext::new();
+  /// context.register_decorator("greeting".to_string(), Box::new(
+  ///   |params, options, environment, hb_context| {
+  ///     // with {{* greeting}}, block can use {{@greeting}}
+  ///     environment.insert_data("greeting".to_string(), Box::new("hi".to_string()));
+  ///     Ok(())
+  /// }));
+  /// ```
+  pub fn insert_data(&mut self, name: String, value: Box<HBData>) {
+    self.data.push((format!("@{}", name), value));
+  }
+}
+
 
 /// Provides rendering and lookup features to registered helpers
 #[allow(dead_code)] // alow dead, only used from user defined helpers
@@ -494,8 +571,16 @@ pub struct HelperOptions<'a> {
   inverse: Option<&'a Entries>,
   hb_context: &'a EvalContext,
   global_data: &'a HashMap<&'a str, &'a (HBData + 'a)>,
+  // values of the block params of enclosing blocks
+  block_param_values: &'a HashMap<&'a str, &'a (HBData + 'a)>,
   context_stack: &'a Vec<&'a (HBData + 'a)>,
   options: &'a [(String, HBValHolder)],
+  block_params: &'a [String],
+  // values of options given as sub expressions, by option index
+  sub_expression_options: Vec<Option<Json>>,
+  partials: InlinePartials<'a>,
+  // value given by a helper called as a sub expression
+  value: Option<::std::cell::RefCell<Option<Json>>>,
 }
 
 // alow dead, only used from user defined helpers
@@ -504,39 +589,54 @@ impl <'a> HelperOptions<'a> {
 
   // rough handlebars path parsing, not solid AT ALL, but should do the job
   fn parse_path(path: &str) -> Vec<String> {
-    let path_reg = Regex::new(r##"(\.\.|\.)/?|(@?[^!"#%&\\'()*+,./;<=>\[\]^`{|}~ \t]+)[./]?|\[([^\]]+)\][./]?"##).ok().unwrap();
+    let is_id_char = |c: char| !"!\"#%&\\'()*+,./;<=>[]^`{|}~ \t".contains(c);
     let mut r = Vec::new();
-    for captures1 in path_reg.captures_iter(path) {
-      match (captures1.at(1), captures1.at(2), captures1.at(3)) {
-        (Some(dot), None, None) => {
-          r.push(dot.to_string())
+    let mut rest = path;
+
+    while let Some(c) = rest.chars().next() {
+      let bracket_close = if c == '[' { rest[1..].find(']').map(|i| i + 1) } else { None };
+      let (entry, len, separators) = match (c, bracket_close) {
+        ('.', _) if rest.starts_with("..") => (Some(".."), 2, "/"),
+        ('.', _) => (Some("."), 1, "/"),
+        ('[', Some(close)) if close > 1 => (Some(&rest[1..close]), close + 1, "./"),
+        _ => match rest.find(|c: char| !is_id_char(c)).unwrap_or(rest.len()) {
+          0 => (None, c.len_utf8(), ""),
+          len => (Some(&rest[..len]), len, "./"),
         },
-        (None, Some(id), None) => {
-          r.push(id.to_string())
-        },
-        (None, None, Some(id)) => {
-          r.push(id.to_string())
-        },
-        _ => (),
+      };
+
+      rest = &rest[len..];
+      if let Some(entry) = entry {
+        r.push(entry.to_string());
+        if rest.starts_with(|c: char| separators.contains(c)) {
+          rest = &rest[1..];
+        }
       }
     }
+
     r
   }
 
   fn render_template(&self, template: Option<&'a Entries>, data: &'a HBData, out: &mut SafeWriting) -> HBEvalResult {
     match template {
-      Some(t) => eval_with_globals(t, data, out, self.hb_context, self.global_data, self.context_stack, None),
+      Some(t) => eval_with_globals(t, data, out, self.hb_context, self.global_data, self.block_param_values, self.context_stack, &self.partials, None, &mut true),
       None => Ok(()),
     }
 
   }
 
-  /// data passed as options (eg `{{helper option="one" option=name.val}}`)
-  pub fn option_by_name(&self, name: &String) -> Option<&'a(HBData + 'a)> {
-    match self.options.iter().find(|&&(ref n, _)| { n == name }) {
-      Some(&(_, HBValHolder::String(ref s))) => Some(s as &HBData),
-      Some(&(_, HBValHolder::Path(ref p))) => value_for_key_path_in_context(self.context, p, self.context_stack, self.global_data, self.hb_context.compat),
-      _ => None,
+  /// data passed as options (eg `{{helper option="one" option=name.val option=(other_helper name)}}`)
+  pub fn option_by_name(&self, name: &String) -> Option<&HBData> {
+    match self.options.iter().position(|&(ref n, _)| { n == name }) {
+      Some(index) => match (&self.options[index].1, &self.sub_expression_options[index]) {
+        (_, &Some(ref s)) => Some(s as &HBData),
+        (&HBValHolder::String(ref s), _) => Some(s as &HBData),
+        (&HBValHolder::Path(ref p), _) => value_for_key_path_in_context(self.context, p, self.context_stack, self.global_data, self.block_param_values, self.hb_context.compat),
+        (&HBValHolder::Literal(ref d, ref s), _) => value_for_key_path_in_context(self.context, &vec![s.clone()], self.context_stack, self.global_data, self.block_param_values, false)
+          .or(Some(d as &HBData)),
+        _ => None,
+      },
+      None => None,
     }
   }
 
@@ -574,7 +674,7 @@ impl <'a> HelperOptions<'a> {
     if key_write_ok.is_ok() {
       if let Ok(str_key) = String::from_utf8(buf) {
         let key_path = HelperOptions::parse_path(&str_key);
-        value_for_key_path_in_context(context, &key_path, self.context_stack, self.global_data, self.hb_context.compat)
+        value_for_key_path_in_context(context, &key_path, self.context_stack, self.global_data, self.block_param_values, self.hb_context.compat)
       } else {
         None
       }
@@ -624,6 +724,20 @@ impl <'a> HelperOptions<'a> {
   /// ```
 
   pub fn render_fn_with_context_and_globals(&self, data: &HBData, out: &mut SafeWriting, globals: &HashMap<&str, &HBData>) -> HBEvalResult {
+    self.render_fn_with_context_globals_and_block_params(data, out, globals, &HashMap::new())
+  }
+
+  pub fn render_fn_with_globals(&self, out: &mut SafeWriting, globals: &HashMap<&str, &HBData>) -> HBEvalResult {
+    self.render_fn_with_context_and_globals(self.context, out, globals)
+  }
+
+  /// same as render_fn, with the block params values bound by `bind_block_params`
+  pub fn render_fn_with_block_params(&self, out: &mut SafeWriting, block_params: &HashMap<&str, &HBData>) -> HBEvalResult {
+    self.render_fn_with_context_globals_and_block_params(self.context, out, &HashMap::new(), block_params)
+  }
+
+  /// same as render_fn_with_context_and_globals, with the block params values bound by `bind_block_params`
+  pub fn render_fn_with_context_globals_and_block_params(&self, data: &HBData, out: &mut SafeWriting, globals: &HashMap<&str, &HBData>, block_params: &HashMap<&str, &HBData>) -> HBEvalResult {
     let mut h = HashMap::new();
 
     for (k, v) in self.global_data.iter() {
@@ -634,40 +748,169 @@ impl <'a> HelperOptions<'a> {
       h.insert(*k, *v);
     }
 
+    let mut p = HashMap::new();
+
+    for (k, v) in self.block_param_values.iter() {
+      p.insert(*k, *v);
+    }
+
+    for (k, v) in block_params.iter() {
+      p.insert(*k, *v);
+    }
+
     match self.block {
-      Some(t) => eval_with_globals(t, unsafe {::std::mem::transmute(data)}, out, self.hb_context, &h, self.context_stack, None),
+      Some(t) => eval_with_globals(t, unsafe {::std::mem::transmute(data)}, out, self.hb_context, &h, &p, self.context_stack, &self.partials, None, &mut true),
       None    => Ok(()),
     }
   }
 
-  pub fn render_fn_with_globals(&self, out: &mut SafeWriting, globals: &HashMap<&str, &HBData>) -> HBEvalResult {
-    self.render_fn_with_context_and_globals(self.context, out, globals)
+  /// true if helper is called as a sub expression (eg `(helper a)`), see `return_value`
+  pub fn is_sub_expression(&self) -> bool {
+    self.value.is_some()
+  }
+
+  /// for a helper called as a sub expression, gives the value of the sub expression,
+  /// that otherwise is the helper output as a string. It's ignored elsewhere.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use rumblebars::EvalContext;
+  /// use rumblebars::HBData;
+  ///
+  /// let mut context = EvalContext::new();
+  /// context.register_helper("first".to_string(), Box::new(
+  ///   |params, options, out, hb_context| {
+  ///     // {{#if (first a b)}} is falsy when a is false, rather than the "false" output
+  ///     match params.first() {
+  ///       Some(p) if options.is_sub_expression() => { options.return_value(p.json_value()); Ok(()) },
+  ///       Some(p) => p.write_value(out),
+  ///       None => Ok(()),
+  ///     }
+  /// }));
+  /// ```
+  pub fn return_value(&self, value: Json) {
+    if let Some(ref cell) = self.value {
+      *cell.borrow_mut() = Some(value);
+    }
+  }
+
+  /// block params names, as declared in block expression (eg `user` and `i` for `{{#each users as |user i|}}`)
+  pub fn block_params(&self) -> &[String] {
+    self.block_params
+  }
+
+  /// binds values to block params names, in declaration order, for block rendering with
+  /// `render_fn_with_block_params`. Unbound names or extra values are ignored. Block params are
+  /// visible from the block and the blocks nested in it, but not from the partials it renders.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use rumblebars::EvalContext;
+  /// use rumblebars::HBData;
+  ///
+  /// let mut context = EvalContext::new();
+  /// context.register_helper("twice".to_string(), Box::new(
+  ///   |params, options, out, hb_context| {
+  ///     let (first, second) = ("first".to_string(), "second".to_string());
+  ///     let mut block_params = ::std::collections::HashMap::<&str, &HBData>::new();
+  ///     // with {{#twice as |a b|}}, block can use {{a}} and {{b}}
+  ///     options.bind_block_params(&[&first, &second], &mut block_params);
+  ///     options.render_fn_with_block_params(out, &block_params)
+  /// }));
+  /// ```
+  pub fn bind_block_params<'b>(&'b self, values: &[&'b HBData], block_params: &mut HashMap<&'b str, &'b HBData>) {
+    for (name, value) in self.block_params.iter().zip(values.iter()) {
+      block_params.insert(&name[..], *value);
+    }
   }
 }
 
 impl Helper {
   pub fn new_with_function(f: HelperFunction) -> Helper {
-    Helper { helper_func: f }
+    Helper { helper_func: f, builtin: false }
+  }
+
+  fn new_builtin(f: HelperFunction) -> Helper {
+    Helper { helper_func: f, builtin: true }
+  }
+
+  /// true for the helpers registered by default, as long as they are not overridden
+  pub fn is_builtin(&self) -> bool {
+    self.builtin
   }
 
   fn build_param_vec<'a, 'b>(
     context: &'a HBData,
     params: &'a [HBValHolder],
+    sub_expressions: &'a [Option<Json>],
     ctxt_stack: &'b Vec<&'a HBData>,
     global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
     hb_context: &'a EvalContext,
   ) -> Vec<&'a (HBData + 'a)>
   {
-    params.iter().map(|v| {
-      match v {
-        &HBValHolder::String(ref s) => s as &HBData,
-        &HBValHolder::Path(ref p) => value_for_key_path_in_context(context, p, ctxt_stack, global_data, false).unwrap_or(&hb_context.falsy),
-        &HBValHolder::Literal(ref d, ref s) => value_for_key_path_in_context(context, &vec![s.clone()], ctxt_stack, global_data, false)
-          .unwrap_or(d as &HBData)
+    params.iter().zip(sub_expressions.iter()).map(|(v, sub_expression)| {
+      match (v, sub_expression) {
+        (_, &Some(ref s)) => s as &HBData,
+        (&HBValHolder::String(ref s), _) => s as &HBData,
+        (&HBValHolder::Path(ref p), _) => value_for_key_path_in_context(context, p, ctxt_stack, global_data, block_param_values, false).unwrap_or(&hb_context.falsy),
+        (&HBValHolder::Literal(ref d, ref s), _) => value_for_key_path_in_context(context, &vec![s.clone()], ctxt_stack, global_data, block_param_values, false)
+          .unwrap_or(d as &HBData),
+        (&HBValHolder::SubExpression(_), &None) => &hb_context.falsy,
       }
     }).collect::<Vec<_>>()
   }
 
+  // sub expression value is the one returned by its helper call, or its output, or the value at its path if there is no such helper
+  pub fn eval_sub_expression<'a>(
+    exp: &'a HBExpression,
+    context: &'a HBData,
+    ctxt_stack: &Vec<&'a HBData>,
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
+    hb_context: &'a EvalContext,
+  ) -> io::Result<Json>
+  {
+    match (exp.base.first(), exp.base.len()) {
+      (Some(ref single), 1) if hb_context.has_helper_with_name(&single) => {
+        let mut buf: Vec<u8> = Vec::new();
+        let value = try!(hb_context.helper_with_name(&single).unwrap().call(
+          context, &exp.params, &exp.options, &mut SafeWriting::Unsafe(&mut buf), hb_context, ctxt_stack, global_data, block_param_values, true
+        ));
+
+        Ok(value.unwrap_or_else(|| Json::String(String::from_utf8_lossy(&buf).into_owned())))
+      },
+      _ => Ok(match value_for_key_path_in_context(context, &exp.base, ctxt_stack, global_data, block_param_values, hb_context.compat) {
+        Some(v) => v.json_value(),
+        None => Json::Null,
+      }),
+    }
+  }
+
+  // evaluate sub expressions of a params or options list, other values are left to None
+  fn eval_sub_expressions<'a, I: Iterator<Item=&'a HBValHolder>>(
+    values: I,
+    context: &'a HBData,
+    ctxt_stack: &Vec<&'a HBData>,
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
+    hb_context: &'a EvalContext,
+  ) -> io::Result<Vec<Option<Json>>>
+  {
+    let mut evaluated = Vec::new();
+
+    for v in values {
+      evaluated.push(match v {
+        &HBValHolder::SubExpression(ref exp) => Some(try!(Helper::eval_sub_expression(exp, context, ctxt_stack, global_data, block_param_values, hb_context))),
+        _ => None,
+      });
+    }
+
+    Ok(evaluated)
+  }
+
   fn call_for_block<'a, 'b, 'c>(
     &self,
     block: Option<&'a Entries>,
@@ -676,22 +919,27 @@ impl Helper {
     context: &'a HBData,
     params: &'a [HBValHolder],
     options: &'a [(String, HBValHolder)],
+    block_params: &'a [String],
     out: &'b mut SafeWriting,
     hb_context: &'a EvalContext,
     ctxt_stack: &'c Vec<&'a HBData>,
-    global_data: &HashMap<&str, &'a HBData>
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
+    partials: InlinePartials<'a>
   ) -> HBEvalResult {
+    let sub_expressions = try!(Helper::eval_sub_expressions(params.iter(), context, ctxt_stack, global_data, block_param_values, hb_context));
 
     let condition = match params.first() {
       Some(val) => match val {
         &HBValHolder::String(ref s) => s.as_bool(),
-        &HBValHolder::Path(ref p) => if let Some(v) = value_for_key_path_in_context(context, p, ctxt_stack, global_data, hb_context.compat) {
+        &HBValHolder::Path(ref p) => if let Some(v) = value_for_key_path_in_context(context, p, ctxt_stack, global_data, block_param_values, hb_context.compat) {
           v.as_bool()
         } else {
           false
         },
-        &HBValHolder::Literal(ref d, ref s) => value_for_key_path_in_context(context, &vec![s.clone()], ctxt_stack, global_data, false)
-          .unwrap_or(d as &HBData).as_bool()
+        &HBValHolder::Literal(ref d, ref s) => value_for_key_path_in_context(context, &vec![s.clone()], ctxt_stack, global_data, block_param_values, false)
+          .unwrap_or(d as &HBData).as_bool(),
+        &HBValHolder::SubExpression(_) => sub_expressions.first().and_then(|s| s.as_ref()).map(|s| s.as_bool()).unwrap_or(false),
       },
       _ => false
     };
@@ -704,11 +952,16 @@ impl Helper {
       condition: (!inverse_condition && condition) || (inverse_condition && !condition),
       // options: Helper::build_options_map(options, ctxt_stack, global_data),
       options: options,
+      block_params: block_params,
+      sub_expression_options: try!(Helper::eval_sub_expressions(options.iter().map(|&(_, ref v)| v), context, ctxt_stack, global_data, block_param_values, hb_context)),
       global_data: unsafe { ::std::mem::transmute(global_data) },
+      block_param_values: unsafe { ::std::mem::transmute(block_param_values) },
       context_stack: unsafe { ::std::mem::transmute(ctxt_stack) },
+      partials: partials,
+      value: None,
     };
 
-    (self.helper_func)(&Helper::build_param_vec(context, params, ctxt_stack, global_data, hb_context), &helper_options, out, hb_context)
+    (self.helper_func)(&Helper::build_param_vec(context, params, &sub_expressions, ctxt_stack, global_data, block_param_values, hb_context), &helper_options, out, hb_context)
   }
 
   fn call_fn<'a, 'b, 'c>(
@@ -719,8 +972,27 @@ impl Helper {
     out: &'b mut SafeWriting,
     hb_context: &'a EvalContext,
     ctxt_stack: &'c Vec<&'a HBData>,
-    global_data: &HashMap<&str, &'a HBData>
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>
   ) -> HBEvalResult {
+    self.call(context, params, options, out, hb_context, ctxt_stack, global_data, block_param_values, false).map(|_| ())
+  }
+
+  // calls helper without a block, as a sub expression it may return a value
+  fn call<'a, 'b, 'c>(
+    &self,
+    context: &'a HBData,
+    params: &'a [HBValHolder],
+    options: &'a [(String, HBValHolder)],
+    out: &'b mut SafeWriting,
+    hb_context: &'a EvalContext,
+    ctxt_stack: &'c Vec<&'a HBData>,
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
+    sub_expression: bool,
+  ) -> io::Result<Option<Json>> {
+    let sub_expressions = try!(Helper::eval_sub_expressions(params.iter(), context, ctxt_stack, global_data, block_param_values, hb_context));
+
     let helper_options = HelperOptions {
       block: None,
       inverse: None,
@@ -729,21 +1001,64 @@ impl Helper {
       condition: true,
       // options: Helper::build_options_map(options, ctxt_stack, global_data),
       options: options,
+      block_params: &[],
+      sub_expression_options: try!(Helper::eval_sub_expressions(options.iter().map(|&(_, ref v)| v), context, ctxt_stack, global_data, block_param_values, hb_context)),
       global_data: unsafe { ::std::mem::transmute(global_data) },
+      block_param_values: unsafe { ::std::mem::transmute(block_param_values) },
       context_stack: unsafe { ::std::mem::transmute(ctxt_stack) },
+      partials: ::std::rc::Rc::new(vec![]),
+      value: if sub_expression { Some(::std::cell::RefCell::new(None)) } else { None },
     };
 
-    (self.helper_func)(&Helper::build_param_vec(context, params, ctxt_stack, global_data, hb_context), &helper_options, out, hb_context)
+    try!((self.helper_func)(&Helper::build_param_vec(context, params, &sub_expressions, ctxt_stack, global_data, block_param_values, hb_context), &helper_options, out, hb_context));
+
+    Ok(helper_options.value.and_then(|v| v.into_inner()))
   }
 
+  fn call_decorator<'a, 'c>(
+    decorator: &DecoratorFunction,
+    block: Option<&'a Entries>,
+    context: &'a HBData,
+    params: &'a [HBValHolder],
+    options: &'a [(String, HBValHolder)],
+    environment: &mut BlockEnvironment,
+    hb_context: &'a EvalContext,
+    ctxt_stack: &'c Vec<&'a HBData>,
+    global_data: &HashMap<&str, &'a HBData>,
+    block_param_values: &HashMap<&str, &'a HBData>,
+    partials: InlinePartials<'a>
+  ) -> HBEvalResult {
+    let sub_expressions = try!(Helper::eval_sub_expressions(params.iter(), context, ctxt_stack, global_data, block_param_values, hb_context));
+
+    let helper_options = HelperOptions {
+      block: block,
+      inverse: None,
+      context: context,
+      hb_context: hb_context,
+      condition: true,
+      options: options,
+      block_params: &[],
+      sub_expression_options: try!(Helper::eval_sub_expressions(options.iter().map(|&(_, ref v)| v), context, ctxt_stack, global_data, block_param_values, hb_context)),
+      global_data: unsafe { ::std::mem::transmute(global_data) },
+      block_param_values: unsafe { ::std::mem::transmute(block_param_values) },
+      context_stack: unsafe { ::std::mem::transmute(ctxt_stack) },
+      partials: partials,
+      value: None,
+    };
+
+    decorator(&Helper::build_param_vec(context, params, &sub_expressions, ctxt_stack, global_data, block_param_values, hb_context), &helper_options, environment, hb_context)
+  }
 }
 
 /// Stores partials, helpers and configuration flags
 pub struct EvalContext {
   partials: HashMap<String, Template>,
   helpers: HashMap<String, Helper>,
+  decorators: HashMap<String, DecoratorFunction>,
   /// mustache compatibility flag, to enable recursive lookups
   pub compat: bool,
+  /// `@level` data, `"info"` by default
+  pub level: String,
   falsy: Json,
 }
 
@@ -751,16 +1066,18 @@ impl Default for EvalContext {
   fn default() -> EvalContext {
     let mut helpers = HashMap::new();
 
-    helpers.insert("each".to_string(),   Helper::new_with_function(Box::new(::helpers_builtins::each_helper)));
-    helpers.insert("if".to_string(),     Helper::new_with_function(Box::new(::helpers_builtins::if_helper)));
-    helpers.insert("unless".to_string(), Helper::new_with_function(Box::new(::helpers_builtins::unless_helper)));
-    helpers.insert("lookup".to_string(), Helper::new_with_function(Box::new(::helpers_builtins::lookup_helper)));
-    helpers.insert("with".to_string(),   Helper::new_with_function(Box::new(::helpers_builtins::with_helper)));
+    helpers.insert("each".to_string(),   Helper::new_builtin(Box::new(::helpers_builtins::each_helper)));
+    helpers.insert("if".to_string(),     Helper::new_builtin(Box::new(::helpers_builtins::if_helper)));
+    helpers.insert("unless".to_string(), Helper::new_builtin(Box::new(::helpers_builtins::unless_helper)));
+    helpers.insert("lookup".to_string(), Helper::new_builtin(Box::new(::helpers_builtins::lookup_helper)));
+    helpers.insert("with".to_string(),   Helper::new_builtin(Box::new(::helpers_builtins::with_helper)));
 
     EvalContext {
       partials: Default::default(),
       helpers: helpers,
+      decorators: Default::default(),
       compat: false,
+      level: "info".to_string(),
       falsy: Json::Null,
     }
   }
@@ -846,19 +1163,131 @@ impl EvalContext {
   pub fn has_helper_with_name(&self, name: &str) -> bool {
     return self.helpers.contains_key(name);
   }
+
+  /// adds a decorator to the evaluation context, it is called with the environment
+  /// of the block where it's used (eg `{{* decorator}}`), before block rendering
+  pub fn register_decorator(&mut self, name: String, d: DecoratorFunction) {
+    self.decorators.insert(name, d);
+  }
+
+  /// true if a decorator with given name is registered
+  pub fn has_decorator_with_name(&self, name: &str) -> bool {
+    return self.decorators.contains_key(name);
+  }
 }
 
 /// Global function for template evaluation. See [`Template`](struct.Template.html) docs.
 pub fn eval(template: &Template, data: &HBData, out: &mut io::Write, eval_context: &EvalContext) -> HBEvalResult {
-  let log = "info".to_string();
   let mut globals = HashMap::new();
   globals.insert("@root", data);
-  globals.insert("@level", &log);
+  globals.insert("@level", &eval_context.level as &HBData);
 
   let mut html_safe = HTMLSafeWriter::new(out);
   let mut safe_writer = SafeWriting::Safe(&mut html_safe);
 
-  eval_with_globals(&template.entries, data, &mut safe_writer, eval_context, &globals, &vec![data], None)
+  eval_with_globals(&template.entries, data, &mut safe_writer, eval_context, &globals, &HashMap::new(), &vec![data], &::std::rc::Rc::new(vec![]), None, &mut true)
+}
+
+// inline partials (`{{#*inline "name"}}`) and partial blocks (`@partial-block`) visible from a block,
+// enclosing blocks definitions first
+type InlinePartials<'a> = ::std::rc::Rc<Vec<InlinePartial<'a>>>;
+
+#[derive(Clone)]
+struct InlinePartial<'a> {
+  name: ::std::borrow::Cow<'a, str>,
+  entries: PartialEntries<'a>,
+  // partial blocks are rendered with the partials visible where they were given
+  scope: Option<InlinePartials<'a>>,
+}
+
+#[derive(Clone)]
+enum PartialEntries<'a> {
+  Borrowed(&'a Entries),
+  // partials registered by decorators are owned by the entries of their block
+  Owned(::std::rc::Rc<Template>),
+}
+
+// add inline partials defined in entries to the enclosing ones
+fn scoped_partials<'a>(entries: &'a Entries, enclosing: &InlinePartials<'a>) -> InlinePartials<'a> {
+  let mut defined = entries.iter().filter_map(|e| match **e {
+    HBEntry::Eval(HBExpression { ref base, ref params, render_options: RenderOptions { decorator: true, .. }, block: Some(ref block), .. }) => {
+      match (base.first().map(|s| &s[..]), base.len(), params.first()) {
+        (Some("inline"), 1, Some(&HBValHolder::String(ref name))) => Some(InlinePartial { name: (&name[..]).into(), entries: PartialEntries::Borrowed(&**block), scope: None }),
+        _ => None,
+      }
+    },
+    _ => None,
+  }).peekable();
+
+  if defined.peek().is_none() {
+    return enclosing.clone();
+  }
+
+  let mut partials = (**enclosing).clone();
+  partials.extend(defined);
+  ::std::rc::Rc::new(partials)
+}
+
+// partials visible from a partial called with a block : the ones defined in block, and block itself as `@partial-block`
+fn partial_block_scope<'a>(block: &'a Entries, enclosing: &InlinePartials<'a>) -> InlinePartials<'a> {
+  let mut partials = (*scoped_partials(block, enclosing)).clone();
+  partials.push(InlinePartial { name: "@partial-block".into(), entries: PartialEntries::Borrowed(block), scope: Some(enclosing.clone()) });
+  ::std::rc::Rc::new(partials)
+}
+
+// name of a registered decorator used in entries
+fn decorator_name<'a>(entry: &'a HBEntry, eval_context: &EvalContext) -> Option<&'a str> {
+  match *entry {
+    HBEntry::Eval(HBExpression { ref base, render_options: RenderOptions { decorator: true, .. }, .. }) => {
+      match (base.first(), base.len()) {
+        (Some(name), 1) if eval_context.has_decorator_with_name(name) => Some(&name[..]),
+        _ => None,
+      }
+    },
+    _ => None,
+  }
+}
+
+fn has_decorators(entries: &Entries, eval_context: &EvalContext) -> bool {
+  entries.iter().any(|e| decorator_name(e, eval_context).is_some())
+}
+
+// calls registered decorators of entries, in order, to get the environment of these entries
+fn run_decorators<'a, 'c>(
+  entries: &'a Entries,
+  context: &'a HBData,
+  eval_context: &'a EvalContext,
+  global_data: &HashMap<&str, &'a HBData>,
+  block_param_values: &HashMap<&str, &'a HBData>,
+  ctxt_stack: &'c Vec<&'a HBData>,
+  partials: &InlinePartials<'a>,
+) -> io::Result<BlockEnvironment> {
+  let mut environment = BlockEnvironment::new();
+
+  for e in entries.iter() {
+    if let (Some(name), &HBEntry::Eval(ref exp)) = (decorator_name(e, eval_context), &**e) {
+      try!(Helper::call_decorator(
+        &eval_context.decorators[name],
+        exp.block.as_ref().map(|b| &**b),
+        context,
+        &exp.params,
+        &exp.options,
+        &mut environment,
+        eval_context,
+        ctxt_stack,
+        global_data,
+        block_param_values,
+        partials.clone()
+      ));
+    }
+  }
+
+  Ok(environment)
+}
+
+// innermost inline partial with that name
+fn inline_partial<'a>(partials: &InlinePartials<'a>, name: &str) -> Option<(PartialEntries<'a>, Option<InlinePartials<'a>>)> {
+  partials.iter().rev().find(|p| p.name == name).map(|p| (p.entries.clone(), p.scope.clone()))
 }
 
 struct RenderEntry<'a> {
@@ -866,19 +1295,25 @@ struct RenderEntry<'a> {
   data: &'a (HBData+'a),
   stack: Vec<&'a (HBData+'a)>,
   indent: Option<String>,
+  partials: InlinePartials<'a>,
+  // entries of partials don't see block params of the blocks rendering them
+  in_partial: bool,
 }
 
 impl<'a> RenderEntry<'a> {
   fn new(
     entry: &'a Box<HBEntry>,
     data: &'a (HBData+'a),
-    stack: Vec<&'a (HBData+'a)>, indent: Option<String>
+    stack: Vec<&'a (HBData+'a)>, indent: Option<String>,
+    partials: InlinePartials<'a>, in_partial: bool
   ) -> RenderEntry<'a> {
     RenderEntry {
       entry: entry,
       data: data,
       stack: stack,
       indent: indent,
+      partials: partials,
+      in_partial: in_partial,
     }
   }
 }
@@ -888,6 +1323,9 @@ struct IterationControl<'a> {
   data_iter: ::std::rc::Rc<::std::cell::RefCell<HBValuesIter<'a>>>,
   stack: Vec<&'a (HBData+'a)>,
   indent: Option<String>,
+  // partials of the enclosing block, entries are scoped on each iteration
+  partials: InlinePartials<'a>,
+  in_partial: bool,
 }
 
 enum StackEntry<'a> {
@@ -897,7 +1335,13 @@ enum StackEntry<'a> {
 }
 
 
-pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBData, out: &mut SafeWriting, eval_context: &'a EvalContext, global_data: &HashMap<&str, &'c HBData>, context_stack: &Vec<&'b HBData>, indent: Option<String>) -> HBEvalResult {
+// renders entries from the state of generated code, where no inline partial is in scope
+pub fn eval_from_state<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBData, out: &mut SafeWriting, eval_context: &'a EvalContext, global_data: &HashMap<&str, &'c HBData>, block_param_values: &HashMap<&str, &'c HBData>, context_stack: &Vec<&'b HBData>, indent: Option<String>, line_start: &mut bool) -> HBEvalResult {
+  eval_with_globals(entries, data, out, eval_context, global_data, block_param_values, context_stack, &::std::rc::Rc::new(vec![]), indent, line_start)
+}
+
+// partials content is indented at the start of its lines, `line_start` tells if output is at the start of one
+pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBData, out: &mut SafeWriting, eval_context: &'a EvalContext, global_data: &HashMap<&str, &'c HBData>, block_param_values: &HashMap<&str, &'c HBData>, context_stack: &Vec<&'b HBData>, partials: &InlinePartials<'a>, indent: Option<String>, line_start: &mut bool) -> HBEvalResult {
   use self::StackEntry::{FlowEntry, ContextIterControlPoint, CleanUpPartialContext};
   // evaluation is done by iterating through each HBEntry to evaluate
   //  - raw copy,
@@ -911,19 +1355,69 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
   //  - a ref to their associated context
   //  - a context stack, to have access of context of parent blocks (copied for each entry)
   //  - an indentation level (for partials, copied for each entry)
+  //  - the inline partials it can use
+  //
+  // decorators of the root level entries may add partials and data for them
+  let BlockEnvironment { partials: decorated, data: decorated_data } = try!(run_decorators(entries, data, eval_context, global_data, block_param_values, context_stack, partials));
+
+  let mut decorated_globals = HashMap::new();
+  let global_data = if decorated_data.is_empty() {
+    global_data
+  } else {
+    for (k, v) in global_data.iter() {
+      decorated_globals.insert(*k, *v);
+    }
+    for &(ref k, ref v) in decorated_data.iter() {
+      decorated_globals.insert(&k[..], &**v);
+    }
+    &decorated_globals
+  };
+
+  let mut partials = scoped_partials(entries, partials);
+  if !decorated.is_empty() {
+    let mut decorated_partials = (*partials).clone();
+    for (name, t) in decorated.into_iter() {
+      decorated_partials.push(InlinePartial { name: name.into(), entries: PartialEntries::Owned(::std::rc::Rc::new(t)), scope: None });
+    }
+    partials = ::std::rc::Rc::new(decorated_partials);
+  }
+
   let mut stack = entries.iter().rev().map(|e| {
     FlowEntry(RenderEntry::new(
       e, data,
       context_stack.iter().map(|s| *s).collect::<Vec<_>>(),
-      indent.clone()
+      indent.clone(), partials.clone(), false
     ))
   }).collect::<Vec<_>>();
 
+  // block params are visible from nested blocks, but not from partials
+  let partial_block_params = HashMap::new();
+  let block_params_for = |in_partial: bool| if in_partial { &partial_block_params } else { block_param_values };
+
   // used for storage of partials optional keys, computed at evaluation
   // this can leak, but probably not in relevant cases.
   let mut partial_options_current_index = 0;
   let mut partial_options_contexts = HashMap::<usize, FallbackToOptions>::new();
 
+  // stacks entries of a block for rendering, with the inline partials they define,
+  // blocks with decorators need their own environment and are rendered right away
+  macro_rules! push_block {
+    ($entries: expr, $data: expr, $ctxt_stack: expr, $indent: expr, $partials: expr, $in_partial: expr) => ({
+      let block_entries: &Entries = $entries;
+      if has_decorators(block_entries, eval_context) {
+        try!(eval_with_globals(block_entries, $data, out, eval_context, global_data, block_params_for($in_partial), &$ctxt_stack, &$partials, $indent, line_start));
+      } else {
+        let block_partials = scoped_partials(block_entries, &$partials);
+        for e in block_entries.iter().rev() {
+          stack.push(FlowEntry(RenderEntry::new(
+            e, $data,
+            $ctxt_stack.clone(), $indent.clone(), block_partials.clone(), $in_partial
+          )));
+        }
+      }
+    })
+  }
+
   while stack.len() > 0 {
     let w_ok = if let Some(stack_entry) = stack.pop() {
       match stack_entry {
@@ -934,50 +1428,79 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
             data_iter: control.data_iter.clone(),
             stack: control.stack.clone(),
             indent: control.indent.clone(),
+            partials: control.partials.clone(),
+            in_partial: control.in_partial,
           }));
-          for e in control.entries.iter().rev() {
-            stack.push(FlowEntry(RenderEntry::new(
-              &e, next,
-              control.stack.clone(), control.indent.clone()
-            )));
-          }
+          push_block!(control.entries, next, control.stack, control.indent.clone(), control.partials, control.in_partial);
           Ok(())
         } else {
           Ok(())
         },
         FlowEntry(flow_entry) => {
           let ctxt = flow_entry.data;
+          let block_param_values = block_params_for(flow_entry.in_partial);
 
           match **flow_entry.entry {
+            // decorators are not rendered, inline partials are collected with their enclosing block entries
+            HBEntry::Eval(HBExpression{render_options: RenderOptions {decorator: true, ..}, ..}) => Ok(()),
             HBEntry::Raw(ref s) => {
-              IndentWriter::with_indent(flow_entry.indent.clone(), &mut out.into_unsafe(), &|w| {
+              IndentWriter::with_indent(flow_entry.indent.clone(), true, line_start, &mut out.into_unsafe(), &|w| {
                 w.write_all(&s.as_bytes())
               })
             },
             HBEntry::Partial(ref exp) => {
-              match exp.base.first() {
-                Some(ref single) if exp.base.len() == 1 => {
-                  match eval_context.partial_with_name(&single) {
-                    Some(ref t) => {
-                      let c_ctxt = if let Some(&HBValHolder::Path(ref p)) = exp.params.get(0) {
-                        value_for_key_path_in_context(ctxt, p, &flow_entry.stack, global_data, eval_context.compat).unwrap_or(ctxt)
+              // computed partial names are given as a sub expression, before partial params
+              let (name, params) = match (exp.base.len(), exp.params.split_first()) {
+                (0, Some((&HBValHolder::SubExpression(ref name_exp), params))) => {
+                  let name = match try!(Helper::eval_sub_expression(name_exp, ctxt, &flow_entry.stack, global_data, block_param_values, eval_context)) {
+                    Json::String(s) => s,
+                    v => rendered(&v),
+                  };
+                  (Some(name), params)
+                },
+                (0, _) => (None, &exp.params[..]),
+                _ => (Some(exp.base.join("/")), &exp.params[..]),
+              };
+
+              match name {
+                Some(ref single) => {
+                  // inline partials have precedence over registered ones
+                  let found = inline_partial(&flow_entry.partials, &single)
+                    .or_else(|| eval_context.partial_with_name(&single).map(|t| (PartialEntries::Borrowed(&t.entries), None)));
+
+                  // partials evaluation scope, a missing partial falls back to its block if any
+                  let found = match (found, &exp.block) {
+                    (Some((partial_entries, _)), &Some(ref block)) => Some((partial_entries, partial_block_scope(block, &flow_entry.partials))),
+                    (Some((partial_entries, scope)), &None) => Some((partial_entries, scope.unwrap_or(flow_entry.partials.clone()))),
+                    (None, &Some(ref block)) => Some((PartialEntries::Borrowed(&**block), flow_entry.partials.clone())),
+                    (None, &None) => None,
+                  };
+
+                  match found {
+                    Some((partial_entries, partials_scope)) => {
+                      let c_ctxt = if let Some(&HBValHolder::Path(ref p)) = params.get(0) {
+                        value_for_key_path_in_context(ctxt, p, &flow_entry.stack, global_data, block_param_values, eval_context.compat).unwrap_or(ctxt)
                       } else {
                         ctxt
                       };
 
                       let with_options_fallback = if exp.options.len() > 0 {
                         let mut options_contexts: HashMap<&str, &HBData> = HashMap::new();
+                        let mut sub_expressions: HashMap<&str, Json> = HashMap::new();
                         for o in exp.options.iter() {
                           match o {
                             &(ref name, HBValHolder::String(ref s)) => {
                               options_contexts.insert(&name, s as &HBData);
                             },
                             &(ref name, HBValHolder::Path(ref p)) => {
-                              options_contexts.insert(&name, value_for_key_path_in_context(ctxt, p, &flow_entry.stack, global_data, eval_context.compat).unwrap_or(&eval_context.falsy));
+                              options_contexts.insert(&name, value_for_key_path_in_context(ctxt, p, &flow_entry.stack, global_data, block_param_values, eval_context.compat).unwrap_or(&eval_context.falsy));
                             },
                             &(ref name, HBValHolder::Literal(ref j, _)) => {
                               options_contexts.insert(&name, j as &HBData);
                             },
+                            &(ref name, HBValHolder::SubExpression(ref sub_exp)) => {
+                              sub_expressions.insert(&name[..], try!(Helper::eval_sub_expression(sub_exp, ctxt, &flow_entry.stack, global_data, block_param_values, eval_context)));
+                            },
                           }
                         }
 
@@ -987,7 +1510,7 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
                         // outside it's current scope
                         partial_options_contexts.insert(
                           partial_options_current_index,
-                          FallbackToOptions { data: c_ctxt, options: options_contexts }
+                          FallbackToOptions { data: c_ctxt, options: options_contexts, sub_expressions: sub_expressions }
                         );
                         unsafe { ::std::mem::transmute(
                           partial_options_contexts.get(&partial_options_current_index).map(|f| f as &HBData).unwrap()
@@ -1003,14 +1526,22 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
                         (&None, &None) => None,
                       };
 
+                      // standalone partials start a line, nested ones are tracked along with their parent lines
+                      if flow_entry.indent.is_none() && may_indent.is_some() {
+                        *line_start = true;
+                      }
+
                       stack.push(CleanUpPartialContext(partial_options_current_index));
                       partial_options_current_index += 1;
 
-                      for ref e in t.entries.iter().rev() {
-                        stack.push(FlowEntry(RenderEntry::new(
-                          e, with_options_fallback,
-                          flow_entry.stack.clone(), may_indent.clone()
-                        )))
+                      match partial_entries {
+                        PartialEntries::Borrowed(partial_entries) => {
+                          push_block!(partial_entries, with_options_fallback, flow_entry.stack, may_indent, partials_scope, true);
+                        },
+                        // owned entries don't live as long as the stack, they are rendered right away
+                        PartialEntries::Owned(template) => {
+                          try!(eval_with_globals(&template.entries, with_options_fallback, out, eval_context, global_data, block_params_for(true), &flow_entry.stack, &partials_scope, may_indent, line_start));
+                        },
                       }
 
                       Ok(())
@@ -1018,34 +1549,34 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
                     _ => Ok(())
                   }
                 }
-                Some(_) => panic!("invalid partial name '{}'", exp.path()),
-                None => panic!("invalid empty string to retrieve partial by name"),
+                // no usable name, nothing to render
+                None => Ok(()),
               }
             },
 
-            HBEntry::Eval(HBExpression{ref base, ref params, ref options, ref render_options, block: None, else_block: None}) => {
+            HBEntry::Eval(HBExpression{ref base, ref params, ref options, ref render_options, block: None, else_block: None, ..}) => {
               match (base.first(), base.len()) {
                 (Some(ref single), 1) if eval_context.has_helper_with_name(&single) => {
                   let helper = eval_context.helper_with_name(&single).unwrap();
                   if render_options.escape {
-                    IndentWriter::with_indent(flow_entry.indent.clone(), out, &|w| {
-                      helper.call_fn(ctxt, &params, &options, w, eval_context, &flow_entry.stack, global_data)
+                    IndentWriter::with_indent(flow_entry.indent.clone(), false, line_start, out, &|w| {
+                      helper.call_fn(ctxt, &params, &options, w, eval_context, &flow_entry.stack, global_data, block_param_values)
                     })
                   } else {
-                    IndentWriter::with_indent(flow_entry.indent.clone(), &mut out.into_unsafe(), &|w| {
-                      helper.call_fn(ctxt, &params, &options, w, eval_context, &flow_entry.stack, global_data)
+                    IndentWriter::with_indent(flow_entry.indent.clone(), false, line_start, &mut out.into_unsafe(), &|w| {
+                      helper.call_fn(ctxt, &params, &options, w, eval_context, &flow_entry.stack, global_data, block_param_values)
                     })
                   }
                 },
-                _ => match value_for_key_path_in_context(ctxt, base, &flow_entry.stack, global_data, eval_context.compat) {
+                _ => match value_for_key_path_in_context(ctxt, base, &flow_entry.stack, global_data, block_param_values, eval_context.compat) {
                   Some(v) => match v.typed_node() {
                     HBNodeType::Leaf(_) | HBNodeType::Array(_)=> {
                       if render_options.escape {
-                        IndentWriter::with_indent(flow_entry.indent.clone(), out, &|w| {
+                        IndentWriter::with_indent(flow_entry.indent.clone(), false, line_start, out, &|w| {
                           v.write_value(w)
                         })
                       } else {
-                        IndentWriter::with_indent(flow_entry.indent.clone(), &mut out.into_unsafe(), &|w| {
+                        IndentWriter::with_indent(flow_entry.indent.clone(), false, line_start, &mut out.into_unsafe(), &|w| {
                           v.write_value(w)
                         })
                       }
@@ -1057,7 +1588,7 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
               }
             },
 
-            HBEntry::Eval(HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}) => {
+            HBEntry::Eval(HBExpression{ref base, ref params, ref options, ref block_params, ref render_options, ref block, ref else_block}) => {
               render_options.escape; // only suppress unused warning
               match (base.first(), base.len()) {
                 (Some(ref single), 1) if eval_context.has_helper_with_name(&single) => {
@@ -1079,38 +1610,44 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
                       ctxt,
                       &params,
                       &options,
+                      &block_params,
                       out,
                       eval_context,
                       &flow_entry.stack,
-                      global_data
+                      global_data,
+                      block_param_values,
+                      flow_entry.partials.clone()
                     )
                   } else {
                     Ok(())
                   }
                 },
+                // raw block without helper, content is rendered as is
+                _ if render_options.raw_block => {
+                  if let &Some(ref block_found) = block {
+                    for e in block_found.iter().rev() {
+                      stack.push(FlowEntry(RenderEntry::new(
+                        e, ctxt,
+                        flow_entry.stack.clone(), flow_entry.indent.clone(), flow_entry.partials.clone(), flow_entry.in_partial
+                      )))
+                    }
+                  }
+
+                  Ok(())
+                },
                 _ => {
-                  let c_ctxt = value_for_key_path_in_context(ctxt, base, &flow_entry.stack, global_data, eval_context.compat);
+                  let c_ctxt = value_for_key_path_in_context(ctxt, base, &flow_entry.stack, global_data, block_param_values, eval_context.compat);
 
                   match (c_ctxt.unwrap_or(&eval_context.falsy), block) {
                     (c, &Some(ref block_found)) => {
                       match c.typed_node() {
                         HBNodeType::Branch(_) | HBNodeType::Leaf(_) | HBNodeType::Null => {
                           if c.as_bool() && !render_options.inverse || !c.as_bool() && render_options.inverse {
-                            for e in block_found.iter().rev() {
-                              let mut c_stack = flow_entry.stack.clone();
-                              c_stack.push(ctxt);
-                              stack.push(FlowEntry(RenderEntry::new(
-                                e, c,
-                                c_stack, flow_entry.indent.clone()
-                              )))
-                            }
+                            let mut c_stack = flow_entry.stack.clone();
+                            c_stack.push(ctxt);
+                            push_block!(block_found, c, c_stack, flow_entry.indent.clone(), flow_entry.partials, flow_entry.in_partial);
                           } else if let &Some(ref inv_block) = else_block {
-                            for e in inv_block.iter().rev() {
-                              stack.push(FlowEntry(RenderEntry::new(
-                                e, ctxt,
-                                flow_entry.stack.clone(), flow_entry.indent.clone()
-                              )))
-                            }
+                            push_block!(inv_block, ctxt, flow_entry.stack, flow_entry.indent.clone(), flow_entry.partials, flow_entry.in_partial);
                           }
                         },
                         HBNodeType::Array(_) => {
@@ -1135,15 +1672,12 @@ pub fn eval_with_globals<'a: 'b, 'b: 'c, 'c>(entries: &'a Entries, data: &'a HBD
                               entries: block_found,
                               data_iter: iter_cell.clone(),
                               stack: c_stack,
-                              indent: flow_entry.indent.clone()
+                              indent: flow_entry.indent.clone(),
+                              partials: flow_entry.partials.clone(),
+                              in_partial: flow_entry.in_partial,
                             }));
                           } else if let &Some(ref inv_block) = else_block {
-                            for e in inv_block.iter().rev() {
-                              stack.push(FlowEntry(RenderEntry::new(
-                                e, ctxt,
-                                flow_entry.stack.clone(), flow_entry.indent.clone()
-                              )))
-                            }
+                            push_block!(inv_block, ctxt, flow_entry.stack, flow_entry.indent.clone(), flow_entry.partials, flow_entry.in_partial);
                           }
 
                         },
@@ -1210,7 +1744,7 @@ mod tests {
     {
       let mut safe_writer = HTMLSafeWriter::new(&mut buf);
       let mut html_safe = SafeWriting::Safe(&mut safe_writer);
-      value_for_key_path_in_context(&json, &vec!["a".to_string()], &vec![], &h, false).unwrap().write_value(&mut html_safe).unwrap();
+      value_for_key_path_in_context(&json, &vec!["a".to_string()], &vec![], &h, &h, false).unwrap().write_value(&mut html_safe).unwrap();
     }
 
 
@@ -1226,7 +1760,7 @@ mod tests {
     {
       let mut safe_writer = HTMLSafeWriter::new(&mut buf);
       let mut html_safe = SafeWriting::Safe(&mut safe_writer);
-      value_for_key_path_in_context(&json, &vec!["a".to_string(), "b".to_string()], &vec![], &h, false).unwrap().write_value(&mut html_safe).unwrap();
+      value_for_key_path_in_context(&json, &vec!["a".to_string(), "b".to_string()], &vec![], &h, &h, false).unwrap().write_value(&mut html_safe).unwrap();
     }
 
 
@@ -1242,7 +1776,7 @@ mod tests {
     {
       let mut safe_writer = HTMLSafeWriter::new(&mut buf);
       let mut html_safe = SafeWriting::Safe(&mut safe_writer);
-      value_for_key_path_in_context(&json, &vec!["a".to_string(), "0".to_string()], &vec![], &h, false).unwrap().write_value(&mut html_safe).unwrap();
+      value_for_key_path_in_context(&json, &vec!["a".to_string(), "0".to_string()], &vec![], &h, &h, false).unwrap().write_value(&mut html_safe).unwrap();
     }
 
 
@@ -1258,7 +1792,7 @@ mod tests {
     {
       let mut safe_writer = HTMLSafeWriter::new(&mut buf);
       let mut html_safe = SafeWriting::Safe(&mut safe_writer);
-      value_for_key_path_in_context(&json, &vec![".".to_string()], &vec![], &h, false).unwrap().write_value(&mut html_safe).unwrap();
+      value_for_key_path_in_context(&json, &vec![".".to_string()], &vec![], &h, &h, false).unwrap().write_value(&mut html_safe).unwrap();
     }
 
 
@@ -1274,7 +1808,7 @@ mod tests {
     {
       let mut safe_writer = HTMLSafeWriter::new(&mut buf);
       let mut html_safe = SafeWriting::Safe(&mut safe_writer);
-      value_for_key_path_in_context(&json, &vec![".".to_string(), "t".to_string()], &vec![], &h, false).unwrap().write_value(&mut html_safe).unwrap();
+      value_for_key_path_in_context(&json, &vec![".".to_string(), "t".to_string()], &vec![], &h, &h, false).unwrap().write_value(&mut html_safe).unwrap();
     }
 
 
@@ -1286,7 +1820,7 @@ mod tests {
     let json = Json::from_str(r##"{"a": 1}"##).unwrap();
     let h = HashMap::new();
 
-    match value_for_key_path_in_context(&json, &vec!["a".to_string(), "b".to_string()], &vec![], &h, false) {
+    match value_for_key_path_in_context(&json, &vec!["a".to_string(), "b".to_string()], &vec![], &h, &h, false) {
       Some(_) => assert!(false),
       None    => assert!(true),
     }
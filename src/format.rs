@@ -0,0 +1,255 @@
+use lexer::{tokenize, Token, TokenKind, Delimiters};
+use lexer::Token::{TokSetDelimiters, TokBlockExp, TokBlockElseCond, TokBlockElseChain, TokBlockEndExp, TokRawBlockStart, TokRawBlockEnd, TokRaw, TokEscaped};
+use parse::{parse, parse_tag, ParseError};
+use print::print_tag;
+
+// indentation of block contents, relative to the line opening the block
+static INDENT: &'static str = "  ";
+static TAB_INDENT: &'static str = "\t";
+
+static WHITESPACE: &'static [char] = &[' ', '\t', '\r', '\n'];
+
+// how a tag lays out the lines that follow it
+#[derive(Clone,Copy,PartialEq)]
+enum Role {
+  Inline,
+  Open,
+  Else,
+  Close,
+}
+
+enum Piece {
+  Text(String),
+  // raw blocks content is kept as is, lines it spans included
+  Verbatim(String),
+  Tag(String, Role),
+}
+
+// a template line, its indentation is held apart from its content
+#[derive(Default)]
+struct Line {
+  indent: String,
+  pieces: Vec<Piece>,
+}
+
+impl Line {
+  fn is_blank(&self) -> bool {
+    self.pieces.is_empty()
+  }
+
+  fn starts_with(&self, role: Role) -> bool {
+    match self.pieces.first() {
+      Some(&Piece::Tag(_, r)) => r == role,
+      _ => false,
+    }
+  }
+}
+
+// which indentation a line gets
+enum Align {
+  // left as is, outside of any block
+  Top,
+  // content of a block
+  Content(usize),
+  // `{{else}}` and closing tags are aligned with the line opening their block
+  Opener(usize),
+}
+
+/// Reindents template source : block contents are indented along with their
+/// nesting, else and closing tags are aligned with the line opening their
+/// block and whitespace inside tags is normalized.
+///
+/// Lines outside of blocks keep their indentation, blocks content keeps its
+/// relative indentation and is indented by two spaces, or by a tab in templates
+/// indented with tabs. Blank lines in blocks are emptied. Raw blocks content and
+/// comments are kept as is. Tags following set delimiters tags are printed
+/// with the delimiters they set.
+///
+/// Normalizing tags and aligning tags standing alone on their line keeps the
+/// rendered output as is, these lines render nothing. Reindenting other lines
+/// in blocks does not : text renders its indentation, and standalone partials
+/// indent each line they render.
+///
+/// ```
+/// let template = "<ul>\n{{#each  items as |item| }}\n<li>{{ item.name }}</li>\n    {{^}}\n<li>none</li>\n{{/each}}\n</ul>";
+///
+/// assert_eq!(rumblebars::format(template).unwrap(), "<ul>\n{{#each items as |item|}}\n  <li>{{item.name}}</li>\n{{else}}\n  <li>none</li>\n{{/each}}\n</ul>");
+/// ```
+pub fn format(template: &str) -> Result<String, ParseError> {
+  try!(parse(template));
+
+  let lines = lines(template);
+  let aligns = align(&lines);
+  let unit = indent_unit(&lines);
+
+  // blocks are numbered in opening order, as done when aligning
+  let mut opener_indents: Vec<String> = vec![];
+  let mut out: Vec<String> = vec![];
+
+  for (line, (align, base)) in lines.iter().zip(aligns.into_iter()) {
+    let indent = match align {
+      Align::Top => line.indent.clone(),
+      Align::Content(_) if line.is_blank() => String::new(),
+      Align::Content(block) => format!("{}{}{}", opener_indents[block], unit, &line.indent[base..]),
+      Align::Opener(block) => opener_indents[block].clone(),
+    };
+
+    let mut formatted = indent.clone();
+    for piece in line.pieces.iter() {
+      match *piece {
+        Piece::Text(ref s) | Piece::Verbatim(ref s) => formatted.push_str(s),
+        Piece::Tag(ref s, role) => {
+          if role == Role::Open {
+            opener_indents.push(indent.clone());
+          }
+          formatted.push_str(s);
+        },
+      }
+    }
+    out.push(formatted);
+  }
+
+  Ok(out.join("\n"))
+}
+
+// splits template into lines of text and canonical tags
+fn lines(template: &str) -> Vec<Line> {
+  let mut lines = vec![Line::default()];
+  let mut raw_block = false;
+  let mut delimiters = Delimiters::default();
+
+  for (kind, _) in tokenize(template) {
+    let tok = match kind {
+      TokenKind::Template(tok) => tok,
+      TokenKind::Mark(_) | TokenKind::Expression(_) => continue,
+    };
+    let source = tok.source().to_string();
+
+    match tok {
+      TokRaw(_) if raw_block => push_piece(&mut lines, Piece::Verbatim(source)),
+      TokRaw(_) | TokEscaped(_) => push_text(&mut lines, &source),
+      _ => {
+        let lead = &source[..source.len() - source.trim_left_matches(WHITESPACE).len()];
+        let tag = source[lead.len()..].trim_right_matches(WHITESPACE);
+        let trail = &source[lead.len() + tag.len()..];
+
+        raw_block = match tok { TokRawBlockStart(_) => true, TokRawBlockEnd(_) => false, _ => raw_block };
+
+        push_text(&mut lines, lead);
+        match parse_tag(&tok, &delimiters) {
+          Ok((_, entry, _)) => push_piece(&mut lines, Piece::Tag(print_tag(&tok, &entry, &delimiters), role(&tok))),
+          Err(_) => push_piece(&mut lines, Piece::Text(tag.to_string())),
+        }
+        push_text(&mut lines, trail);
+
+        // following tags are lexed with the new delimiters
+        if let TokSetDelimiters(_) = tok {
+          if let Ok(new_delimiters) = delimiters.set_by(&source) {
+            delimiters = new_delimiters;
+          }
+        }
+      },
+    }
+  }
+
+  lines
+}
+
+fn role(tok: &Token) -> Role {
+  match *tok {
+    TokBlockExp(..) => Role::Open,
+    TokBlockElseCond(_) | TokBlockElseChain(_) => Role::Else,
+    TokBlockEndExp(_) => Role::Close,
+    _ => Role::Inline,
+  }
+}
+
+fn push_piece(lines: &mut Vec<Line>, piece: Piece) {
+  if let Some(line) = lines.last_mut() {
+    line.pieces.push(piece);
+  }
+}
+
+// text is split into lines, blanks starting a line are its indentation
+fn push_text(lines: &mut Vec<Line>, text: &str) {
+  for (index, part) in text.split('\n').enumerate() {
+    if index > 0 {
+      lines.push(Line::default());
+    }
+
+    let line = match lines.last_mut() {
+      Some(line) => line,
+      None => return,
+    };
+    let content = if line.is_blank() {
+      let content = part.trim_left_matches(|c| c == ' ' || c == '\t');
+      line.indent.push_str(&part[..part.len() - content.len()]);
+      content
+    } else {
+      part
+    };
+
+    match line.pieces.last_mut() {
+      _ if content.is_empty() => (),
+      Some(&mut Piece::Text(ref mut s)) => s.push_str(content),
+      _ => line.pieces.push(Piece::Text(content.to_string())),
+    }
+  }
+}
+
+// block contents are indented by tabs when the first indented line is
+fn indent_unit(lines: &[Line]) -> &'static str {
+  match lines.iter().filter(|line| !line.is_blank()).map(|line| &line.indent).find(|indent| !indent.is_empty()) {
+    Some(indent) if indent.starts_with('\t') => TAB_INDENT,
+    _ => INDENT,
+  }
+}
+
+// tells how each line is aligned, along with the length of the base indentation of its block
+// content : the prefix shared by its lines, that is replaced by the block indentation
+fn align(lines: &[Line]) -> Vec<(Align, usize)> {
+  let mut open: Vec<usize> = vec![];
+  let mut bases: Vec<Option<String>> = vec![];
+  let mut aligns = vec![];
+
+  for line in lines.iter() {
+    let align = match open.last() {
+      None => Align::Top,
+      Some(&block) if line.starts_with(Role::Else) || line.starts_with(Role::Close) => Align::Opener(block),
+      Some(&block) => {
+        if !line.is_blank() {
+          bases[block] = Some(match bases[block].take() {
+            Some(base) => common_prefix(&base, &line.indent).to_string(),
+            None => line.indent.clone(),
+          });
+        }
+        Align::Content(block)
+      },
+    };
+    aligns.push(align);
+
+    for piece in line.pieces.iter() {
+      match *piece {
+        Piece::Tag(_, Role::Open) => {
+          open.push(bases.len());
+          bases.push(None);
+        },
+        Piece::Tag(_, Role::Close) => {
+          open.pop();
+        },
+        _ => (),
+      }
+    }
+  }
+
+  aligns.into_iter().map(|align| {
+    let base = match align { Align::Content(block) => bases[block].as_ref().map(|b| b.len()).unwrap_or(0), _ => 0 };
+    (align, base)
+  }).collect()
+}
+
+// indentations are made of spaces and tabs, they are compared byte per byte
+fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+  let len = a.bytes().zip(b.bytes()).take_while(|&(x, y)| x == y).count();
+  &a[..len]
+}
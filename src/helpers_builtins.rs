@@ -45,7 +45,10 @@ pub fn each_helper(params: &[&HBData], options: &HelperOptions, out: &mut SafeWr
           each_globs.insert("@first", &first as &HBData);
           each_globs.insert("@last", &last as &HBData);
 
-          r = options.render_fn_with_context_and_globals(item, out, &each_globs);
+          let mut each_params = HashMap::new();
+          options.bind_block_params(&[item, &d_index], &mut each_params);
+
+          r = options.render_fn_with_context_globals_and_block_params(item, out, &each_globs, &each_params);
 
           if r.is_err() {
             break;
@@ -62,7 +65,7 @@ pub fn each_helper(params: &[&HBData], options: &HelperOptions, out: &mut SafeWr
       if len > 0 {
         let mut r = Ok(());
         for (index, ref key) in keys.enumerate() {
-          if let Some(o) = options.context.get_key(&key) {
+          if let Some(o) = use_context.get_key(&key) {
             let key = key.to_string();
             let first = (index == 0).to_json();
             let last = (index == len-1).to_json();
@@ -73,7 +76,10 @@ pub fn each_helper(params: &[&HBData], options: &HelperOptions, out: &mut SafeWr
             each_globs.insert("@first", &first as &HBData);
             each_globs.insert("@last", &last as &HBData);
 
-            r = options.render_fn_with_context_and_globals(o, out, &each_globs);
+            let mut each_params = HashMap::new();
+            options.bind_block_params(&[o, &key], &mut each_params);
+
+            r = options.render_fn_with_context_globals_and_block_params(o, out, &each_globs, &each_params);
 
             if r.is_err() {
               break;
@@ -95,13 +101,13 @@ pub fn lookup_helper(params: &[&HBData], options: &HelperOptions, out: &mut Safe
   match (params.first(), params.get(1)) {
     (Some(&key), None) => {
       match options.lookup(key) {
-        Some(data) => data.write_value(out),
+        Some(data) => lookup_result(data, options, out),
         None => Ok(())
       }
     },
     (Some(&context), Some(&key)) => {
       match options.lookup_with_context(key, context) {
-        Some(data) => data.write_value(out),
+        Some(data) => lookup_result(data, options, out),
         None => Ok(())
       }
     },
@@ -109,9 +115,23 @@ pub fn lookup_helper(params: &[&HBData], options: &HelperOptions, out: &mut Safe
   }
 }
 
+// as a sub expression, lookup result keeps its type
+fn lookup_result(data: &HBData, options: &HelperOptions, out: &mut SafeWriting) -> HBEvalResult {
+  if options.is_sub_expression() {
+    options.return_value(data.json_value());
+    Ok(())
+  } else {
+    data.write_value(out)
+  }
+}
+
 pub fn with_helper(params: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
   match (params.first(), params.len()) {
-    (Some(&context), 1) if context.as_bool() => options.render_fn_with_context(context, out),
+    (Some(&context), 1) if context.as_bool() => {
+      let mut with_params = HashMap::new();
+      options.bind_block_params(&[context], &mut with_params);
+      options.render_fn_with_context_globals_and_block_params(context, out, &HashMap::new(), &with_params)
+    },
     _ => options.inverse(out),
   }
 }
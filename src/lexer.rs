@@ -0,0 +1,1259 @@
+use self::Token::{TokSimpleExp, TokNoEscapeExp, TokCommentExp, TokSetDelimiters, TokBlockExp, TokBlockElseCond, TokBlockElseChain, TokBlockEndExp, TokPartialExp, TokRawBlockStart, TokRawBlockEnd, TokRaw, TokEscaped};
+use self::HBToken::{TokPathEntry,TokNoWhiteSpaceBefore, TokNoWhiteSpaceAfter,TokStringParam,TokLiteral,TokParamStart, TokParamSep, TokOption, TokSubExpStart, TokSubExpEnd, TokBlockParams, TokLeadingWhiteSpace, TokTrailingWhiteSpace};
+
+use parse::{Span, SpanCursor};
+
+/// Template level tokens : raw text, escapes, and whole expression tags along
+/// with their surrounding whitespace. Raw block content is passed as raw text.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Token {
+  // base template tokens
+  TokSimpleExp(String),
+  TokNoEscapeExp(String),
+  TokCommentExp(String),
+  TokSetDelimiters(String),
+  TokPartialExp(String,),
+  TokBlockExp(String, bool),
+  TokBlockElseCond(String),
+  TokBlockElseChain(String),
+  TokBlockEndExp(String,),
+  TokRawBlockStart(String),
+  TokRawBlockEnd(String),
+  TokRaw(String),
+  TokEscaped(String),
+}
+
+impl Token {
+  /// source text matched by the lexer for this token
+  pub fn source(&self) -> &str {
+    match *self {
+      TokSimpleExp(ref s) | TokNoEscapeExp(ref s) | TokCommentExp(ref s) | TokSetDelimiters(ref s) | TokPartialExp(ref s) |
+      TokBlockExp(ref s, _) | TokBlockElseCond(ref s) | TokBlockElseChain(ref s) | TokBlockEndExp(ref s) | TokRawBlockStart(ref s) |
+      TokRawBlockEnd(ref s) | TokRaw(ref s) | TokEscaped(ref s) => s,
+    }
+  }
+}
+
+/// Expression tag parts : path entries, params, options, `~` whitespace control
+/// marks and the tag surrounding whitespace. Delimiters and comment text are
+/// left out.
+#[derive(Debug,Clone,PartialEq)]
+pub enum HBToken {
+  TokPathEntry(String),
+  TokNoWhiteSpaceBefore,
+  TokNoWhiteSpaceAfter,
+  TokStringParam(String),
+  TokLiteral(String),
+  TokParamStart,
+  TokParamSep,
+  TokOption(String),
+  TokSubExpStart,
+  TokSubExpEnd,
+  TokBlockParams(String),
+  TokLeadingWhiteSpace(String),
+  TokTrailingWhiteSpace(String),
+}
+
+//
+// Both lexers pick the longest match among the rules of their current state,
+// the last rule listed wins on equal length. Input that no rule matches is skipped.
+//
+
+// length of the longest `f` matching chars run at the start of `s`
+fn run_len<F: Fn(char) -> bool>(s: &str, f: F) -> usize {
+  s.find(|c: char| !f(c)).unwrap_or(s.len())
+}
+
+fn first_char_len(s: &str) -> usize {
+  s.chars().next().map(|c| c.len_utf8()).unwrap_or(0)
+}
+
+fn is_template_whitespace(c: char) -> bool {
+  c == ' ' || c == '\t' || c == '\r' || c == '\n'
+}
+
+fn is_blank(c: char) -> bool {
+  c == ' ' || c == '\t'
+}
+
+// expression tags delimiters, `{{` and `}}` unless changed by a set delimiters tag (eg `{{=<% %>=}}`).
+// Escapes only apply with default delimiters.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Delimiters {
+  pub open: String,
+  pub close: String,
+}
+
+impl Default for Delimiters {
+  fn default() -> Delimiters {
+    Delimiters { open: "{{".to_string(), close: "}}".to_string() }
+  }
+}
+
+impl Delimiters {
+  // delimiters can't be empty, nor hold whitespace or `=`
+  pub fn new(open: &str, close: &str) -> Option<Delimiters> {
+    if [open, close].iter().all(|d| d.len() > 0 && !d.contains('=') && !d.chars().any(|c| c.is_whitespace())) {
+      Some(Delimiters { open: open.to_string(), close: close.to_string() })
+    } else {
+      None
+    }
+  }
+
+  fn is_default(&self) -> bool {
+    self.open == "{{" && self.close == "}}"
+  }
+
+  // delimiters set by a set delimiters tag lexed with these ones, or the invalid delimiters it holds
+  pub fn set_by(&self, tag: &str) -> Result<Delimiters, String> {
+    let tag = tag.trim_matches(is_template_whitespace);
+    let content = if tag.len() >= self.open.len() + self.close.len() + 2 {
+      &tag[self.open.len() + 1..tag.len() - self.close.len() - 1]
+    } else {
+      ""
+    };
+    let delimiters = content.split_whitespace().collect::<Vec<_>>();
+
+    match (delimiters.len(), delimiters.first(), delimiters.last()) {
+      (2, Some(open), Some(close)) => Delimiters::new(open, close).ok_or(delimiters.join(" ")),
+      _ => Err(delimiters.join(" ")),
+    }
+  }
+}
+
+// offsets of every closing delimiter in the lexed input, overlapping ones included, so that each
+// expression looks its closing delimiter up instead of searching the rest of the input,
+// which made lexing quadratic with unclosed expressions
+struct Closes<'a> {
+  offsets: &'a [usize],
+  // offset of the lexed input rest, rules match from there
+  base: usize,
+  // closing delimiter length
+  len: usize,
+}
+
+impl<'a> Closes<'a> {
+  fn offsets(input: &str, close: &str) -> Vec<usize> {
+    let (bytes, close) = (input.as_bytes(), close.as_bytes());
+    (0..(bytes.len() + 1).saturating_sub(close.len())).filter(|&i| bytes[i..].starts_with(close)).collect()
+  }
+
+  // first closing delimiter at or after `at`, both relative to the input rest
+  fn find(&self, at: usize) -> Option<usize> {
+    let index = match self.offsets.binary_search(&(self.base + at)) { Ok(i) | Err(i) => i };
+    self.offsets.get(index).map(|&offset| offset - self.base)
+  }
+}
+
+// expression content, up to the first closing delimiter included : expressions content
+// can't hold `}}`, but may hold single braces
+fn expression_close(closes: &Closes, at: usize) -> Option<usize> {
+  closes.find(at).map(|close| close + closes.len)
+}
+
+// raw block delimiters content, closed by `}}` and the closing delimiter (`}}}}` by default)
+fn raw_close(s: &str, closes: &Closes, at: usize) -> Option<usize> {
+  match closes.find(at) {
+    // closing delimiter found within the braces
+    Some(close) if s[close..].starts_with("}}") && closes.find(close + 2) == Some(close + 2) => Some(close + 2 + closes.len),
+    Some(close) if s[at..close].ends_with("}}") => Some(close + closes.len),
+    _ => None,
+  }
+}
+
+// the mark opening specific expressions (eg `#` for blocks)
+fn marked_expression(s: &str, closes: &Closes, at: usize, mark: char) -> Option<usize> {
+  if s[at..].starts_with(mark) { expression_close(closes, at + 1) } else { None }
+}
+
+// any expression but comments
+fn simple_expression(s: &str, closes: &Closes, at: usize) -> Option<usize> {
+  match s[at..].chars().next() {
+    Some(c) if c != '!' => expression_close(closes, at + c.len_utf8()),
+    _ => None,
+  }
+}
+
+// `{{{exp}}}` and `{{&exp}}`
+fn no_escape_expression(s: &str, closes: &Closes, at: usize) -> Option<usize> {
+  if s[at..].starts_with('&') {
+    return expression_close(closes, at + 1);
+  }
+  if !s[at..].starts_with('{') {
+    return None;
+  }
+
+  let close = match closes.find(at + 1) {
+    Some(close) => close,
+    None => return None,
+  };
+
+  // the closing brace either directly precedes the closing delimiter, or is before
+  // its whitespace control mark and blanks
+  if s[close..].starts_with('}') && closes.find(close + 1) == Some(close + 1) {
+    Some(close + 1 + closes.len)
+  } else {
+    let content = &s[at + 1..close];
+    let content = if content.ends_with('~') { &content[..content.len() - 1] } else { content };
+    if content.trim_right_matches(is_blank).ends_with('}') { Some(close + closes.len) } else { None }
+  }
+}
+
+// `{{else}}` and `{{^}}`
+fn else_expression(s: &str, at: usize, close_delimiter: &str) -> Option<usize> {
+  let close = if s[at..].starts_with('^') {
+    at + 1 + run_len(&s[at + 1..], is_blank)
+  } else {
+    let keyword = at + run_len(&s[at..], |c| is_blank(c) || c == '\r');
+    if !s[keyword..].starts_with("else") {
+      return None;
+    }
+    keyword + 4 + run_len(&s[keyword + 4..], |c| is_blank(c) || c == '\r')
+  };
+  let close = if s[close..].starts_with('~') { close + 1 } else { close };
+
+  if s[close..].starts_with(close_delimiter) { Some(close + close_delimiter.len()) } else { None }
+}
+
+// `{{else helper params}}`
+fn else_chain_expression(s: &str, closes: &Closes, close_delimiter: &str, at: usize) -> Option<usize> {
+  let keyword = at + run_len(&s[at..], |c| is_blank(c) || c == '\r');
+  if !s[keyword..].starts_with("else") {
+    return None;
+  }
+
+  let blanks = run_len(&s[keyword + 4..], is_blank);
+  let helper = keyword + 4 + blanks;
+  match s[helper..].chars().next() {
+    Some(c) if blanks > 0 && c != '}' && !s[helper..].starts_with(close_delimiter) => expression_close(closes, helper + c.len_utf8()),
+    _ => None,
+  }
+}
+
+// expression tag with its surrounding whitespace, `content` matches from the open
+// delimiter end up to the close delimiter end
+fn tag<F: Fn(&str, usize) -> Option<usize>>(s: &str, open_delimiter: &str, content: F) -> Option<usize> {
+  let open = run_len(s, is_template_whitespace);
+  if !s[open..].starts_with(open_delimiter) {
+    return None;
+  }
+
+  // `~` mark is optional, a content starting with `~` is matched both ways
+  let at = open + open_delimiter.len();
+  let end = match (content(s, at), s[at..].starts_with('~')) {
+    (end, false) => end,
+    (end, true) => ::std::cmp::max(end, content(s, at + 1)),
+  };
+
+  end.map(|end| end + run_len(&s[end..], is_template_whitespace))
+}
+
+// `{{=<% %>=}}`, closed by `=` and the closing delimiter
+fn set_delimiters_expression(s: &str, closes: &Closes, at: usize) -> Option<usize> {
+  if !s[at..].starts_with('=') {
+    return None;
+  }
+
+  let mut from = at + 1;
+  while let Some(close) = closes.find(from) {
+    if close > at + 1 && s[..close].ends_with('=') {
+      return Some(close + closes.len);
+    }
+    from = close + 1;
+  }
+  None
+}
+
+// length of the raw block delimiters opening (`{{{{` by default) at the start of `s`
+fn raw_open_len(s: &str, open_delimiter: &str) -> usize {
+  if s.starts_with(open_delimiter) && s[open_delimiter.len()..].starts_with("{{") { open_delimiter.len() + 2 } else { 0 }
+}
+
+fn raw_block_start(s: &str, closes: &Closes, open_delimiter: &str) -> Option<usize> {
+  let open = run_len(s, is_template_whitespace);
+  let content = match raw_open_len(&s[open..], open_delimiter) {
+    0 => return None,
+    len => open + len,
+  };
+  if s[content..].starts_with('/') {
+    return None;
+  }
+
+  match first_char_len(&s[content..]) {
+    0 => None,
+    len => raw_close(s, closes, content + len),
+  }
+}
+
+// text up to the next whitespace, expression, or backslash when escapes apply, along with
+// leading whitespace
+fn pass_through(s: &str, open_delimiter: &str, escapes: bool) -> usize {
+  let start = run_len(s, is_template_whitespace);
+  let open_char = open_delimiter.chars().next();
+
+  s[start..].char_indices()
+    .find(|&(i, c)| is_template_whitespace(c) || (escapes && c == '\\') || (Some(c) == open_char && s[start + i..].starts_with(open_delimiter)))
+    .map(|(i, _)| start + i)
+    .unwrap_or(s.len())
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum TemplateRule {
+  PassThrough,
+  SimpleExp,
+  NoEscapeExp,
+  PartialExp,
+  EndExp,
+  BlockExp,
+  BlockInverseExp,
+  ElseExp,
+  ElseChainExp,
+  CommentExp,
+  SetDelimitersExp,
+  RawBlockStart,
+  PassEscape,
+  EscapedExp,
+  EscapedEscape,
+  EscapedSkip,
+  RawContent,
+  RawBraces,
+  RawNestedStart,
+  RawBlockEnd,
+}
+
+fn template_rule_match(rule: TemplateRule, s: &str, closes: &Closes, delimiters: &Delimiters, escapes: bool) -> Option<usize> {
+  let (open, close) = (&delimiters.open[..], &delimiters.close[..]);
+
+  match rule {
+    TemplateRule::PassThrough => Some(pass_through(s, open, escapes)),
+    // raw blocks starts leave their trailing whitespace out, they can't be taken for a longer expression
+    TemplateRule::SimpleExp | TemplateRule::NoEscapeExp if raw_block_start(s, closes, open).is_some() => None,
+    TemplateRule::SimpleExp => tag(s, open, |s, at| simple_expression(s, closes, at)),
+    TemplateRule::NoEscapeExp => tag(s, open, |s, at| no_escape_expression(s, closes, at)),
+    TemplateRule::PartialExp => tag(s, open, |s, at| marked_expression(s, closes, at, '>')),
+    TemplateRule::EndExp => tag(s, open, |s, at| marked_expression(s, closes, at, '/')),
+    TemplateRule::BlockExp => tag(s, open, |s, at| marked_expression(s, closes, at, '#')),
+    TemplateRule::BlockInverseExp => tag(s, open, |s, at| marked_expression(s, closes, at, '^')),
+    TemplateRule::ElseExp => tag(s, open, |s, at| else_expression(s, at, close)),
+    TemplateRule::ElseChainExp => tag(s, open, |s, at| else_chain_expression(s, closes, close, at)),
+    TemplateRule::CommentExp => tag(s, open, |s, at| marked_expression(s, closes, at, '!')),
+    TemplateRule::SetDelimitersExp => tag(s, open, |s, at| set_delimiters_expression(s, closes, at)),
+    TemplateRule::RawBlockStart => raw_block_start(s, closes, open),
+    _ if !escapes => None,
+    TemplateRule::PassEscape => if s.starts_with('\\') { Some(1) } else { None },
+    TemplateRule::EscapedExp => if s.starts_with("\\{") { Some(2) } else { None },
+    TemplateRule::EscapedEscape => if s.starts_with("\\\\") { Some(2) } else { None },
+    TemplateRule::EscapedSkip => {
+      let escaped = if s.starts_with("\\\\") { 2 } else if s.starts_with('\\') { 1 } else { return None };
+      match s[escaped..].chars().next() {
+        Some(c) if c != '{' && c != '\\' && c != '\r' && c != '\n' => Some(escaped + c.len_utf8()),
+        _ => None,
+      }
+    },
+    TemplateRule::RawContent | TemplateRule::RawBraces | TemplateRule::RawNestedStart | TemplateRule::RawBlockEnd => None,
+  }
+}
+
+fn raw_rule_match(rule: TemplateRule, s: &str, closes: &Closes, delimiters: &Delimiters) -> Option<usize> {
+  let open_char = delimiters.open.chars().next().unwrap_or('{');
+
+  match rule {
+    TemplateRule::RawContent => Some(run_len(s, |c| c != open_char)),
+    TemplateRule::RawBraces => Some(run_len(s, |c| c == open_char)),
+    TemplateRule::RawNestedStart => {
+      let content = raw_open_len(s, &delimiters.open);
+      if content == 0 || s[content..].starts_with('/') {
+        return None;
+      }
+      match first_char_len(&s[content..]) {
+        0 => None,
+        len => raw_close(s, closes, content + len),
+      }
+    },
+    TemplateRule::RawBlockEnd => match raw_open_len(s, &delimiters.open) {
+      content if content > 0 && s[content..].starts_with('/') => raw_close(s, closes, content + 1),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+static TEMPLATE_RULES: &'static [TemplateRule] = &[
+  TemplateRule::PassThrough,
+  TemplateRule::SimpleExp,
+  TemplateRule::NoEscapeExp,
+  TemplateRule::PartialExp,
+  TemplateRule::EndExp,
+  TemplateRule::BlockExp,
+  TemplateRule::BlockInverseExp,
+  TemplateRule::ElseExp,
+  TemplateRule::ElseChainExp,
+  TemplateRule::CommentExp,
+  TemplateRule::SetDelimitersExp,
+  TemplateRule::RawBlockStart,
+  TemplateRule::PassEscape,
+  TemplateRule::EscapedExp,
+  TemplateRule::EscapedEscape,
+  TemplateRule::EscapedSkip,
+];
+
+// raw block content is passed through until its end, nested raw blocks included
+static RAW_RULES: &'static [TemplateRule] = &[
+  TemplateRule::RawContent,
+  TemplateRule::RawBraces,
+  TemplateRule::RawNestedStart,
+  TemplateRule::RawBlockEnd,
+];
+
+/// Splits a template into raw text and expressions, expressions hold their
+/// surrounding whitespace. Set delimiters tags change the delimiters of the
+/// following expressions.
+pub struct HandleBarsLexer<'a> {
+  input: &'a str,
+  pos: usize,
+  raw: bool,
+  raw_depth: usize,
+  start: usize,
+  delimiters: Delimiters,
+  closes: Vec<usize>,
+}
+
+impl<'a> HandleBarsLexer<'a> {
+  pub fn new(input: &'a str) -> HandleBarsLexer<'a> {
+    HandleBarsLexer::with_delimiters(input, Delimiters::default())
+  }
+
+  pub fn with_delimiters(input: &'a str, delimiters: Delimiters) -> HandleBarsLexer<'a> {
+    let closes = Closes::offsets(input, &delimiters.close);
+    HandleBarsLexer { input: input, pos: 0, raw: false, raw_depth: 0, start: 0, delimiters: delimiters, closes: closes }
+  }
+
+  // byte range of the last returned token
+  fn span(&self) -> (usize, usize) {
+    (self.start, self.pos)
+  }
+
+  /// Tokens along with their byte range in the lexed input.
+  pub fn spanned(self) -> SpannedTokens<'a> {
+    SpannedTokens { lexer: self }
+  }
+}
+
+/// Iterator over tokens and their byte range, see `HandleBarsLexer::spanned()`.
+pub struct SpannedTokens<'a> {
+  lexer: HandleBarsLexer<'a>,
+}
+
+impl<'a> Iterator for SpannedTokens<'a> {
+  type Item = (Token, (usize, usize));
+
+  fn next(&mut self) -> Option<(Token, (usize, usize))> {
+    self.lexer.next().map(|tok| (tok, self.lexer.span()))
+  }
+}
+
+impl<'a> Iterator for HandleBarsLexer<'a> {
+  type Item = Token;
+
+  fn next(&mut self) -> Option<Token> {
+    while self.pos < self.input.len() {
+      let input = self.input;
+      let rest = &input[self.pos..];
+      let closes = Closes { offsets: &self.closes, base: self.pos, len: self.delimiters.close.len() };
+      let matched = if self.raw {
+        longest_match(RAW_RULES, |rule| raw_rule_match(rule, rest, &closes, &self.delimiters))
+      } else {
+        let escapes = self.delimiters.is_default();
+        longest_match(TEMPLATE_RULES, |rule| template_rule_match(rule, rest, &closes, &self.delimiters, escapes))
+      };
+
+      let (len, rule) = match matched {
+        Some(matched) => matched,
+        None => {
+          self.pos += first_char_len(rest);
+          continue;
+        },
+      };
+      let s = rest[..len].to_string();
+      self.start = self.pos;
+      self.pos += len;
+
+      return Some(match rule {
+        TemplateRule::PassThrough | TemplateRule::PassEscape | TemplateRule::EscapedSkip => TokRaw(s),
+        TemplateRule::SimpleExp => TokSimpleExp(s),
+        TemplateRule::NoEscapeExp => TokNoEscapeExp(s),
+        TemplateRule::PartialExp => TokPartialExp(s),
+        TemplateRule::EndExp => TokBlockEndExp(s),
+        TemplateRule::BlockExp => TokBlockExp(s, false),
+        TemplateRule::BlockInverseExp => TokBlockExp(s, true),
+        TemplateRule::ElseExp => TokBlockElseCond(s),
+        TemplateRule::ElseChainExp => TokBlockElseChain(s),
+        TemplateRule::CommentExp => TokCommentExp(s),
+        TemplateRule::SetDelimitersExp => {
+          // invalid delimiters are left as they are, the parser reports them
+          if let Ok(delimiters) = self.delimiters.set_by(&s) {
+            self.closes = Closes::offsets(input, &delimiters.close);
+            self.delimiters = delimiters;
+          }
+          TokSetDelimiters(s)
+        },
+        TemplateRule::RawBlockStart => {
+          self.raw = true;
+          TokRawBlockStart(s)
+        },
+        TemplateRule::EscapedExp | TemplateRule::EscapedEscape => TokEscaped(s),
+        TemplateRule::RawContent | TemplateRule::RawBraces => TokRaw(s),
+        TemplateRule::RawNestedStart => {
+          self.raw_depth += 1;
+          TokRaw(s)
+        },
+        TemplateRule::RawBlockEnd => {
+          if self.raw_depth > 0 {
+            self.raw_depth -= 1;
+            TokRaw(s)
+          } else {
+            self.raw = false;
+            TokRawBlockEnd(s)
+          }
+        },
+      });
+    }
+
+    None
+  }
+}
+
+// longest non empty match, the last rule wins on equal length
+fn longest_match<R: Copy, F: Fn(R) -> Option<usize>>(rules: &[R], matches: F) -> Option<(usize, R)> {
+  let mut longest = None;
+
+  for &rule in rules.iter() {
+    match (matches(rule), longest) {
+      (Some(len), Some((longest_len, _))) if len > 0 && len >= longest_len => longest = Some((len, rule)),
+      (Some(len), None) if len > 0 => longest = Some((len, rule)),
+      _ => (),
+    }
+  }
+
+  longest
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum ExpressionState {
+  Initial,
+  Accessor,
+  PartialName,
+  PropertyPath,
+  IdAny,
+  Params,
+  StringParam,
+  SqStringParam,
+  OptionValue,
+  Options,
+  Comment,
+  ForceEnd,
+  TrailingWhiteSpace,
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+enum ExpressionRule {
+  NoWhiteSpace,
+  Start,
+  StartNoWhiteSpace,
+  WhiteSpace,
+  CommentStart,
+  CommentStartNoWhiteSpace,
+  CommentContent,
+  End,
+  Identifier,
+  PartialName,
+  BracketIdStart,
+  BracketedId,
+  BracketIdEnd,
+  StringStart,
+  StringContent,
+  StringEnd,
+  SqStringStart,
+  SqStringContent,
+  SqStringEnd,
+  This,
+  ParentAlias,
+  AccessorSep,
+  AccessorEnd,
+  ParamsSep,
+  Literal,
+  OptionName,
+  SubExpStart,
+  SubExpEnd,
+  BlockParams,
+}
+
+fn expression_rules(state: ExpressionState) -> &'static [ExpressionRule] {
+  use self::ExpressionRule::*;
+
+  // opening delimiters win over identical closing ones (eg `|data|`)
+  static INITIAL: &'static [ExpressionRule] = &[End, NoWhiteSpace, Start, StartNoWhiteSpace, WhiteSpace, CommentStart, CommentStartNoWhiteSpace];
+  static ACCESSOR: &'static [ExpressionRule] = &[Identifier, BracketIdStart, StringStart, SqStringStart, SubExpStart, This, ParentAlias, NoWhiteSpace, End];
+  static PARTIAL_NAME: &'static [ExpressionRule] = &[PartialName, BracketIdStart, StringStart, SqStringStart, SubExpStart, NoWhiteSpace, End];
+  static PROPERTY_PATH: &'static [ExpressionRule] = &[AccessorSep, AccessorEnd, SubExpEnd, NoWhiteSpace, End];
+  static ID_ANY: &'static [ExpressionRule] = &[BracketedId, BracketIdEnd];
+  static PARAMS: &'static [ExpressionRule] = &[ParamsSep, Identifier, StringStart, SqStringStart, This, ParentAlias, Literal, SubExpStart, SubExpEnd, BlockParams, OptionName, NoWhiteSpace, End];
+  static STRING_PARAM: &'static [ExpressionRule] = &[StringContent, StringEnd];
+  static SQ_STRING_PARAM: &'static [ExpressionRule] = &[SqStringContent, SqStringEnd];
+  static OPTION_VALUE: &'static [ExpressionRule] = &[Identifier, BracketIdStart, StringStart, SqStringStart, This, ParentAlias, Literal, SubExpStart];
+  static OPTIONS: &'static [ExpressionRule] = &[OptionName, ParamsSep, SubExpEnd, BlockParams, NoWhiteSpace, End];
+  static COMMENT: &'static [ExpressionRule] = &[End, NoWhiteSpace, CommentContent];
+  static FORCE_END: &'static [ExpressionRule] = &[End];
+  static TRAILING_WHITESPACE: &'static [ExpressionRule] = &[WhiteSpace];
+
+  match state {
+    ExpressionState::Initial => INITIAL,
+    ExpressionState::Accessor => ACCESSOR,
+    ExpressionState::PartialName => PARTIAL_NAME,
+    ExpressionState::PropertyPath => PROPERTY_PATH,
+    ExpressionState::IdAny => ID_ANY,
+    ExpressionState::Params => PARAMS,
+    ExpressionState::StringParam => STRING_PARAM,
+    ExpressionState::SqStringParam => SQ_STRING_PARAM,
+    ExpressionState::OptionValue => OPTION_VALUE,
+    ExpressionState::Options => OPTIONS,
+    ExpressionState::Comment => COMMENT,
+    ExpressionState::ForceEnd => FORCE_END,
+    ExpressionState::TrailingWhiteSpace => TRAILING_WHITESPACE,
+  }
+}
+
+fn is_identifier_char(c: char) -> bool {
+  !"!\"#%&'()*+,./;<=>@[\\]^`{|}~ \t".contains(c)
+}
+
+// identifiers end before the closing delimiter, that may hold identifier chars (eg `%>`)
+fn identifier_len(s: &str, close: &str) -> usize {
+  let at = if s.starts_with('@') { 1 } else { 0 };
+  let len = s[at..].char_indices()
+    .find(|&(i, c)| !is_identifier_char(c) || s[at + i..].starts_with(close))
+    .map(|(i, _)| i)
+    .unwrap_or(s.len() - at);
+  match len {
+    0 => 0,
+    len => at + len,
+  }
+}
+
+fn number_len(s: &str) -> usize {
+  let sign = if s.starts_with('-') { 1 } else { 0 };
+  let is_digit = |c: char| c.is_digit(10);
+  let integer = run_len(&s[sign..], is_digit);
+  if integer == 0 {
+    return 0;
+  }
+
+  let end = sign + integer;
+  let decimals = if s[end..].starts_with('.') { run_len(&s[end + 1..], is_digit) } else { 0 };
+  if decimals > 0 { end + 1 + decimals } else { end }
+}
+
+// quoted string content, up to the closing quote, escaped characters included
+fn string_content_len(s: &str, quote: char) -> usize {
+  let mut len = 0;
+
+  loop {
+    let rest = &s[len..];
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+      (Some('\\'), Some(c)) => len += 1 + c.len_utf8(),
+      (Some(c), _) if c != quote => len += c.len_utf8(),
+      _ => return len,
+    }
+  }
+}
+
+// quoted string content without its escaping backslashes, before quotes and backslashes
+fn unescape_string(s: &str) -> String {
+  let mut unescaped = String::with_capacity(s.len());
+  let mut chars = s.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match (c, chars.peek()) {
+      ('\\', Some(&next)) if next == '"' || next == '\'' || next == '\\' => {
+        unescaped.push(next);
+        chars.next();
+      },
+      _ => unescaped.push(c),
+    }
+  }
+
+  unescaped
+}
+
+// comment content, up to the closing delimiter or its `~` mark
+fn comment_content_len(s: &str, close: &str) -> usize {
+  let mut len = 0;
+
+  loop {
+    let rest = &s[len..];
+    match rest.chars().next() {
+      Some(_) if rest.starts_with(close) => return len,
+      Some('~') if rest[1..].starts_with(close) => return len,
+      Some(c) => len += c.len_utf8(),
+      None => return len,
+    }
+  }
+}
+
+// expression kind mark following the opening braces, partial blocks are marked with `#>`
+fn mark_len(s: &str) -> usize {
+  match run_len(s, |c| "{#/>^&".contains(c)).min(1) {
+    1 if s.starts_with("#>") => 2,
+    len => len,
+  }
+}
+
+fn expression_rule_match(rule: ExpressionRule, s: &str, open: &str, close: &str) -> usize {
+  let starts = |prefix: &str| if s.starts_with(prefix) { prefix.len() } else { 0 };
+
+  match rule {
+    ExpressionRule::NoWhiteSpace => starts("~"),
+    ExpressionRule::Start => match starts(open) {
+      0 => 0,
+      len => len + mark_len(&s[len..]),
+    },
+    ExpressionRule::StartNoWhiteSpace => {
+      if !s.starts_with(open) {
+        return 0;
+      }
+      let brace = if s[open.len()..].starts_with('{') { open.len() + 1 } else { open.len() };
+      if !s[brace..].starts_with('~') {
+        return 0;
+      }
+      brace + 1 + mark_len(&s[brace + 1..])
+    },
+    ExpressionRule::WhiteSpace => {
+      let mut len = 0;
+      loop {
+        match s[len..].chars().next() {
+          Some(' ') | Some('\t') | Some('\n') => len += 1,
+          Some('\r') if s[len + 1..].starts_with('\n') => len += 2,
+          _ => return len,
+        }
+      }
+    },
+    ExpressionRule::CommentStart | ExpressionRule::CommentStartNoWhiteSpace => {
+      let mark = if rule == ExpressionRule::CommentStart { "!" } else { "~!" };
+      if s.starts_with(open) && s[open.len()..].starts_with(mark) { open.len() + mark.len() } else { 0 }
+    },
+    ExpressionRule::CommentContent => comment_content_len(s, close),
+    ExpressionRule::End => if s.starts_with('}') && s[1..].starts_with(close) { 1 + close.len() } else { starts(close) },
+    ExpressionRule::Identifier => identifier_len(s, close),
+    // partial names are literal, even when they look like paths (eg `{{> shared/dude.thing}}`),
+    // unless they are bracketed, quoted or computed
+    ExpressionRule::PartialName => match s.chars().next() {
+      Some(c) if "[\"'(".contains(c) => 0,
+      _ => s.char_indices()
+        .find(|&(i, c)| c.is_whitespace() || c == '}' || c == '~' || s[i..].starts_with(close))
+        .map(|(i, _)| i)
+        .unwrap_or(s.len()),
+    },
+    ExpressionRule::BracketIdStart => starts("["),
+    ExpressionRule::BracketedId => run_len(s, |c| c != ']'),
+    ExpressionRule::BracketIdEnd => starts("]"),
+    ExpressionRule::StringStart | ExpressionRule::StringEnd => starts("\""),
+    ExpressionRule::StringContent => string_content_len(s, '"'),
+    ExpressionRule::SqStringStart | ExpressionRule::SqStringEnd => starts("'"),
+    ExpressionRule::SqStringContent => string_content_len(s, '\''),
+    ExpressionRule::This => ::std::cmp::max(starts("this"), starts(".")),
+    ExpressionRule::ParentAlias => starts(".."),
+    ExpressionRule::AccessorSep => ::std::cmp::max(starts("."), starts("/")),
+    ExpressionRule::AccessorEnd | ExpressionRule::ParamsSep => run_len(s, is_blank),
+    ExpressionRule::Literal => {
+      let keyword = ["true", "false", "null", "undefined"].iter().map(|k| starts(k)).max().unwrap_or(0);
+      ::std::cmp::max(keyword, number_len(s))
+    },
+    ExpressionRule::OptionName => match identifier_len(s, close) {
+      0 => 0,
+      len => if s[len..].starts_with('=') { len + 1 } else { 0 },
+    },
+    ExpressionRule::SubExpStart => starts("("),
+    ExpressionRule::SubExpEnd => starts(")"),
+    ExpressionRule::BlockParams => {
+      let blanks = if s.starts_with("as") { run_len(&s[2..], is_blank) } else { 0 };
+      if blanks == 0 || !s[2 + blanks..].starts_with('|') {
+        return 0;
+      }
+      let names = 2 + blanks + 1;
+      let names_end = names + run_len(&s[names..], |c| c != '|' && c != '}');
+      if s[names_end..].starts_with('|') { names_end + 1 } else { 0 }
+    },
+  }
+}
+
+/// Splits a single expression (as matched by `HandleBarsLexer`) into path entries,
+/// params, options and whitespace control marks.
+pub struct HBExpressionLexer<'a> {
+  input: &'a str,
+  open: &'a str,
+  close: &'a str,
+  pos: usize,
+  state: ExpressionState,
+  in_options: bool,
+  in_params: bool,
+  sub_expressions: Vec<(bool, bool)>,
+  string_content: String,
+  string_start: usize,
+  span: (usize, usize),
+}
+
+impl<'a> HBExpressionLexer<'a> {
+  pub fn new(input: &'a str) -> HBExpressionLexer<'a> {
+    HBExpressionLexer::with_delimiters(input, "{{", "}}")
+  }
+
+  pub fn with_delimiters(input: &'a str, open: &'a str, close: &'a str) -> HBExpressionLexer<'a> {
+    HBExpressionLexer {
+      input: input,
+      open: open,
+      close: close,
+      pos: 0,
+      state: ExpressionState::Initial,
+      in_options: false,
+      in_params: false,
+      sub_expressions: vec![],
+      string_content: String::new(),
+      string_start: 0,
+      span: (0, 0),
+    }
+  }
+
+  // byte range of the last returned token
+  fn span(&self) -> (usize, usize) {
+    self.span
+  }
+
+  /// true when the expression ended within a quoted string, missing its closing quote
+  pub fn in_string(&self) -> bool {
+    match self.state {
+      ExpressionState::StringParam | ExpressionState::SqStringParam => true,
+      _ => false,
+    }
+  }
+
+  fn params_or_options(&mut self) {
+    self.state = if self.in_options { ExpressionState::Options } else { ExpressionState::Params };
+  }
+
+  // back to params or options, with the unescaped string content
+  fn end_string(&mut self) -> Option<HBToken> {
+    self.params_or_options();
+    Some( TokStringParam( ::std::mem::replace(&mut self.string_content, String::new()) ) )
+  }
+
+  // sub expressions are lexed as expressions, save enclosing params/options state
+  fn start_sub_expression(&mut self) -> Option<HBToken> {
+    self.sub_expressions.push((self.in_params, self.in_options));
+    self.in_params = false;
+    self.in_options = false;
+    self.state = ExpressionState::Accessor;
+    Some( TokSubExpStart )
+  }
+
+  // back to enclosing params/options state
+  fn end_sub_expression(&mut self) -> Option<HBToken> {
+    if let Some((in_params, in_options)) = self.sub_expressions.pop() {
+      self.in_params = in_params;
+      self.in_options = in_options;
+    }
+    self.state = if self.in_options {
+      ExpressionState::Options
+    } else if self.in_params {
+      ExpressionState::Params
+    } else {
+      // sub expression was the expression base (a partial name), params may follow
+      ExpressionState::PropertyPath
+    };
+    Some( TokSubExpEnd )
+  }
+
+  fn action(&mut self, rule: ExpressionRule, s: &str) -> Option<HBToken> {
+    use self::ExpressionState::*;
+
+    match (self.state, rule) {
+      (_, ExpressionRule::NoWhiteSpace) => { self.state = ForceEnd; Some( TokNoWhiteSpaceAfter ) },
+      (_, ExpressionRule::End) => { self.state = TrailingWhiteSpace; None },
+
+      (_, ExpressionRule::Start) => { self.state = if s.ends_with('>') { PartialName } else { Accessor }; None },
+      (_, ExpressionRule::StartNoWhiteSpace) => { self.state = if s.ends_with('>') { PartialName } else { Accessor }; Some( TokNoWhiteSpaceBefore ) },
+      (Initial, ExpressionRule::WhiteSpace) => Some( TokLeadingWhiteSpace( s.to_string() ) ),
+      (_, ExpressionRule::WhiteSpace) => Some( TokTrailingWhiteSpace( s.to_string() ) ),
+      (_, ExpressionRule::CommentStart) => { self.state = Comment; None },
+      (_, ExpressionRule::CommentStartNoWhiteSpace) => { self.state = Comment; Some( TokNoWhiteSpaceBefore ) },
+      (_, ExpressionRule::CommentContent) => None,
+
+      (_, ExpressionRule::Identifier) | (_, ExpressionRule::PartialName) => { self.state = PropertyPath; Some( TokPathEntry( s.to_string() ) ) },
+      (_, ExpressionRule::This) => { self.state = PropertyPath; Some( TokPathEntry( ".".to_string() ) ) },
+      (_, ExpressionRule::ParentAlias) => { self.state = PropertyPath; Some( TokPathEntry( "..".to_string() ) ) },
+      (_, ExpressionRule::BracketIdStart) => { self.state = IdAny; None },
+      (_, ExpressionRule::BracketedId) => Some( TokPathEntry( s.to_string() ) ),
+      (_, ExpressionRule::BracketIdEnd) => { self.state = PropertyPath; None },
+      (_, ExpressionRule::AccessorSep) => { self.state = Accessor; None },
+      (_, ExpressionRule::AccessorEnd) => {
+        self.params_or_options();
+        if self.in_params {
+          Some( TokParamSep )
+        } else {
+          self.in_params = true;
+          Some( TokParamStart )
+        }
+      },
+
+      (_, ExpressionRule::StringStart) => { self.state = StringParam; None },
+      (_, ExpressionRule::SqStringStart) => { self.state = SqStringParam; None },
+      (_, ExpressionRule::StringContent) | (_, ExpressionRule::SqStringContent) => { self.string_content = unescape_string(s); None },
+      (_, ExpressionRule::StringEnd) | (_, ExpressionRule::SqStringEnd) => self.end_string(),
+
+      (OptionValue, ExpressionRule::Literal) => { self.state = Options; Some( TokLiteral( s.to_string() ) ) },
+      (_, ExpressionRule::Literal) => Some( TokLiteral( s.to_string() ) ),
+      (Options, ExpressionRule::ParamsSep) => None,
+      (_, ExpressionRule::ParamsSep) => Some( TokParamSep ),
+      (_, ExpressionRule::OptionName) => {
+        self.in_options = true;
+        self.state = OptionValue;
+        Some( TokOption( s.trim_right_matches('=').to_string() ) )
+      },
+      (_, ExpressionRule::BlockParams) => Some( TokBlockParams( s.to_string() ) ),
+
+      (_, ExpressionRule::SubExpStart) => self.start_sub_expression(),
+      (_, ExpressionRule::SubExpEnd) => self.end_sub_expression(),
+    }
+  }
+}
+
+impl<'a> Iterator for HBExpressionLexer<'a> {
+  type Item = HBToken;
+
+  fn next(&mut self) -> Option<HBToken> {
+    while self.pos < self.input.len() {
+      let input = self.input;
+      let rest = &input[self.pos..];
+      let (open, close) = (self.open, self.close);
+      let matched = longest_match(expression_rules(self.state), |rule| Some(expression_rule_match(rule, rest, open, close)));
+
+      match matched {
+        Some((len, rule)) => {
+          let start = self.pos;
+          self.pos += len;
+          if rule == ExpressionRule::StringStart || rule == ExpressionRule::SqStringStart {
+            self.string_start = start;
+          }
+          if let Some(tok) = self.action(rule, &rest[..len]) {
+            self.span = match tok {
+              // strings span their quotes, whitespace control marks only span the `~`
+              TokStringParam(_) => (self.string_start, self.pos),
+              TokNoWhiteSpaceBefore => {
+                let mark = start + rest[..len].find('~').unwrap_or(0);
+                (mark, mark + 1)
+              },
+              _ => (start, self.pos),
+            };
+            return Some(tok);
+          }
+        },
+        None => self.pos += first_char_len(rest),
+      }
+    }
+
+    None
+  }
+}
+
+/// Kind of a token yielded by [`tokenize()`](fn.tokenize.html).
+#[derive(Debug,Clone,PartialEq)]
+pub enum TokenKind {
+  /// raw text, escapes, or a whole expression tag
+  Template(Token),
+  /// a mark of the expression tag yielded just before
+  Mark(TagMark),
+  /// a part of the expression tag yielded just before
+  Expression(HBToken),
+}
+
+/// Marks telling expression tags kind, yielded by [`tokenize()`](fn.tokenize.html).
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TagMark {
+  /// `#` of blocks
+  Block,
+  /// `^` of inverse blocks
+  InverseBlock,
+  /// `/` of block ends, raw blocks included
+  BlockEnd,
+  /// `else` keyword, or the `^` standing for it
+  Else,
+  /// `>` of partials and partial blocks
+  Partial,
+  /// `*` of decorators
+  Decorator,
+  /// `{` or `&` of unescaped expressions
+  NoEscape,
+  /// `!` of comments
+  Comment,
+}
+
+/// Iterator over a template tokens and their location, see [`tokenize()`](fn.tokenize.html).
+pub struct Tokens<'a> {
+  lexer: HandleBarsLexer<'a>,
+  // marks and expression tokens of the last tag along with their byte range, in reverse order
+  pending: Vec<(TokenKind, usize, usize)>,
+  // delimiters of the next tag, changed along with the lexer ones
+  delimiters: Delimiters,
+  cursor: SpanCursor<'a>,
+}
+
+/// Splits a template into tokens, for tools such as syntax highlighters.
+///
+/// Each template token is followed by the marks and tokens of its
+/// expression, if any, in template order. Spans are byte ranges of
+/// `template`. Whitespace control marks and the tags surrounding whitespace
+/// are yielded, as parsing relies on them, and comments are yielded as a
+/// whole expression tag. Tags are split as they are parsed : the `else`
+/// keyword of `{{else if x}}` is a mark followed by the `if` helper, and raw
+/// blocks tags are split as regular tags, and partial names are a single path
+/// entry (eg `shared/header` in `{{> shared/header}}`).
+///
+/// Set delimiters tags are yielded as a whole, without marks nor parts, and
+/// the tags that follow them are tokenized with the delimiters they set.
+///
+/// ```
+/// use rumblebars::{tokenize, Token, HBToken, TokenKind, TagMark};
+///
+/// let tokens: Vec<_> = tokenize("a {{~#b}}").map(|(kind, span)| (kind, span.start, span.end)).collect();
+///
+/// assert_eq!(tokens, vec![
+///   (TokenKind::Template(Token::TokRaw("a".to_string())), 0, 1),
+///   (TokenKind::Template(Token::TokBlockExp(" {{~#b}}".to_string(), false)), 1, 9),
+///   (TokenKind::Expression(HBToken::TokLeadingWhiteSpace(" ".to_string())), 1, 2),
+///   (TokenKind::Expression(HBToken::TokNoWhiteSpaceBefore), 4, 5),
+///   (TokenKind::Mark(TagMark::Block), 5, 6),
+///   (TokenKind::Expression(HBToken::TokPathEntry("b".to_string())), 6, 7),
+/// ]);
+/// ```
+pub fn tokenize(template: &str) -> Tokens {
+  Tokens { lexer: HandleBarsLexer::new(template), pending: vec![], delimiters: Delimiters::default(), cursor: SpanCursor::new(template) }
+}
+
+// marks and expression tokens of a tag lexed with `delimiters`, located at `offset` in the template,
+// in reverse order. Expressions are lexed as they are parsed : without decorator marks, partial
+// blocks `#`, chained else keyword, and raw block delimiters extra braces
+fn tag_tokens(tok: &Token, offset: usize, delimiters: &Delimiters) -> Vec<(TokenKind, usize, usize)> {
+  use self::TagMark::*;
+
+  let source = tok.source();
+  let raw = match *tok { TokRawBlockStart(_) | TokRawBlockEnd(_) => true, _ => false };
+  let open = source.find(&delimiters.open[..]).unwrap_or(0) + delimiters.open.len() + if raw { 2 } else { 0 };
+  let at = if source[open..].starts_with('~') { open + 1 } else { open };
+  let after_mark = source[at..].chars().nth(1);
+
+  let mut marks = vec![];
+  let mut left_out = vec![];
+  let mut else_keyword = None;
+
+  match *tok {
+    TokSimpleExp(_) if source[at..].starts_with('*') => {
+      marks.push((Decorator, at, at + 1));
+      left_out.push((at, at + 1));
+    },
+    TokNoEscapeExp(_) => marks.push((NoEscape, at, at + 1)),
+    TokPartialExp(_) => marks.push((Partial, at, at + 1)),
+    TokCommentExp(_) => marks.push((Comment, at, at + 1)),
+    TokBlockEndExp(_) => marks.push((BlockEnd, at, at + 1)),
+    TokBlockExp(_, true) => marks.push((InverseBlock, at, at + 1)),
+    TokBlockExp(_, false) => {
+      marks.push((Block, at, at + 1));
+      match after_mark {
+        Some('*') => {
+          marks.push((Decorator, at + 1, at + 2));
+          left_out.push((at + 1, at + 2));
+        },
+        Some('>') => {
+          marks.push((Partial, at + 1, at + 2));
+          left_out.push((at, at + 1));
+        },
+        _ => (),
+      }
+    },
+    TokBlockElseCond(_) if source[at..].starts_with('^') => marks.push((Else, at, at + 1)),
+    TokBlockElseCond(_) | TokBlockElseChain(_) => {
+      let keyword = at + source[at..].find("else").unwrap_or(0);
+      marks.push((Else, keyword, keyword + 4));
+      match *tok {
+        TokBlockElseChain(_) => left_out.push((keyword, keyword + 4)),
+        _ => else_keyword = Some((keyword, keyword + 4)),
+      }
+    },
+    TokRawBlockStart(_) | TokRawBlockEnd(_) => {
+      if let TokRawBlockEnd(_) = *tok {
+        marks.push((BlockEnd, at, at + 1));
+      }
+      left_out.push((open - 2, open));
+      if let Some(close) = source.rfind(&delimiters.close[..]) {
+        left_out.push((close - 2, close));
+      }
+    },
+    _ => (),
+  }
+
+  let mut expression = String::new();
+  let mut kept = 0;
+  for &(start, end) in left_out.iter() {
+    expression.push_str(&source[kept..start]);
+    kept = end;
+  }
+  expression.push_str(&source[kept..]);
+
+  // expression offsets back to the source ones, end offsets stay before left out ranges
+  let to_source = |at: usize, end: bool| left_out.iter().fold(at, |at, &(start, stop)| {
+    if start < at || (start == at && !end) { at + stop - start } else { at }
+  });
+
+  let mut tokens: Vec<_> = marks.into_iter().map(|(mark, start, end)| (TokenKind::Mark(mark), offset + start, offset + end)).collect();
+  let mut lexer = HBExpressionLexer::with_delimiters(&expression, &delimiters.open, &delimiters.close);
+  while let Some(tok) = lexer.next() {
+    let (start, end) = lexer.span();
+    let (start, end) = (to_source(start, false), to_source(end, true));
+    // `{{else}}` is lexed as is, its keyword is only yielded as a mark
+    match else_keyword {
+      Some((keyword, keyword_end)) if start >= keyword && end <= keyword_end => continue,
+      _ => tokens.push((TokenKind::Expression(tok), offset + start, offset + end)),
+    }
+  }
+
+  tokens.sort_by(|a, b| a.1.cmp(&b.1));
+  tokens.reverse();
+  tokens
+}
+
+impl<'a> Iterator for Tokens<'a> {
+  type Item = (TokenKind, Span);
+
+  fn next(&mut self) -> Option<(TokenKind, Span)> {
+    if let Some((kind, start, end)) = self.pending.pop() {
+      return Some((kind, self.cursor.span(start, end)));
+    }
+
+    self.lexer.next().map(|tok| {
+      let (start, end) = self.lexer.span();
+      match tok {
+        TokRaw(_) | TokEscaped(_) => (),
+        TokSetDelimiters(ref s) => if let Ok(delimiters) = self.delimiters.set_by(s) {
+          self.delimiters = delimiters;
+        },
+        _ => self.pending = tag_tokens(&tok, start, &self.delimiters),
+      }
+      (TokenKind::Template(tok), self.cursor.span(start, end))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{HandleBarsLexer, HBExpressionLexer, tokenize};
+
+  fn tokens(template: &str) -> Vec<String> {
+    HandleBarsLexer::new(template).map(|t| format!("{:?}", t)).collect()
+  }
+
+  fn expression_tokens(exp: &str) -> Vec<String> {
+    HBExpressionLexer::new(exp).map(|t| format!("{:?}", t)).collect()
+  }
+
+  #[test]
+  fn lex_template() {
+    assert_eq!(tokens("a b {{c}}\n{{#d}}{{else}}{{else e}}{{/d}}"), vec![
+      "TokRaw(\"a\")", "TokRaw(\" b\")", "TokSimpleExp(\" {{c}}\\n\")", "TokBlockExp(\"{{#d}}\", false)",
+      "TokBlockElseCond(\"{{else}}\")", "TokBlockElseChain(\"{{else e}}\")", "TokBlockEndExp(\"{{/d}}\")",
+    ]);
+  }
+
+  #[test]
+  fn lex_longest_expression() {
+    assert_eq!(tokens("{{{a}}}{{&b}}{{~{c}~}}"), vec![
+      "TokNoEscapeExp(\"{{{a}}}\")", "TokNoEscapeExp(\"{{&b}}\")", "TokNoEscapeExp(\"{{~{c}~}}\")",
+    ]);
+    assert_eq!(tokens("{{~! a }}{{^}}{{^a}}"), vec![
+      "TokCommentExp(\"{{~! a }}\")", "TokBlockElseCond(\"{{^}}\")", "TokBlockExp(\"{{^a}}\", true)",
+    ]);
+  }
+
+  #[test]
+  fn lex_escapes() {
+    assert_eq!(tokens("\\{{a}} \\\\{{b}} \\a { c"), vec![
+      "TokEscaped(\"\\\\{\")", "TokRaw(\"{a}}\")", "TokRaw(\" \")", "TokEscaped(\"\\\\\\\\\")", "TokSimpleExp(\"{{b}} \")",
+      "TokRaw(\"\\\\a\")", "TokRaw(\" {\")", "TokRaw(\" c\")",
+    ]);
+  }
+
+  #[test]
+  fn lex_raw_block() {
+    assert_eq!(tokens("{{{{a}}}}{{{{b}}}}{{c}}{{{{/b}}}}{{{{/a}}}}"), vec![
+      "TokRawBlockStart(\"{{{{a}}}}\")", "TokRaw(\"{{{{b}}}}\")", "TokRaw(\"{{\")", "TokRaw(\"c}}\")",
+      "TokRaw(\"{{{{/b}}}}\")", "TokRawBlockEnd(\"{{{{/a}}}}\")",
+    ]);
+  }
+
+  #[test]
+  fn lex_expression() {
+    assert_eq!(expression_tokens("  {{~#each this.[a b] \"c\\\"\" -1.5 (d e) f=true as |g|~}}\n"), vec![
+      "TokLeadingWhiteSpace(\"  \")", "TokNoWhiteSpaceBefore", "TokPathEntry(\"each\")", "TokParamStart",
+      "TokPathEntry(\".\")", "TokPathEntry(\"a b\")", "TokParamSep", "TokStringParam(\"c\\\"\")", "TokParamSep",
+      "TokLiteral(\"-1.5\")", "TokParamSep", "TokSubExpStart", "TokPathEntry(\"d\")", "TokParamStart", "TokPathEntry(\"e\")",
+      "TokSubExpEnd", "TokParamSep", "TokOption(\"f\")", "TokLiteral(\"true\")", "TokBlockParams(\"as |g|\")",
+      "TokNoWhiteSpaceAfter", "TokTrailingWhiteSpace(\"\\n\")",
+    ]);
+  }
+
+  #[test]
+  fn lex_string_escapes() {
+    assert_eq!(expression_tokens(r#"{{a "b\"c\\" 'd\'e\\' "f\g" "h\'"}}"#), vec![
+      "TokPathEntry(\"a\")", "TokParamStart", "TokStringParam(\"b\\\"c\\\\\")", "TokParamSep",
+      "TokStringParam(\"d'e\\\\\")", "TokParamSep", "TokStringParam(\"f\\\\g\")", "TokParamSep", "TokStringParam(\"h'\")",
+    ]);
+  }
+
+  #[test]
+  fn tokenize_spans() {
+    let template = "a\n{{~! c ~}}\n{{#if \"x\" b=(c)}}";
+    let spans: Vec<_> = tokenize(template).map(|(kind, span)| (format!("{:?}", kind), &template[span.start..span.end], span.line, span.column)).collect();
+
+    assert_eq!(spans, vec![
+      ("Template(TokRaw(\"a\"))".to_string(), "a", 1, 1),
+      ("Template(TokCommentExp(\"\\n{{~! c ~}}\\n\"))".to_string(), "\n{{~! c ~}}\n", 1, 2),
+      ("Expression(TokLeadingWhiteSpace(\"\\n\"))".to_string(), "\n", 1, 2),
+      ("Expression(TokNoWhiteSpaceBefore)".to_string(), "~", 2, 3),
+      ("Mark(Comment)".to_string(), "!", 2, 4),
+      ("Expression(TokNoWhiteSpaceAfter)".to_string(), "~", 2, 8),
+      ("Expression(TokTrailingWhiteSpace(\"\\n\"))".to_string(), "\n", 2, 11),
+      ("Template(TokBlockExp(\"{{#if \\\"x\\\" b=(c)}}\", false))".to_string(), "{{#if \"x\" b=(c)}}", 3, 1),
+      ("Mark(Block)".to_string(), "#", 3, 3),
+      ("Expression(TokPathEntry(\"if\"))".to_string(), "if", 3, 4),
+      ("Expression(TokParamStart)".to_string(), " ", 3, 6),
+      ("Expression(TokStringParam(\"x\"))".to_string(), "\"x\"", 3, 7),
+      ("Expression(TokParamSep)".to_string(), " ", 3, 10),
+      ("Expression(TokOption(\"b\"))".to_string(), "b=", 3, 11),
+      ("Expression(TokSubExpStart)".to_string(), "(", 3, 13),
+      ("Expression(TokPathEntry(\"c\"))".to_string(), "c", 3, 14),
+      ("Expression(TokSubExpEnd)".to_string(), ")", 3, 15),
+    ]);
+  }
+}
+
+#[cfg(test)]
+#[cfg(feature = "nightly")]
+mod bench {
+  use super::HandleBarsLexer;
+  use test::Bencher;
+
+  #[bench]
+  fn lex_template(b: &mut Bencher) {
+    b.iter(|| {
+      HandleBarsLexer::new("a {{b}} {{#c d e=f}}{{{g}}}{{else}}h{{/c}}\n").count()
+    })
+  }
+}
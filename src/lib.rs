@@ -4,13 +4,12 @@
 //!
 //! This crates provides a library for parsing and expanding handlebars template
 //!
-//! to use with rust nighly feature build with ```cargo build --features nightly --no-default-features```
+//! benchmarks need rust nightly, run them with ```cargo bench --features nightly```
 
 //!
-//! Rumblebars passes **all mustaches specs** [[1]](#1) and **272 handlebars tests** [[2]](#2). Template evaluation is rendered to a `io::Writer`, so that you can choose wether if you hold result in memory or not. It also input data angostic, given that your data structure implements the `HBData` trait (Json implementation provided).
+//! Rumblebars passes **all mustaches specs** and **260 handlebars tests** [[1]](#1). Template evaluation is rendered to a `io::Writer`, so that you can choose wether if you hold result in memory or not. It also input data angostic, given that your data structure implements the `HBData` trait (Json implementation provided).
 //!
-//!  [1] <a name="1"></a> except delimiter changes test suite and one test failing because of a trailing space
-//!  [2] <a name="2"></a> all tests that does not involves javascript in data and partials, and see the [comments for other cases](https://github.com/nicolas-cherel/rumblebars/blob/master/tests/eval/handlebars.rs#L88-L134)
+//!  [1] <a name="1"></a> all tests that does not involves javascript in data and partials, and see the [comments for other cases](https://github.com/nicolas-cherel/rumblebars/blob/master/tests/eval/handlebars.rs#L93-L133)
 //!
 //! ## HMTL escaping safety
 //!
@@ -55,6 +54,10 @@
 //! rumblebars::parse("{{hello}}").unwrap();
 //! ```
 //!
+//! Inline templates can be checked at compile time with the `hbs!("{{hello}}")` macro
+//! of the `rumblebars_macros` crate, syntax errors are then reported by the compiler. The
+//! template is still built at runtime, from its entries parsed while compiling.
+//!
 //! Same with `eval()` :
 //!
 //! ```
@@ -96,22 +99,14 @@
 //! # else { panic!("should not reach") }
 //! ```
 //!
+//! Helpers can also be called as subexpressions in parameters and options (eg `{{outer (inner a b) key=(other c)}}`), the inner helper gives the parameter value with `HelperOptions::return_value`, keeping its type (eg `{{#if (lookup . "k")}}`), or else the content it writes is used as a string, falsy when empty. A subexpression without helper is the value at its path.
+//!
 
 #![crate_name="rumblebars"]
 
 #![cfg_attr(feature = "nightly", feature(test))]
 #![cfg_attr(feature = "nightly", feature(const_fn))]
-#![cfg_attr(feature = "nightly", feature(plugin))]
-#![cfg_attr(feature = "nightly", plugin(rustlex))]
-
-#[cfg(feature = "with-syntex")] extern crate rustlex_codegen as rustlex;
-#[cfg(feature = "nightly")] #[warn(plugin_as_library)] extern crate rustlex;
-
-#[cfg(feature = "with-syntex")] mod parse { include!(concat!(env!("OUT_DIR"), "/parse.rs")); }
-#[cfg(feature = "nightly")] mod parse;
-
 
-extern crate regex;
 extern crate rustc_serialize as serialize;
 
 
@@ -121,8 +116,37 @@ extern crate rustc_serialize as serialize;
 extern crate lazy_static;
 
 pub use self::parse::parse;
+pub use self::parse::parse_with_name;
+pub use self::parse::parse_with_name_and_delimiters;
+pub use self::parse::parse_recovering;
+pub use self::parse::parse_recovering_with_delimiters;
+pub use self::parse::parse_with_delimiters;
 pub use self::parse::ParseError;
+pub use self::parse::ParseErrorKind;
+pub use self::parse::Span;
 pub use self::parse::Template;
+pub use self::parse::Entries;
+pub use self::parse::HBEntry;
+pub use self::parse::HBExpression;
+pub use self::parse::HBValHolder;
+pub use self::parse::RenderOptions;
+pub use self::print::print;
+pub use self::format::format;
+pub use self::lexer::tokenize;
+pub use self::lexer::Tokens;
+pub use self::lexer::TokenKind;
+pub use self::lexer::TagMark;
+pub use self::lexer::Token;
+pub use self::lexer::HBToken;
+pub use self::codegen::compile_to_rust;
+pub use self::codegen::CodegenError;
+pub use self::codegen::template_expression;
+#[doc(hidden)] pub use self::codegen::runtime as codegen_runtime;
+pub use self::references::Reference;
+pub use self::visit::Visitor;
+pub use self::visit::VisitorMut;
+pub use self::visit::walk_expression;
+pub use self::visit::walk_expression_mut;
 pub use self::eval::eval;
 pub use self::eval::HBData;
 pub use self::eval::HBIter;
@@ -132,11 +156,20 @@ pub use self::eval::HBEvalResult;
 pub use self::eval::EvalContext;
 pub use self::eval::HelperOptions;
 pub use self::eval::HelperOptionsByName;
+pub use self::eval::BlockEnvironment;
 pub use self::eval::SafeWriting;
 pub use self::eval::HTMLSafeWriter;
 
+mod codegen;
 mod eval;
+mod format;
 mod helpers_builtins;
+mod lexer;
+mod parse;
+mod precompiled;
+mod print;
+mod references;
+mod visit;
 
 pub mod preludes {
   pub mod hbdata {
@@ -144,7 +177,7 @@ pub mod preludes {
   }
 
   pub mod helpers {
-    pub use super::super::eval::{EvalContext, HelperOptions, HelperOptionsByName, SafeWriting, HTMLSafeWriter};
+    pub use super::super::eval::{EvalContext, HelperOptions, HelperOptionsByName, BlockEnvironment, SafeWriting, HTMLSafeWriter};
   }
 }
 
@@ -1,238 +1,16 @@
-use std::io::BufReader;
 use serialize::json::Json;
-use regex::Regex;
 
-use self::Token::{TokSimpleExp, TokNoEscapeExp, TokCommentExp, TokBlockExp, TokBlockElseCond, TokBlockEndExp, TokPartialExp, TokRaw};
-use self::HBToken::{TokPathEntry,TokNoWhiteSpaceBefore, TokNoWhiteSpaceAfter,TokStringParam,TokParamStart, TokParamSep, TokOption, TokLeadingWhiteSpace, TokTrailingWhiteSpace};
-
-#[derive(Debug)]
-pub enum Token {
-  // base template tokens
-  TokSimpleExp(String),
-  TokNoEscapeExp(String),
-  TokCommentExp(String),
-  TokPartialExp(String,),
-  TokBlockExp(String, bool),
-  TokBlockElseCond(String),
-  TokBlockEndExp(String,),
-  TokRaw(String),
-}
-
-#[derive(Debug)]
-pub enum HBToken {
-  TokPathEntry(String),
-  TokNoWhiteSpaceBefore,
-  TokNoWhiteSpaceAfter,
-  TokStringParam(String),
-  TokParamStart,
-  TokParamSep,
-  TokOption(String),
-  TokLeadingWhiteSpace(String),
-  TokTrailingWhiteSpace(String),
-}
-
-rustlex! HandleBarsLexer {
-  // expression definitions
-
-  let OPEN  = "{{" '~'?;
-  let CLOSE = [' ''\t']* '~'? "}}";
-  let EXP = ('}'?[^'}'])*;
-
-  let NEW_LINE     = (['\n'] | ['\r']['\n']);
-  let IGN_WP        = [' ''\t''\r']*;
-  let ALL_WP        = (NEW_LINE | IGN_WP)*;
-  let PASS_THROUGH  = ALL_WP* ('{'?[^'{'' ''\t''\r''\n''\\'])*;
-  let PASS_ESC      = '\\';
-  let ESCAPED_EXP   = '\\' '{';
-  let ESCAPED_ESC   = '\\' '\\';
-  let ESCAPED_SKIP  = '\\' '\\'? [^'{''\\''\r''\n'];
-
-  let BLOCK_EXP     = ALL_WP OPEN '#' EXP CLOSE ALL_WP;
-  let BLOCK_INV_EXP = ALL_WP OPEN '^' EXP CLOSE ALL_WP;
-  let END_EXP       = ALL_WP OPEN '/' EXP CLOSE   ALL_WP;
-  let NO_ESC_EXP    = ALL_WP OPEN ('{' EXP '}' | '&' EXP) CLOSE ALL_WP;
-  let PARTIAL_EXP   = ALL_WP OPEN '>' EXP CLOSE ALL_WP;
-  let SIMPLE_EXP    = ALL_WP OPEN [^'!'] EXP CLOSE ALL_WP;
-  let ELSE_EXP      = ALL_WP OPEN (IGN_WP "else" IGN_WP | '^') CLOSE ALL_WP;
-
-  let COMMENT_EXP   = ALL_WP OPEN '!' EXP CLOSE ALL_WP;
-
-  // then rules
-  PASS_THROUGH      => |lexer:&mut HandleBarsLexer<R>| { Some( TokRaw( lexer.yystr() ) ) }
-
-  SIMPLE_EXP        => |lexer:&mut HandleBarsLexer<R>| { Some( TokSimpleExp(     lexer.yystr() ) ) }
-  NO_ESC_EXP        => |lexer:&mut HandleBarsLexer<R>| { Some( TokNoEscapeExp(   lexer.yystr() ) ) }
-  PARTIAL_EXP       => |lexer:&mut HandleBarsLexer<R>| { Some( TokPartialExp(    lexer.yystr() ) ) }
-  END_EXP           => |lexer:&mut HandleBarsLexer<R>| { Some( TokBlockEndExp(   lexer.yystr() ) ) }
-  BLOCK_EXP         => |lexer:&mut HandleBarsLexer<R>| { Some( TokBlockExp(      lexer.yystr(), false ) ) }
-  BLOCK_INV_EXP     => |lexer:&mut HandleBarsLexer<R>| { Some( TokBlockExp(      lexer.yystr(), true  ) ) }
-  ELSE_EXP          => |lexer:&mut HandleBarsLexer<R>| { Some( TokBlockElseCond( lexer.yystr() ) ) }
-
-  COMMENT_EXP       => |lexer:&mut HandleBarsLexer<R>| { Some( TokCommentExp(    lexer.yystr() ) ) }
-
-  PASS_ESC          => |lexer:&mut HandleBarsLexer<R>| { Some( TokRaw( lexer.yystr()    ) ) }
-  ESCAPED_EXP       => |    _:&mut HandleBarsLexer<R>| { Some( TokRaw( "{".to_string()  ) ) }
-  ESCAPED_ESC       => |    _:&mut HandleBarsLexer<R>| { Some( TokRaw( "\\".to_string() ) ) }
-  ESCAPED_SKIP      => |lexer:&mut HandleBarsLexer<R>| { Some( TokRaw( lexer.yystr()    ) ) }
-}
-
-rustlex! HBExpressionLexer {
-  token HBToken;
-  property in_options:bool = false;
-  property in_params:bool = false;
-
-
-  let NEW_LINE     =  '\r'?'\n';
-  let IGN_WP        = [' ''\t']*;
-  let ALL_WP        = (NEW_LINE | IGN_WP)+;
-
-  let NO_WP       = '~';
-  let START       = "{{" ['{''#''/''>''^''&']?;
-  let START_NO_WP = "{{" '{'? NO_WP ['#''/''>''^''&']?;
-  let END         =  '}'? "}}";
-
-  let COMMENT_START       = "{{!";
-  let COMMENT_START_NO_WP = "{{~!";
-  let COMMENT_CONTENT     = (([^'}''~']|'}' [^'}']|'~' '}' [^'}'])?)*;
-
-  let STRING_START = '"';
-  let STRING_CTNT  = ("\\\"" | [^'"'])*; // either escaped quote or not quote
-  let STRING_END   = ['"'];
-
-
-  let IDENTIFIER = '@'? [^'!''"''#''%''&''\'''('')''*''+'',''.''/'';''<''=''>''@''[''\\'']''^''`''{''|''}''~'' ''\t']+;
-  let BRACKET_ID_START = '[';
-  let BRACKET_ID_END   = ']';
-  let BRACKETED_ID     = [^']']+;
-  let ACCESSOR_SEP     = ['.''/'];
-  let ACCESSOR_END     = [' ''\t']+;
-
-  let THIS             = "this" | ".";
-  let PARENT_ALIAS     = "..";
-
-  let PARAMS_SEP       = [' ''\t']+;
-
-  let OPTION_NAME      = IDENTIFIER "=";
-
-  INITIAL {
-    NO_WP       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-
-    START       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.ACCESSOR(); None }
-    START_NO_WP => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.ACCESSOR(); Some(TokNoWhiteSpaceBefore) }
-    ALL_WP      => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { Some( TokLeadingWhiteSpace( lexer.yystr() ) ) }
-
-    COMMENT_START       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.COMMENT(); None }
-    COMMENT_START_NO_WP => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.COMMENT(); Some(TokNoWhiteSpaceBefore) }
-
-    END         => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-  }
-
-  ACCESSOR {
-    IDENTIFIER       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( lexer.yystr() ) ) }
-    BRACKET_ID_START => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.ID_ANY(); None }
-
-    STRING_START => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.STRING_PARAM(); None } // for parameters only
-
-    THIS         => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( ".".to_string()  ) ) }
-    PARENT_ALIAS => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( "..".to_string() ) ) }
-
-    NO_WP       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-    END         => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-  }
-
-  PROPERTY_PATH {
-    ACCESSOR_SEP => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.ACCESSOR(); None }
-    ACCESSOR_END => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> {
-      if lexer.in_options  { lexer.OPTIONS() } else { lexer.PARAMS() };
-      if lexer.in_params {
-        Some( TokParamSep )
-      } else {
-        lexer.in_params = true;
-        Some( TokParamStart )
-      }
-    }
-
-    // common ending
-    NO_WP        => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-    END          => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-  }
-
-  ID_ANY {
-    BRACKETED_ID   => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { Some( TokPathEntry( lexer.yystr() ) ) }
-    BRACKET_ID_END => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); None }
-  }
-
-  PARAMS {
-    PARAMS_SEP   => |    _:&mut HBExpressionLexer<R>| -> Option<HBToken> { Some( TokParamSep ) }
-    IDENTIFIER   => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( lexer.yystr() ) ) }
-    STRING_START => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.STRING_PARAM(); None }
-
-    THIS         => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( ".".to_string()  ) ) }
-    PARENT_ALIAS => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( "..".to_string() ) ) }
-
-    // end of parameters
-    OPTION_NAME  => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> {
-      lexer.in_options = true;
-      lexer.OPTION_VALUE();
-      Some( TokOption( lexer.yystr().trim_right_matches('=').to_string() ) )
-    }
-
-    // common expression ending
-    NO_WP        => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-    END          => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-
-  }
-
-  STRING_PARAM {
-    STRING_CTNT => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { Some( TokStringParam( lexer.yystr() ) ) }
-    STRING_END  => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { if lexer.in_options  { lexer.OPTIONS() } else { lexer.PARAMS() }; None }
-  }
-
-  OPTION_VALUE {
-    // all of these have conditional ending with in_params
-    IDENTIFIER       => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( lexer.yystr() ) ) }
-    BRACKET_ID_START => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.ID_ANY(); None }
-    STRING_START     => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.STRING_PARAM(); None }
-
-    THIS         => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( ".".to_string()  ) ) }
-    PARENT_ALIAS => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.PROPERTY_PATH(); Some( TokPathEntry( "..".to_string() ) ) }
-
-    // ok, pure option parsing for now
-  }
-
-  OPTIONS {
-    OPTION_NAME  => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> {  lexer.OPTION_VALUE(); Some( TokOption( lexer.yystr().trim_right_matches('=').to_string() ) ) }
-    PARAMS_SEP   => |    _:&mut HBExpressionLexer<R>| -> Option<HBToken> { None }
-
-    // common expression ending
-    NO_WP        => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-    END          => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-  }
-
-  COMMENT {
-    END             => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-    NO_WP           => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.FORCE_END(); Some( TokNoWhiteSpaceAfter ) }
-
-    COMMENT_CONTENT => |    _:&mut HBExpressionLexer<R>| -> Option<HBToken> { None }
-  }
-
-  FORCE_END {
-    END => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { lexer.TRAILING_WP(); None }
-  }
-
-  TRAILING_WP {
-    ALL_WP => |lexer:&mut HBExpressionLexer<R>| -> Option<HBToken> { Some( TokTrailingWhiteSpace( lexer.yystr() ) ) }
-  }
-
-
-
-}
+use lexer::{HandleBarsLexer, HBExpressionLexer, Token, Delimiters};
+use lexer::Token::{TokSimpleExp, TokNoEscapeExp, TokCommentExp, TokSetDelimiters, TokBlockExp, TokBlockElseCond, TokBlockElseChain, TokBlockEndExp, TokPartialExp, TokRawBlockStart, TokRawBlockEnd, TokRaw, TokEscaped};
+use lexer::HBToken;
+use lexer::HBToken::{TokPathEntry,TokNoWhiteSpaceBefore, TokNoWhiteSpaceAfter,TokStringParam,TokLiteral,TokParamStart, TokParamSep, TokOption, TokSubExpStart, TokSubExpEnd, TokBlockParams, TokLeadingWhiteSpace, TokTrailingWhiteSpace};
 
 #[derive(Debug)]
 pub enum HBValHolder {
   String(String),
   Path(Vec<String>),
   Literal(Json, String),
+  SubExpression(HBExpression),
 }
 
 #[derive(Debug)]
@@ -242,6 +20,8 @@ pub struct RenderOptions {
   pub indent: Option<String>,
   pub no_leading_whitespace: bool,
   pub no_trailing_whitespace: bool,
+  pub raw_block: bool,
+  pub decorator: bool,
 }
 
 #[derive(Debug)]
@@ -249,6 +29,7 @@ pub struct HBExpression {
   pub base: Vec<String>,
   pub params: Vec<HBValHolder>,
   pub options: Vec<(String, HBValHolder)>,
+  pub block_params: Vec<String>,
   pub render_options: RenderOptions,
   pub block: Option<Box<Entries>>,
   pub else_block: Option<Box<Entries>>,
@@ -272,17 +53,8 @@ pub enum HBEntry {
   Partial(HBExpression),
 }
 
-impl HBEntry {
-  fn is_partial(&self) -> bool {
-    match self {
-      &HBEntry::Partial(_) => true,
-      _ => false
-    }
-  }
-}
-
 pub type Entries = Vec<Box<HBEntry>>;
-pub type ParseResult = Result<Template, (ParseError, Option<String>)>;
+pub type ParseResult = Result<Template, ParseError>;
 
 
 
@@ -309,6 +81,60 @@ impl Template {
     parse(template)
   }
 
+  /// same as `new()`, with template name reported in parse errors
+  pub fn new_with_name(template: &str, name: &str) -> ParseResult {
+    parse_with_name(template, name)
+  }
+
+  /// same as `new()`, with `open` and `close` as default delimiters
+  pub fn new_with_delimiters(template: &str, open: &str, close: &str) -> ParseResult {
+    parse_with_delimiters(template, open, close)
+  }
+
+  /// Parses a template read from `reader`. Templates are parsed from a string, so `reader`
+  /// is read to its end before parsing, read failures and invalid UTF-8 are reported as
+  /// `ParseErrorKind::ReadError` errors, that hold the `io::Error` as their source.
+  ///
+  /// ```
+  /// use rumblebars::Template;
+  ///
+  /// let template = Template::from_reader("Hello {{name}}".as_bytes()).unwrap();
+  ///
+  /// assert_eq!(template.to_string(), "Hello {{name}}");
+  /// ```
+  pub fn from_reader<R: io::Read>(reader: R) -> ParseResult {
+    Template::from_reader_with_delimiters(reader, "{{", "}}")
+  }
+
+  /// same as `from_reader()`, with `open` and `close` as default delimiters
+  pub fn from_reader_with_delimiters<R: io::Read>(mut reader: R, open: &str, close: &str) -> ParseResult {
+    let mut template = String::new();
+
+    match reader.read_to_string(&mut template) {
+      Ok(_) => parse_with_delimiters(&template, open, close),
+      Err(e) => Err(ParseError::read_error(e)),
+    }
+  }
+
+  /// Parses the template file at `path`, its path is reported as template name in parse errors.
+  pub fn from_file<P: AsRef<::std::path::Path>>(path: P) -> ParseResult {
+    Template::from_file_with_delimiters(path, "{{", "}}")
+  }
+
+  /// same as `from_file()`, with `open` and `close` as default delimiters
+  pub fn from_file_with_delimiters<P: AsRef<::std::path::Path>>(path: P, open: &str, close: &str) -> ParseResult {
+    let path = path.as_ref();
+    let template = match ::std::fs::File::open(path) {
+      Ok(file) => Template::from_reader_with_delimiters(file, open, close),
+      Err(e) => Err(ParseError::read_error(e)),
+    };
+
+    template.map_err(|mut e| {
+      e.template_name = Some(path.display().to_string());
+      e
+    })
+  }
+
   pub fn eval_to_string(&self, data: &HBData) -> Option<String> {
     let mut buf = Vec::new();
     self.eval(data, &mut buf, &Default::default()).ok().and_then(|_| String::from_utf8(buf).ok())
@@ -328,52 +154,405 @@ impl ::std::default::Default for Template {
 
 /// for `"{{hello}}".parse()` expressions
 impl ::std::str::FromStr for Template {
-  type Err = (ParseError, Option<String>);
+  type Err = ParseError;
 
   fn from_str(s: &str) -> Result<Self, <Self as ::std::str::FromStr>::Err> {
     parse(s)
   }
 }
 
-#[derive(Debug,Clone,Copy)]
-pub enum ParseError {
+#[derive(Debug,Clone)]
+pub enum ParseErrorKind {
   UnkownError, // unknown as ‘still not diagnosed’ case, not ’your grandma's TV is set on fire’ case
   InvalidExpression,
   UnmatchedBlock,
   UnexpectedBlockClose,
+  UnclosedBlock,
+  UnexpectedElse,
+  InvalidDelimiters,
+  // template source could not be read, see `Template::from_reader()`
+  ReadError(::std::sync::Arc<io::Error>),
+}
+
+// read errors are equal when they are of the same kind
+impl PartialEq for ParseErrorKind {
+  fn eq(&self, other: &ParseErrorKind) -> bool {
+    match (self, other) {
+      (&ParseErrorKind::ReadError(ref e), &ParseErrorKind::ReadError(ref f)) => e.kind() == f.kind(),
+      _ => ::std::mem::discriminant(self) == ::std::mem::discriminant(other),
+    }
+  }
+}
+
+impl Eq for ParseErrorKind {}
+
+/// Parse failure report.
+///
+/// Displaying it gives the whole story : template name, location, description
+/// and the source line with the offending expression underlined.
+///
+/// ```
+/// use rumblebars::{parse_with_name, ParseErrorKind};
+///
+/// let err = parse_with_name("{{#if a}}\n  {{/each}}", "page").err().unwrap();
+///
+/// assert_eq!(err.kind, ParseErrorKind::UnmatchedBlock);
+/// assert_eq!(err.expected, Some("if".to_string()));
+/// assert_eq!(err.found, Some("each".to_string()));
+/// assert_eq!(format!("{}", err), "page, line 2, column 3: ‘each’ does not match ‘if’ opened at line 1, column 1\n  {{/each}}\n  ^^^^^^^^^");
+/// ```
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ParseError {
+  pub kind: ParseErrorKind,
+  /// name of the block that should have been closed
+  pub expected: Option<String>,
+  /// offending expression, or name of the block closed instead of `expected` one
+  pub found: Option<String>,
+  /// location of the offending expression
+  pub span: Span,
+  /// location of the expression that opened `expected` block
+  pub opened_at: Option<Span>,
+  /// template name, as given to `parse_with_name()`, or file path for `Template::from_file()`
+  pub template_name: Option<String>,
+  /// source line of the offending expression, underlined
+  pub snippet: String,
+}
+
+impl ParseError {
+  fn new(template: &str, kind: ParseErrorKind, span: Span) -> ParseError {
+    ParseError {
+      kind: kind,
+      expected: None,
+      found: None,
+      span: span,
+      opened_at: None,
+      template_name: None,
+      snippet: span.snippet(template),
+    }
+  }
+
+  fn read_error(e: io::Error) -> ParseError {
+    ParseError::new("", ParseErrorKind::ReadError(::std::sync::Arc::new(e)), Span::new("", 0, 0))
+  }
+}
+
+impl ::std::fmt::Display for ParseError {
+  fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    if let Some(ref name) = self.template_name {
+      try!(write!(f, "{}, ", name));
+    }
+
+    let found = self.found.as_ref().map(|s| &s[..]).unwrap_or("");
+
+    // no source to locate anything in
+    if let ParseErrorKind::ReadError(ref e) = self.kind {
+      return write!(f, "could not read template: {}", e);
+    }
+
+    try!(write!(f, "{}: ", self.span));
+
+    try!(match self.kind {
+      ParseErrorKind::InvalidExpression => write!(f, "could not parse ‘{}’", found),
+      ParseErrorKind::UnexpectedBlockClose => write!(f, "‘{}’ does not close any block", found),
+      ParseErrorKind::UnmatchedBlock => {
+        let expected = self.expected.as_ref().map(|s| &s[..]).unwrap_or("");
+        match self.opened_at {
+          Some(ref opened_at) => write!(f, "‘{}’ does not match ‘{}’ opened at {}", found, expected, opened_at),
+          None => write!(f, "‘{}’ does not match ‘{}’", found, expected),
+        }
+      },
+      ParseErrorKind::UnclosedBlock => {
+        let expected = self.expected.as_ref().map(|s| &s[..]).unwrap_or("");
+        write!(f, "‘{}’ is not closed", expected)
+      },
+      ParseErrorKind::UnexpectedElse => write!(f, "‘{}’ is not in a block, or block already has an else", found),
+      ParseErrorKind::InvalidDelimiters => write!(f, "‘{}’ are not valid delimiters", found),
+      ParseErrorKind::ReadError(ref e) => write!(f, "could not read template: {}", e),
+      ParseErrorKind::UnkownError => write!(f, "unknown error"),
+    });
+
+    write!(f, "\n{}", self.snippet)
+  }
+}
+
+impl ::std::error::Error for ParseError {
+  fn description(&self) -> &str {
+    match self.kind {
+      ParseErrorKind::UnkownError => "unknown template parsing error",
+      ParseErrorKind::InvalidExpression => "invalid template expression",
+      ParseErrorKind::UnmatchedBlock => "unmatched template block",
+      ParseErrorKind::UnexpectedBlockClose => "unexpected template block close",
+      ParseErrorKind::UnclosedBlock => "unclosed template block",
+      ParseErrorKind::UnexpectedElse => "unexpected template else",
+      ParseErrorKind::InvalidDelimiters => "invalid template delimiters",
+      ParseErrorKind::ReadError(_) => "template could not be read",
+    }
+  }
+
+  // `cause()` defaults to it
+  fn source(&self) -> Option<&(::std::error::Error + 'static)> {
+    match self.kind {
+      ParseErrorKind::ReadError(ref e) => Some(&**e),
+      _ => None,
+    }
+  }
+}
+
+/// Location of a template fragment, mostly used to report parse errors.
+///
+/// Offsets are byte offsets in the parsed template, line and column
+/// numbers start at 1, columns are counted in chars.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Span {
+  /// span for the `start..end` byte range of `template`
+  pub fn new(template: &str, start: usize, end: usize) -> Span {
+    let start = ::std::cmp::min(start, template.len());
+    let line_start = template[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    Span {
+      start: start,
+      end: ::std::cmp::max(start, ::std::cmp::min(end, template.len())),
+      line: template[..start].matches('\n').count() + 1,
+      column: template[line_start..start].chars().count() + 1,
+    }
+  }
+
+  /// source line holding the span start, underlined with carets
+  ///
+  /// ```
+  /// use rumblebars::Span;
+  ///
+  /// let template = "hello\n{{#a}}{{/b}}";
+  /// assert_eq!(Span::new(template, 12, 18).snippet(template), "{{#a}}{{/b}}\n      ^^^^^^");
+  /// ```
+  pub fn snippet(&self, template: &str) -> String {
+    let line_start = template[..self.start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = template[self.start..].find('\n').map(|i| self.start + i).unwrap_or(template.len());
+    let underlined = template[self.start..::std::cmp::min(self.end, line_end)].chars().count();
+
+    let mut snippet = template[line_start..line_end].trim_right_matches('\r').to_string();
+    snippet.push('\n');
+    snippet.extend(::std::iter::repeat(' ').take(self.column - 1));
+    snippet.extend(::std::iter::repeat('^').take(::std::cmp::max(underlined, 1)));
+    snippet
+  }
 }
 
-lazy_static! {
-  static ref INDENT_MATCH: Regex = Regex::new("([:blank:]*)$").unwrap();
-  static ref END_WP_TRIMMER: Regex = Regex::new("(\r?\n)[:blank:]*(\\{\\{~?[#!/](?:\\}?[^}])*\\}\\})[:blank:]*(:?\r?\n)?\\z").unwrap();
-  static ref PARTIAL_END_WP_TRIMMER: Regex = Regex::new("(\r?\n[:blank:]*)(\\{\\{~?>(?:\\}?[^}])*\\}\\})[:blank:]*(:?\r?\n)?\\z").unwrap();
+// locates the spans of a template in order : lines and columns are counted from the
+// last located span rather than from the template start, spans located backwards excepted
+pub struct SpanCursor<'a> {
+  template: &'a str,
+  // last located offset, with its line and column
+  at: (usize, usize, usize),
+}
+
+impl<'a> SpanCursor<'a> {
+  pub fn new(template: &'a str) -> SpanCursor<'a> {
+    SpanCursor { template: template, at: (0, 1, 1) }
+  }
+
+  pub fn span(&mut self, start: usize, end: usize) -> Span {
+    let (offset, line, column) = self.at;
+    let start = ::std::cmp::min(start, self.template.len());
+    if start < offset {
+      return Span::new(self.template, start, end);
+    }
+
+    let passed = &self.template[offset..start];
+    let (line, column) = match passed.rfind('\n') {
+      Some(i) => (line + passed.matches('\n').count(), passed[i + 1..].chars().count() + 1),
+      None => (line, column + passed.chars().count()),
+    };
+    self.at = (start, line, column);
 
-  static ref TRIM_LEAD_SPACE_MATCHER: Regex = Regex::new("((?:[:blank:]|\r?\n)*)(\r?\n)[:blank:]*$").unwrap();
-  static ref TRIM_TRAIL_SPACE_MATCHER: Regex = Regex::new("^([:blank:]*\r?\n)(.*)").unwrap();
+    Span { start: start, end: ::std::cmp::max(start, ::std::cmp::min(end, self.template.len())), line: line, column: column }
+  }
 }
 
-fn parse_hb_expression(exp: &str) -> Result<HBExpressionParsing, (ParseError, Option<String>)> {
-  let mut lexer = HBExpressionLexer::new(BufReader::new(exp.as_bytes()));
-  let mut render_options = RenderOptions {
+impl ::std::fmt::Display for Span {
+  fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    write!(f, "line {}, column {}", self.line, self.column)
+  }
+}
+
+static WHITESPACE: &'static [char] = &[' ', '\t', '\r', '\n'];
+static BLANKS: &'static [char] = &[' ', '\t'];
+
+fn default_render_options() -> RenderOptions {
+  RenderOptions {
     escape: true,
     indent: None,
     no_leading_whitespace: false,
     no_trailing_whitespace: false,
-    inverse: false
-  };
+    inverse: false,
+    raw_block: false,
+    decorator: false,
+  }
+}
+
+// parses params and options up to the first token that is not part of them, returned along
+fn parse_hb_params<I: Iterator<Item=HBToken>>(lexer: &mut I) -> Result<(Vec<HBValHolder>, Vec<(String, HBValHolder)>, Option<HBToken>), ParseErrorKind> {
+  let mut params = vec![];
+  let mut options = vec![];
+  let mut param_path = vec![];
+  let mut pending = None;
+  let mut stop = None;
+
+  loop {
+    let tok = match pending.take().or_else(|| lexer.next()) {
+      Some(tok) => tok,
+      None => break,
+    };
+
+    match tok {
+      TokPathEntry(path_comp) => { param_path.push(path_comp) },
+      TokStringParam(s) => { params.push(HBValHolder::String(s)) },
+      TokLiteral(s) => { params.push(literal_value(s)) },
+      TokSubExpStart => { params.push(HBValHolder::SubExpression(try!(parse_hb_sub_expression(lexer)))) },
+      TokParamSep => {
+        if param_path.len() > 0 {
+          params.push(HBValHolder::Path(param_path));
+          param_path = vec![];
+        }
+      },
+      // options starts here
+      TokOption(opt) => {
+        let option_name = opt;
+        let mut opt_path = vec![];
+        let mut opt_val  = None;
+
+        // we have an option, get its value, token following a path value is handled as next token
+        while let Some(tok) = lexer.next() {
+          match tok {
+            TokPathEntry(s) => {
+              opt_path.push(s);
+            },
+            TokStringParam(s) => {
+              opt_val = Some(HBValHolder::String(s));
+              break;
+            },
+            TokLiteral(s) => {
+              opt_val = Some(literal_value(s));
+              break;
+            },
+            TokSubExpStart => {
+              opt_val = Some(HBValHolder::SubExpression(try!(parse_hb_sub_expression(lexer))));
+              break;
+            },
+            tok => {
+              pending = Some(tok);
+              break;
+            }
+          }
+        }
+
+        // option without its value (eg `{{x a=}}`)
+        if opt_val.is_none() && opt_path.is_empty() {
+          return Err(ParseErrorKind::InvalidExpression);
+        }
+
+        options.push((option_name, opt_val.unwrap_or(HBValHolder::Path(opt_path))));
+      },
+      tok => {
+        stop = Some(tok);
+        break;
+      }
+    }
+  }
+
+  if param_path.len() > 0 {
+    params.push(HBValHolder::Path(param_path));
+  }
+
+  Ok((params, options, stop))
+}
+
+// numbers, booleans, null and undefined, keeping their source
+pub fn literal_value(s: String) -> HBValHolder {
+  match &s[..] {
+    "undefined" => HBValHolder::Literal(Json::Null, s),
+    _ => match Json::from_str(&s) {
+      Ok(j) => HBValHolder::Literal(j, s),
+      Err(_) => HBValHolder::Path(vec![s]),
+    },
+  }
+}
+
+// parses a sub expression (helper call as parameter value), up to its closing parenthesis
+fn parse_hb_sub_expression<I: Iterator<Item=HBToken>>(lexer: &mut I) -> Result<HBExpression, ParseErrorKind> {
+  let mut path = vec![];
+  let mut params = vec![];
+  let mut options = vec![];
+  let mut closed = false;
+
+  while let Some(tok) = lexer.next() {
+    match tok {
+      TokPathEntry(path_comp) => { path.push(path_comp) },
+      TokParamStart => {
+        let (p, o, stop) = try!(parse_hb_params(lexer));
+        params = p;
+        options = o;
+        closed = match stop { Some(TokSubExpEnd) => true, _ => false };
+        break;
+      },
+      TokSubExpEnd => {
+        closed = true;
+        break;
+      },
+      _ => { break },
+    }
+  }
+
+  if !closed || path.is_empty() {
+    return Err(ParseErrorKind::InvalidExpression);
+  }
+
+  Ok(HBExpression {
+    base: path,
+    params: params,
+    options: options,
+    block_params: vec![],
+    render_options: default_render_options(),
+    block: None,
+    else_block: None
+  })
+}
+
+fn parse_hb_expression(exp: &str, delimiters: &Delimiters) -> Result<HBExpressionParsing, ParseErrorKind> {
+  parse_hb_any_expression(exp, delimiters, false)
+}
+
+// partial names can be computed at evaluation, from a sub expression (eg `{{> (name) context}}`)
+fn parse_hb_partial_expression(exp: &str, delimiters: &Delimiters) -> Result<HBExpressionParsing, ParseErrorKind> {
+  parse_hb_any_expression(exp, delimiters, true)
+}
+
+fn parse_hb_any_expression(exp: &str, delimiters: &Delimiters, partial: bool) -> Result<HBExpressionParsing, ParseErrorKind> {
+  let mut lexer = HBExpressionLexer::with_delimiters(exp, &delimiters.open, &delimiters.close);
+  let mut render_options = default_render_options();
   let mut leading_whitespace = None;
   let mut trailing_whitespace = None;
   let mut path = vec![];
   let mut params = vec![];
   let mut options = vec![];
+  let mut block_params = vec![];
+  let mut pending = None;
 
+  loop {
+    let tok = match pending.take().or_else(|| lexer.next()) {
+      Some(tok) => tok,
+      None => break,
+    };
 
-  while let Some(tok) = lexer.next() {
     match tok {
-      TokLeadingWhiteSpace(s) => {
-        render_options.indent = INDENT_MATCH.captures(&s).and_then(|s| s.at(1) ).map(|s| s.to_string());
-        leading_whitespace = Some(s);
-      },
+      TokLeadingWhiteSpace(s) => { leading_whitespace = Some(s) },
 
       TokNoWhiteSpaceBefore    => { render_options.no_leading_whitespace = true },
       TokNoWhiteSpaceAfter     => { render_options.no_trailing_whitespace = true },
@@ -381,69 +560,38 @@ fn parse_hb_expression(exp: &str) -> Result<HBExpressionParsing, (ParseError, Op
       TokPathEntry(path_comp)  => { path.push(path_comp) },
       TokStringParam(path_comp)  => { path.push(path_comp) },
 
-      TokParamStart => {
-        let mut param_path = vec![];
-        while let Some(tok) = lexer.next() {
-          match tok {
-            TokPathEntry(path_comp) => { param_path.push(path_comp) },
-            TokStringParam(s) => { params.push(HBValHolder::String(s)) },
-            TokParamSep => {
-              if param_path.len() > 0 {
-                params.push(HBValHolder::Path(param_path));
-                param_path = vec![];
-              }
-            },
-            // options starts here
-            TokOption(opt) => {
-              let option_name = opt;
-              let mut opt_path = vec![];
-              let mut opt_val  = None;
-
-              // we have an option, get its value and following options
-              while let Some(tok) = lexer.next() {
-                match tok {
-                  TokPathEntry(s) => {
-                    opt_path.push(s);
-                  },
-                  TokStringParam(s) => {
-                    opt_val = Some(s);
-                    break;
-                  },
-                  TokNoWhiteSpaceAfter => { render_options.no_trailing_whitespace = true },
-                  TokTrailingWhiteSpace(s) => { trailing_whitespace = Some(s) },
-                  _ => { break }
-                }
-              }
-
-              options.push((option_name, if let Some(val) = opt_val { HBValHolder::String(val) } else { HBValHolder::Path(opt_path) }));
-
-            },
-            TokNoWhiteSpaceAfter => { render_options.no_trailing_whitespace = true },
-            TokTrailingWhiteSpace(s) => { trailing_whitespace = Some(s) },
-            _ => { break; }
-          }
-        }
-        let literal_param = match param_path.first() {
-          Some(s) if param_path.len() == 1 => {
-            if let Ok(j) = Json::from_str(s) {
-              Some(HBValHolder::Literal(j, s.clone()))
-            } else {
-              None
-            }
-          },
-          Some(_) | None => None,
-        };
+      // computed name, stored as the first param in place of the base path
+      TokSubExpStart if partial && path.is_empty() && params.is_empty() => {
+        params.push(HBValHolder::SubExpression(try!(parse_hb_sub_expression(&mut lexer))));
+      },
+      TokSubExpStart => { return Err(ParseErrorKind::InvalidExpression) },
 
-        if let Some(p) = literal_param {
-          params.push(p);
-        } else if param_path.len() > 0 {
-          params.push(HBValHolder::Path(param_path));
+      TokParamStart => {
+        let (p, o, stop) = try!(parse_hb_params(&mut lexer));
+        params.extend(p);
+        options = o;
+        pending = stop;
+      },
+      TokBlockParams(s) => {
+        // names between pipes of `as |name other|`
+        let names = s.trim_right_matches('|').splitn(2, '|').nth(1).unwrap_or("");
+        block_params = names.split(|c: char| c.is_whitespace()).filter(|n| !n.is_empty()).map(|n| n.to_string()).collect();
+        if block_params.is_empty() {
+          return Err(ParseErrorKind::InvalidExpression);
         }
       },
+      // blanks between block params and the end of the tag
+      TokParamSep => (),
+      // closing parenthesis without sub expression
+      TokSubExpEnd => { return Err(ParseErrorKind::InvalidExpression) },
       _ => { break },
     }
   }
 
+  // unterminated string, that took the end of the tag
+  if lexer.in_string() {
+    return Err(ParseErrorKind::InvalidExpression);
+  }
 
   return  Ok((
     leading_whitespace,
@@ -451,6 +599,7 @@ fn parse_hb_expression(exp: &str) -> Result<HBExpressionParsing, (ParseError, Op
       base: path,
       params: params,
       options: options,
+      block_params: block_params,
       render_options: render_options,
       block: None,
       else_block: None
@@ -459,21 +608,54 @@ fn parse_hb_expression(exp: &str) -> Result<HBExpressionParsing, (ParseError, Op
   ))
 }
 
-// after handling parsed token, handle result and leading/trailing whitespace
+// what a tag does to the parse stack, once its surrounding whitespace is handled
 #[derive(Debug)]
 enum Unit {
-  AppendRaw(Box<HBEntry>),
-  Append(Option<String>, Box<HBEntry>, Option<String>),
-  AppendAutoTrim(Option<String>, Box<HBEntry>, Option<String>),
-  Shift(Option<String>,  Box<HBEntry>, bool, Option<String>),
-  Reduce(Option<String>, Box<HBEntry>, Option<String>),
-  TrimOnly(Option<String>, Box<HBEntry>, Option<String>),
+  Append(Box<HBEntry>),
+  Shift(Box<HBEntry>, Collector),
+  Reduce(Box<HBEntry>),
+  // comments only take part in whitespace control
+  Skip(Box<HBEntry>),
 }
 
+impl Unit {
+  // `~` marks, that remove all whitespace before and after the tag
+  fn whitespace_control(&self) -> (bool, bool) {
+    match *self {
+      Unit::Append(ref entry) | Unit::Shift(ref entry, _) | Unit::Reduce(ref entry) | Unit::Skip(ref entry) => match **entry {
+        HBEntry::Eval(ref exp) | HBEntry::Partial(ref exp) => (exp.render_options.no_leading_whitespace, exp.render_options.no_trailing_whitespace),
+        HBEntry::Raw(_) => (false, false),
+      },
+    }
+  }
+}
+
+// whether the last line of `text` only holds blanks, `blank` telling it for the line `text` starts on
+fn ends_blank_line(blank: bool, text: &str) -> bool {
+  match text.rfind('\n') {
+    Some(nl) => text[nl + 1..].chars().all(|c| BLANKS.contains(&c)),
+    None => blank && text.chars().all(|c| BLANKS.contains(&c)),
+  }
+}
+
+// parse stack collectors kinds
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Collector {
+  // block content, or template root
+  Block,
+  // else content of the block below
+  Else,
+  // block content of an `{{else helper}}` chained block, closed along with the block it's chained to
+  ChainedBlock,
+}
+
+// parse stack entry tuple: (template, collector kind, span of the expression that opened it)
+type ParseStack = Vec<(Box<Entries>, Collector, Span)>;
+
 // append entry to stack but if entry is raw data, append it to last raw entry
-fn append_entry(stack: &mut Vec<(Box<Entries>, bool)>, e: Box<HBEntry>) {
+fn append_entry(stack: &mut ParseStack, e: Box<HBEntry>) {
   let may_push_entry = match (stack.last_mut(), &*e) {
-    (Some(&mut(ref mut block, _)), &HBEntry::Raw(ref s)) => {
+    (Some(&mut(ref mut block, _, _)), &HBEntry::Raw(ref s)) => {
       if let Some(ref mut boxed) = (***block).last_mut() {
         if let HBEntry::Raw(ref mut existing) = ***boxed {
           existing.push_str(&s);
@@ -490,7 +672,7 @@ fn append_entry(stack: &mut Vec<(Box<Entries>, bool)>, e: Box<HBEntry>) {
 
   if may_push_entry {
     match stack.last_mut() {
-      Some(&mut (ref mut block, _)) => {
+      Some(&mut (ref mut block, _, _)) => {
         block.push(e)
       },
       _ => ()
@@ -508,6 +690,10 @@ fn append_entry(stack: &mut Vec<(Box<Entries>, bool)>, e: Box<HBEntry>) {
 /// unmatched blocks opening/closing, this class of errors comes with
 /// a short description.
 ///
+/// Errors are reported with a [`ParseError`](struct.ParseError.html),
+/// that tells the [`Span`](struct.Span.html) of the offending expression
+/// and displays the source line with the expression underlined.
+///
 /// # Examples
 ///
 /// Provided that you have continous integration with tests on your code,
@@ -524,308 +710,468 @@ fn append_entry(stack: &mut Vec<(Box<Entries>, bool)>, e: Box<HBEntry>) {
 /// ```
 
 pub fn parse(template: &str) -> ParseResult {
-  // trimming template handling with a regex, as rustlex does not emit tokens on input end,
-  // but it's very (very) convenient for this case
+  parse_with_delimiters(template, "{{", "}}")
+}
 
-  let trimmed = END_WP_TRIMMER.replace_all(&template,"$1$2");
-  let trimmed = PARTIAL_END_WP_TRIMMER.replace_all(&trimmed,"$1$2");
+/// Parses a template using `open` and `close` as default delimiters
+/// instead of `{{` and `}}`.
+///
+/// Delimiters can also be changed from the template itself, using mustache
+/// set delimiters tags (eg `{{=<% %>=}}`).
+///
+/// ```
+/// extern crate rustc_serialize as serialize;
+/// extern crate rumblebars;
+/// # fn main() {
+/// use serialize::json::Json;
+/// use rumblebars::parse_with_delimiters;
+///
+/// let data = Json::from_str(r##"{"name": "doc"}"##).unwrap();
+/// let template = parse_with_delimiters(r"\\section{<%name%>} <%={{ }}=%>{{name}}", "<%", "%>").unwrap();
+///
+/// assert_eq!(template.eval_to_string(&data).unwrap(), r"\\section{doc} doc");
+/// # }
+/// ```
+pub fn parse_with_delimiters(template: &str, open: &str, close: &str) -> ParseResult {
+  let (template, mut errors) = parse_template(template, (open, close), false);
 
-  let lexer = HandleBarsLexer::new(BufReader::new(trimmed.as_bytes()));
+  if errors.is_empty() {
+    Ok(template)
+  } else {
+    Err(errors.remove(0))
+  }
+}
 
-  // parse stack entry tuple: (template, is_else_block)
-  let mut stack = vec![(Box::new(vec![]) , false)];
+/// Parses a handlebars template, going on after errors.
+///
+/// Invalid expressions and closing expressions that do not close any block
+/// are skipped, blocks left open are closed where an enclosing block is closed
+/// or at template end. All errors are reported, in template order, along with
+/// the resulting best-effort template, so that they can all be fixed at once.
+///
+/// ```
+/// extern crate rustc_serialize as serialize;
+/// extern crate rumblebars;
+/// # fn main() {
+/// use serialize::json::Json;
+/// use rumblebars::{parse_recovering, ParseErrorKind};
+///
+/// let (template, errors) = parse_recovering("{{#each a}}{{/if}}{{b}}{{/each}}{{/with}}");
+///
+/// assert_eq!(errors.iter().map(|e| e.kind.clone()).collect::<Vec<_>>(),
+///            vec![ParseErrorKind::UnmatchedBlock, ParseErrorKind::UnexpectedBlockClose]);
+///
+/// let data = Json::from_str(r##"{"a": [{"b": 1}]}"##).unwrap();
+/// assert_eq!(template.eval_to_string(&data), Some("1".to_string()));
+/// # }
+/// ```
+pub fn parse_recovering(template: &str) -> (Template, Vec<ParseError>) {
+  parse_recovering_with_delimiters(template, "{{", "}}")
+}
 
+/// Same as [`parse_recovering()`](fn.parse_recovering.html), using `open` and `close`
+/// as default delimiters.
+pub fn parse_recovering_with_delimiters(template: &str, open: &str, close: &str) -> (Template, Vec<ParseError>) {
+  parse_template(template, (open, close), true)
+}
 
-  let mut previous_trail_whitespace: Option<(String, bool)> = None;
-  let mut first = true;
+// open blocks, innermost first, with the span of the expression that opened them
+fn open_blocks(stack: &ParseStack) -> Vec<(Vec<String>, Span)> {
+  let mut open = vec![];
 
-  for tok in lexer {
-    // handle each token specifities and distribute them to generic shift/reduce handlings
-    let token_result = match tok {
-      TokRaw(s) => {
-        Unit::AppendRaw(Box::new(HBEntry::Raw(s)))
-      },
-      TokSimpleExp(ref exp) => {
-        if let Ok((lead_wp, hb, trail_wp)) = parse_hb_expression(&exp) {
-          Unit::Append(lead_wp, Box::new(HBEntry::Eval(hb)), trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokCommentExp(ref exp) => {
-        if let Ok((lead_wp, hb, trail_wp)) = parse_hb_expression(&exp) {
-          Unit::TrimOnly(lead_wp, Box::new(HBEntry::Eval(hb)), trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokNoEscapeExp(ref exp) => {
-        if let Ok((lead_wp, mut hb, trail_wp)) = parse_hb_expression(&exp) {
-          hb.render_options.escape = false;
-          Unit::Append(lead_wp, Box::new(HBEntry::Eval(hb)), trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokPartialExp(ref exp) => {
-        if let Ok((lead_wp, hb, trail_wp)) = parse_hb_expression(&exp) {
-          Unit::AppendAutoTrim(lead_wp, Box::new(HBEntry::Partial(hb)), trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokBlockExp(ref exp, inverse) => {
-        if let Ok((lead_wp, mut hb, trail_wp)) = parse_hb_expression(&exp) {
-          hb.render_options.inverse = inverse;
-          Unit::Shift(lead_wp, Box::new(HBEntry::Eval(hb)), false, trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokBlockElseCond(ref exp) => {
-        if let Ok((lead_wp, hb, trail_wp)) = parse_hb_expression(&exp) {
-          Unit::Shift(lead_wp, Box::new(HBEntry::Eval(hb)), true, trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
-      },
-      TokBlockEndExp(ref exp) => {
-        if let Ok((lead_wp, hb, trail_wp)) = parse_hb_expression(&exp) {
-          Unit::Reduce(lead_wp, Box::new(HBEntry::Eval(hb)), trail_wp)
-        } else {
-          return Result::Err((ParseError::InvalidExpression, Some(format!("Could not parse {:?}", exp))));
-        }
+  for index in (1..stack.len()).rev() {
+    if let (&(_, Collector::Block, span), &(ref parents, _, _)) = (&stack[index], &stack[index - 1]) {
+      match parents.last().map(|e| &**e) {
+        Some(&HBEntry::Eval(ref parent)) | Some(&HBEntry::Partial(ref parent)) => open.push((parent.base.clone(), span)),
+        _ => (),
       }
-    };
+    }
+  }
 
-    match token_result {
-      // direct append without trimming
-      Unit::AppendRaw(entry) => {
-        match previous_trail_whitespace {
-          Some((s, true)) => append_entry(&mut stack, Box::new(HBEntry::Raw(s))),
-          _ => ()
-        };
+  open
+}
 
-        previous_trail_whitespace = None;
-        append_entry(&mut stack, entry);
-      },
-      // direct append with explicit trimming
-      Unit::Append(lead_wp, entry, trail_wp) => {
-        let (remove_lead_wp, remove_trail_wp) = match *entry {
-          HBEntry::Eval(ref exp) => (exp.render_options.no_leading_whitespace, exp.render_options.no_trailing_whitespace),
-          HBEntry::Partial(ref exp) => (exp.render_options.no_leading_whitespace, exp.render_options.no_trailing_whitespace),
-          _ => (false, false),
-        };
-
-        // if we have previous trail, it's our current leading, so skip if we trim lead
-        match (previous_trail_whitespace, remove_lead_wp) {
-          (Some((s, true)), false) => append_entry(&mut stack, Box::new(HBEntry::Raw(s))),
-          _ => ()
-        };
-
-        previous_trail_whitespace = trail_wp.map(|s| (s, !remove_trail_wp) );
-
-        match (lead_wp, remove_lead_wp) {
-          (Some(space), false) => append_entry(&mut stack, Box::new(HBEntry::Raw(space))),
-          _ => ()
+// reduce innermost open block : its content (and else content) is attached to the block expression,
+// blocks chained to it are reduced first
+fn reduce_block(stack: &mut ParseStack) {
+  loop {
+    let has_else = match stack.last() { Some(&(_, Collector::Else, _)) => true, _ => false };
+    let else_block = if has_else { stack.pop() } else { None };
+    let (block, collector) = match stack.pop() {
+      Some((block, collector, _)) => (block, collector),
+      None => break,
+    };
+
+    if let Some(&mut (ref mut parents, _, _)) = stack.last_mut() {
+      if let Some(boxed_parent) = parents.last_mut() {
+        match **boxed_parent {
+          HBEntry::Eval(ref mut parent) | HBEntry::Partial(ref mut parent) => {
+            parent.block = Some(block);
+            parent.else_block = else_block.map(|(else_block, _, _)| else_block);
+          },
+          _ => (),
         }
+      }
+    }
+
+    if collector != Collector::ChainedBlock {
+      break;
+    }
+  }
+}
+
+// `{{{{name params}}}}` raw block delimiters are parsed as `{{name params}}` expressions
+fn unwrap_raw_block(exp: &str, delimiters: &Delimiters) -> String {
+  let open = exp.find(&delimiters.open[..]).map(|i| i + delimiters.open.len()).unwrap_or(0);
+  let close = exp.rfind(&delimiters.close[..]).unwrap_or(exp.len());
+  let close = ::std::cmp::max(open + 2, close.saturating_sub(2));
+  format!("{}{}{}", &exp[..open], &exp[open + 2..close], &exp[close + 2..])
+}
+
+// decorators are expressions marked with a `*` (eg `{{#*inline "name"}}`), parsed as regular expressions once unmarked
+fn unmark_decorator(exp: &str, delimiters: &Delimiters) -> Option<String> {
+  let start = exp.find(&delimiters.open[..]).map(|i| i + delimiters.open.len()).unwrap_or(0);
+
+  match exp[start..].find(|c: char| c != '~' && c != '#').map(|i| start + i) {
+    Some(marker) if exp[marker..].starts_with('*') => Some(format!("{}{}", &exp[..marker], &exp[marker + 1..])),
+    _ => None,
+  }
+}
+
+// partial blocks (eg `{{#> layout}}`) are parsed as partials once unmarked
+fn unmark_partial_block(exp: &str, delimiters: &Delimiters) -> Option<String> {
+  let start = exp.find(&delimiters.open[..]).map(|i| i + delimiters.open.len()).unwrap_or(0);
+
+  match exp[start..].find('#').map(|i| start + i) {
+    Some(mark) if exp[start..mark].chars().all(|c| c == '~') && exp[mark + 1..].starts_with('>') => {
+      Some(format!("{}{}", &exp[..mark], &exp[mark + 1..]))
+    },
+    _ => None,
+  }
+}
+
+// else expressions are only valid in an open block, that has no else yet
+fn can_shift_else(stack: &ParseStack) -> bool {
+  match stack.last() {
+    Some(&(_, Collector::Else, _)) => false,
+    _ => !open_blocks(stack).is_empty(),
+  }
+}
 
-        append_entry(&mut stack, entry);
+// expression of a tag token lexed with `delimiters`, as it is parsed in templates : along with its
+// surrounding whitespace, and as an `HBEntry::Partial` for partials and partial blocks
+pub fn parse_tag(tok: &Token, delimiters: &Delimiters) -> Result<(Option<String>, Box<HBEntry>, Option<String>), ParseErrorKind> {
+  let (lead_wp, entry, trail_wp) = match *tok {
+    TokSimpleExp(ref exp) => {
+      let decorator = unmark_decorator(&exp, delimiters);
+      let (lead_wp, mut hb, trail_wp) = try!(parse_hb_expression(decorator.as_ref().map(|e| &e[..]).unwrap_or(exp), delimiters));
+      hb.render_options.decorator = decorator.is_some();
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    TokNoEscapeExp(ref exp) => {
+      let (lead_wp, mut hb, trail_wp) = try!(parse_hb_expression(&exp, delimiters));
+      hb.render_options.escape = false;
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    TokPartialExp(ref exp) => {
+      let (lead_wp, hb, trail_wp) = try!(parse_hb_partial_expression(&exp, delimiters));
+      (lead_wp, HBEntry::Partial(hb), trail_wp)
+    },
+    TokBlockExp(ref exp, inverse) => {
+      let decorator = unmark_decorator(&exp, delimiters);
+      let partial_block = unmark_partial_block(&exp, delimiters);
+      let unmarked = decorator.as_ref().or(partial_block.as_ref()).map(|e| &e[..]).unwrap_or(exp);
+
+      let (lead_wp, mut hb, trail_wp) = try!(if partial_block.is_some() { parse_hb_partial_expression(unmarked, delimiters) } else { parse_hb_expression(unmarked, delimiters) });
+      hb.render_options.inverse = inverse;
+      hb.render_options.decorator = decorator.is_some();
+      (lead_wp, if partial_block.is_some() { HBEntry::Partial(hb) } else { HBEntry::Eval(hb) }, trail_wp)
+    },
+    TokBlockElseChain(ref exp) => {
+      // `{{else helper params}}` is parsed as `{{helper params}}` block chained in else
+      let keyword_at = exp.find("else").unwrap_or(0);
+      let (lead_wp, hb, trail_wp) = try!(parse_hb_expression(&format!("{}{}", &exp[..keyword_at], &exp[keyword_at + 4..]), delimiters));
+      // chained helper without its condition
+      if hb.params.is_empty() {
+        return Err(ParseErrorKind::InvalidExpression);
+      }
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    TokCommentExp(ref exp) | TokBlockElseCond(ref exp) | TokBlockEndExp(ref exp) => {
+      let (lead_wp, hb, trail_wp) = try!(parse_hb_expression(&exp, delimiters));
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    // set delimiters tags are parsed as an empty comment, so that they get trimmed like one
+    TokSetDelimiters(ref exp) => {
+      let lead = exp.len() - exp.trim_left_matches(WHITESPACE).len();
+      let trail = exp.trim_right_matches(WHITESPACE).len();
+      let comment = format!("{}{}!{}{}", &exp[..lead], delimiters.open, delimiters.close, &exp[trail..]);
+      let (lead_wp, hb, trail_wp) = try!(parse_hb_expression(&comment, delimiters));
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    TokRawBlockStart(ref exp) | TokRawBlockEnd(ref exp) => {
+      let (lead_wp, mut hb, trail_wp) = try!(parse_hb_expression(&unwrap_raw_block(&exp, delimiters), delimiters));
+      hb.render_options.raw_block = true;
+      (lead_wp, HBEntry::Eval(hb), trail_wp)
+    },
+    // text has no expression
+    TokRaw(_) | TokEscaped(_) => return Err(ParseErrorKind::InvalidExpression),
+  };
+
+  // block params are only declared by helper blocks opening tags
+  match (tok, &entry) {
+    (&TokBlockExp(..), &HBEntry::Eval(_)) | (&TokBlockElseChain(_), _) => (),
+    (_, &HBEntry::Eval(ref hb)) | (_, &HBEntry::Partial(ref hb)) if !hb.block_params.is_empty() => return Err(ParseErrorKind::InvalidExpression),
+    _ => (),
+  }
+
+  Ok((lead_wp, Box::new(entry), trail_wp))
+}
+
+fn parse_template(source: &str, delimiters: (&str, &str), recover: bool) -> (Template, Vec<ParseError>) {
+  let mut errors = vec![];
+  let mut cursor = SpanCursor::new(source);
+
+  // invalid default delimiters are reported at template start, and replaced by `{{ }}`
+  let mut delimiters = match Delimiters::new(delimiters.0, delimiters.1) {
+    Some(delimiters) => delimiters,
+    None => {
+      errors.push(ParseError {
+        found: Some(format!("{} {}", delimiters.0, delimiters.1)),
+        ..ParseError::new(source, ParseErrorKind::InvalidDelimiters, cursor.span(0, 0))
+      });
+      Delimiters::default()
+    },
+  };
+
+  let mut lexer = HandleBarsLexer::with_delimiters(source, delimiters.clone()).spanned().peekable();
+
+  let mut stack: ParseStack = vec![(Box::new(vec![]) , Collector::Block, cursor.span(0, 0))];
+
+  // whitespace control is done in a single pass : text is held until the next tag, that
+  // may trim its end, and the current line is tracked to tell which tags are standalone
+  let mut text = String::new();
+  let mut blank_line = true;
+
+  // report error, and skip offending token when recovering
+  macro_rules! fail {
+    ($error: expr) => ({
+      errors.push($error);
+      if !recover { break }
+      continue
+    })
+  }
+
+  while let Some((tok, (start, end))) = lexer.next() {
+    let tok_span = {
+      let source = tok.source();
+
+      // expressions tokens hold their surrounding whitespace, leave it out of span
+      let (lead, trail) = match tok {
+        TokRaw(_) | TokEscaped(_) => (0, 0),
+        _ => (
+          source.len() - source.trim_left_matches(WHITESPACE).len(),
+          source.len() - source.trim_right_matches(WHITESPACE).len()
+        ),
+      };
+      cursor.span(start + lead, end - trail)
+    };
 
+    // handle each token specifities, along with their surrounding whitespace and
+    // whether they are removed with their line when standalone
+    match tok {
+      TokRaw(s) => {
+        blank_line = ends_blank_line(blank_line, &s);
+        text.push_str(&s);
+        continue;
       },
-      // shift or reduce with auto trim
-      autotrimable @ Unit::Shift(..) |
-      autotrimable @ Unit::Reduce(..) |
-      autotrimable @ Unit::TrimOnly(..) |
-      autotrimable @ Unit::AppendAutoTrim(..) => {
-        let (shift, reduce, append, lead_wp, entry, is_else, trail_wp) = match autotrimable {
-          Unit::Shift(lead_wp, entry, is_else, trail_wp)   => (true, false, false, lead_wp, entry, is_else, trail_wp),
-          Unit::Reduce(lead_wp, entry, trail_wp)           => (false, true, false,  lead_wp, entry, false, trail_wp),
-          Unit::AppendAutoTrim(lead_wp, entry, trail_wp)   => (false, false, true,  lead_wp, entry, false, trail_wp),
-          Unit::TrimOnly(lead_wp, entry, trail_wp)         => (false, false, false,  lead_wp, entry, false, trail_wp),
-          _ => panic!("rustc did compile some weird case"),
-        };
-
-        // extract whitespace options
-        let (remove_lead_wp, remove_trail_wp) = match *entry {
-          HBEntry::Eval(ref exp) => (exp.render_options.no_leading_whitespace, exp.render_options.no_trailing_whitespace),
-          HBEntry::Partial(ref exp) => (exp.render_options.no_leading_whitespace, exp.render_options.no_trailing_whitespace),
-          _ => (false, false),
-        };
-
-        //
-        // calculate autotrimming
-        //   note that end of input trailing space is handled by a prelude replace regex
-        //
-
-        // we use owned leading whitespace, or use previous elligible trailing whitespace
-        // or fallback to a default
-        let lead_space_with_fallbacks = lead_wp.clone()
-          .map(|wp| (wp, true))
-          .or(previous_trail_whitespace.clone().map(|(wp, can_be_used)| (wp, can_be_used)))
-          .or(Some(("".to_string(), false)));
-
-        let (trimmed, trail_match, trail_keep) = match (lead_space_with_fallbacks, trail_wp.clone()) {
-          (Some((ref lead_space, owned_space)), Some(ref trail_space)) => {
-
-            // lead space : split in kept wp and new line that replaces trimmed space
-            //   fallback to neutral values if first encountered exp is trimmable
-            let lead_matches  = if first {
-              // first token, make lead space elligible to trimming, but empty
-              Some((Some(""), Some("")))
-            } else {
-              // check against auto trim leading space rules
-              TRIM_LEAD_SPACE_MATCHER.captures(&lead_space).and_then(|s| Some((s.at(1), s.at(2))))
-            };
-
-            // check trailing whitespace against auto trim trailing space rules
-            let trail_matches = TRIM_TRAIL_SPACE_MATCHER.captures(&trail_space)
-              .and_then(|s| Some((s.at(1).map(|p| p.to_string()), s.at(2).map(|p| p.to_string()))));
-
-            // check matches, is both are ok, go on trimming
-            match (lead_matches, trail_matches) {
-              (Some((Some(lead_keep), Some(lead_nl_match))), Some((trail_m, trail_k))) => {
-                let mut to_insert = String::new();
-                if owned_space && lead_keep.len() > 0 {
-                  // only insert owned whitespace
-                  to_insert.push_str(lead_keep);
-                }
-                if owned_space && lead_nl_match.len() > 0 {
-                  // insert autotrim newline when whitespace is owned and expression  candidate to autriming
-                  to_insert.push_str(lead_nl_match);
-                }
-
-                if to_insert.len() > 0 && !remove_lead_wp {
-                  append_entry(&mut stack, Box::new(HBEntry::Raw(to_insert)));
-                }
-
-                (true, trail_m, trail_k)
-              }
-              _ => (false, None, trail_wp),
-            }
-          },
-          _ => (false, None, trail_wp),
-        };
+      TokEscaped(s) => {
+        // drop escaping backslash
+        blank_line = ends_blank_line(blank_line, &s[1..]);
+        text.push_str(&s[1..]);
+        continue;
+      },
+      TokBlockElseCond(_) | TokBlockElseChain(_) if !can_shift_else(&stack) => {
+        fail!(ParseError { found: Some(tok.source().trim().to_string()), ..ParseError::new(source, ParseErrorKind::UnexpectedElse, tok_span) });
+      },
+      _ => (),
+    }
 
+    let (lead_wp, entry, trail_wp) = match parse_tag(&tok, &delimiters) {
+      Ok(parsed) => parsed,
+      Err(_) => fail!(ParseError { found: Some(tok.source().trim().to_string()), ..ParseError::new(source, ParseErrorKind::InvalidExpression, tok_span) }),
+    };
 
-        // if there is not autotrim nor explicit trimming, push leading whitespace, that might come from previous trailing
-        let usable_previous = previous_trail_whitespace.and_then(|(s, can_use)| if can_use { Some(s) } else { None });
+    // following tags use the new delimiters, as the lexer does
+    if let TokSetDelimiters(ref exp) = tok {
+      match delimiters.set_by(exp) {
+        Ok(new_delimiters) => delimiters = new_delimiters,
+        Err(found) => {
+          errors.push(ParseError { found: Some(found), ..ParseError::new(source, ParseErrorKind::InvalidDelimiters, tok_span) });
+          if !recover { break }
+        },
+      }
+    }
 
-        if let (false, false, Some(space)) = (trimmed, remove_lead_wp, lead_wp.or(usable_previous)) {
-          append_entry(&mut stack, Box::new(HBEntry::Raw(space)));
-        }
+    let decorator = match *entry { HBEntry::Eval(ref hb) => hb.render_options.decorator, _ => false };
+    let (lead_wp, mut unit, trail_wp, may_stand_alone) = match tok {
+      // decorators render nothing, and are standalone like partials
+      TokSimpleExp(_) => (lead_wp, Unit::Append(entry), trail_wp, decorator),
+      TokCommentExp(_) | TokSetDelimiters(_) => (lead_wp, Unit::Skip(entry), trail_wp, true),
+      TokNoEscapeExp(_) => (lead_wp, Unit::Append(entry), trail_wp, false),
+      TokPartialExp(_) => (lead_wp, Unit::Append(entry), trail_wp, true),
+      TokBlockExp(..) => (lead_wp, Unit::Shift(entry, Collector::Block), trail_wp, true),
+      TokBlockElseCond(_) => (lead_wp, Unit::Shift(entry, Collector::Else), trail_wp, true),
+      TokBlockElseChain(_) => (lead_wp, Unit::Shift(entry, Collector::ChainedBlock), trail_wp, true),
+      TokBlockEndExp(_) => (lead_wp, Unit::Reduce(entry), trail_wp, true),
+      // raw blocks are never trimmed, leading whitespace is kept as is
+      TokRawBlockStart(_) => (lead_wp, Unit::Shift(entry, Collector::Block), None, false),
+      TokRawBlockEnd(_) => (None, Unit::Reduce(entry), None, false),
+      TokRaw(_) | TokEscaped(_) => continue,
+    };
 
-        // keep elligible trailing whitespace for next expression auto trimming check
-        previous_trail_whitespace = trail_keep.clone().and_then(|k| {if k == "" { None } else { Some((k, !remove_trail_wp)) }} ).or(trail_match.clone().map(|s| (s, false)));
+    let lead_wp = lead_wp.unwrap_or(String::new());
+    let trail_wp = trail_wp.unwrap_or(String::new());
+    let (remove_lead_wp, remove_trail_wp) = unit.whitespace_control();
+
+    blank_line = ends_blank_line(blank_line, &lead_wp);
+    text.push_str(&lead_wp);
+
+    // standalone tags are alone on their line, that is removed along with them
+    let standalone = may_stand_alone && blank_line && (trail_wp.contains('\n') || lexer.peek().is_none());
+
+    // text before the tag is trimmed, standalone partials are indented with the blanks removed before them
+    if remove_lead_wp {
+      let kept = text.trim_right_matches(WHITESPACE).len();
+      text.truncate(kept);
+    } else if standalone {
+      let kept = text.trim_right_matches(BLANKS).len();
+      if let Unit::Append(ref mut entry) = unit {
+        if let HBEntry::Partial(ref mut exp) = **entry {
+          exp.render_options.indent = if kept < text.len() { Some(text[kept..].to_string()) } else { None };
+        }
+      }
+      text.truncate(kept);
+    }
 
-        if shift || append {
-          // first, just handle partial trimming specific handling for indentation
-          if trimmed && entry.is_partial() {
-            match *entry {
-              HBEntry::Partial(HBExpression {render_options: RenderOptions {indent: Some(ref s), ..}, ..}) => append_entry(&mut stack, Box::new(HBEntry::Raw(s.clone()))),
-              _ => ()
-            }
-          }
+    if !text.is_empty() {
+      append_entry(&mut stack, Box::new(HBEntry::Raw(::std::mem::replace(&mut text, String::new()))));
+    }
 
-          // append, push entry into current collector
-          if ! is_else {
-            append_entry(&mut stack, entry);
-          }
+    // text after the tag is held for the next one, a standalone tag removes its line ending
+    let trail_kept = if remove_trail_wp {
+      ""
+    } else if standalone {
+      &trail_wp[trail_wp.find('\n').map(|nl| nl + 1).unwrap_or(trail_wp.len())..]
+    } else {
+      &trail_wp[..]
+    };
+    text.push_str(trail_kept);
+    blank_line = ends_blank_line(false, &trail_wp);
+
+    match unit {
+      Unit::Skip(_) => (),
+      Unit::Append(entry) => append_entry(&mut stack, entry),
+      Unit::Shift(entry, collector) => {
+        // chained block is the content of an else collector
+        if collector == Collector::ChainedBlock {
+          stack.push((Box::new(vec![]), Collector::Else, tok_span));
+        }
 
-          if shift {
-            // compilation shifting : entry was pushed, and a new collector is inserted
-            stack.push((Box::new(vec![]), is_else));
-          }
+        // else only switches collectors
+        if collector != Collector::Else {
+          append_entry(&mut stack, entry);
+        }
 
-        } else if reduce {
-          // reducing : inspect stack and reduce last elligible token collectors into their parent
-          // (remove entries from stack and attach them to their parent )
-
-          // check if it's a signle block or a block/else reduction
-          let has_else = match stack.last() { Some(&(_, true)) => true, _ => false };
-
-          // pop reduced content from stack
-          let pop = if has_else {
-            (stack.pop(), stack.pop())
-          } else {
-            (None, stack.pop())
-          };
-
-          // attach content to parent
-
-          if let Some(&mut (ref mut parents, _)) = stack.last_mut() {
-            if let HBEntry::Eval(ref hb) = *entry {
-              match (***parents).last_mut() {
-                Some(ref mut boxed_parent) => {
-                  if let HBEntry::Eval(ref mut parent) = ***boxed_parent {
-                    if parent.base == hb.base {
-                      match pop {
-                        (some_else, Some((block, _))) => {
-                          parent.block = Some(block);
-                          if let Some((else_block, _)) = some_else {
-                            parent.else_block = Some(else_block);
-                          }
-                        },
-                        _ => panic!("(some_else, Some((block, _))) pattern should always be matched — parse.rs#parse")
-                      }
-
-                    } else {
-                      return Err((
-                        ParseError::UnmatchedBlock,
-                        Some(format!("‘{}’ does not match ‘{}’", hb.path(), parent.path()))
-                      ));
-                    }
-                  } else {
-                    return Err((
-                      ParseError::UnexpectedBlockClose,
-                      Some(format!("‘{}’ does not close any block", hb.path()))
-                    ));
-                  }
-                }
-                _ => {
-                  return Err((
-                    ParseError::UnexpectedBlockClose,
-                    Some(format!("‘{}’ does not close any block", hb.path()))
-                  ));
-                }
+        // compilation shifting : a new collector gathers the block content
+        stack.push((Box::new(vec![]), collector, tok_span));
+      },
+      Unit::Reduce(entry) => {
+        // reducing : inspect stack and reduce last elligible token collectors into their parent
+        // (remove entries from stack and attach them to their parent )
+        let closing = match *entry { HBEntry::Eval(ref hb) => hb.base.clone(), _ => vec![] };
+        let open = open_blocks(&stack);
+
+        // partial names are literal (eg `{{#> layouts/main}}`), while closing expressions are lexed as paths
+        match open.iter().position(|&(ref base, _)| *base == closing || base.join("/") == closing.join("/")) {
+          // closing innermost block, the regular case
+          Some(0) => reduce_block(&mut stack),
+          // nothing to close
+          None if open.is_empty() => {
+            fail!(ParseError {
+              found: Some(closing.join(".")),
+              ..ParseError::new(source, ParseErrorKind::UnexpectedBlockClose, tok_span)
+            });
+          },
+          // closing some other block : recover by ignoring closing expression if no block matches,
+          // or closing blocks left open if one matches
+          depth => {
+            let (ref expected, opened_at) = open[0];
+            errors.push(ParseError {
+              expected: Some(expected.join(".")),
+              found: Some(closing.join(".")),
+              opened_at: Some(opened_at),
+              ..ParseError::new(source, ParseErrorKind::UnmatchedBlock, tok_span)
+            });
+            if !recover { break }
+
+            if let Some(depth) = depth {
+              for &(ref base, opened_at) in open[1..depth].iter() {
+                errors.push(ParseError {
+                  expected: Some(base.join(".")),
+                  opened_at: Some(opened_at),
+                  ..ParseError::new(source, ParseErrorKind::UnclosedBlock, opened_at)
+                });
+              }
+              for _ in 0..depth + 1 {
+                reduce_block(&mut stack);
               }
-            } else {
-              panic!("Should not reach: there's a bug in handelbars template@ parser, we're doing a block reduce on invalid parsing state");
             }
-          } else {
-            panic!("Should not reach: there's a bug in handelbars template@ parser, we're doing a block reduce on invalid parsing state");
-          }
-
+          },
         }
       },
     }
-
-    // not first token anymore
-    first = false;
   }
 
-  match previous_trail_whitespace {
-    Some((ref s, true)) =>  {
-      append_entry(&mut stack, Box::new(HBEntry::Raw(s.clone())))
-    },
-    _ => ()
-  };
+  if !text.is_empty() {
+    append_entry(&mut stack, Box::new(HBEntry::Raw(text)));
+  }
 
-  if stack.len() > 0 {
-    Result::Ok(Template { entries: *stack.remove(0).0 })
-  } else {
-    Result::Err((ParseError::UnkownError, None))
+  // blocks left open at template end
+  for (base, opened_at) in open_blocks(&stack) {
+    errors.push(ParseError {
+      expected: Some(base.join(".")),
+      opened_at: Some(opened_at),
+      ..ParseError::new(source, ParseErrorKind::UnclosedBlock, opened_at)
+    });
+    reduce_block(&mut stack);
   }
+
+  (Template { entries: *stack.remove(0).0 }, errors)
+}
+
+/// Same as [`parse()`](fn.parse.html), `name` is reported in parse errors.
+pub fn parse_with_name(template: &str, name: &str) -> ParseResult {
+  parse_with_name_and_delimiters(template, name, "{{", "}}")
+}
+
+/// Same as [`parse_with_delimiters()`](fn.parse_with_delimiters.html), `name` is reported in parse errors.
+pub fn parse_with_name_and_delimiters(template: &str, name: &str, open: &str, close: &str) -> ParseResult {
+  parse_with_delimiters(template, open, close).map_err(|mut e| {
+    e.template_name = Some(name.to_string());
+    e
+  })
 }
 
 #[cfg(test)]
 mod tests {
   use std::default::Default;
   use super::{parse, parse_hb_expression, HBEntry, HBExpression, HBValHolder, Template};
+  use lexer::Delimiters;
 
   #[test]
   fn hb_simple() {
-    assert!(match parse_hb_expression("{{i}}") {
+    assert!(match parse_hb_expression("{{i}}", &Delimiters::default()) {
       Ok(_)  => true,
       Err(_) => false,
     })
@@ -833,7 +1179,7 @@ mod tests {
 
   #[test]
   fn hb_simple_base() {
-    match parse_hb_expression("{{i}}") {
+    match parse_hb_expression("{{i}}", &Delimiters::default()) {
       Ok((_, ok, _))  => assert_eq!(ok.base, vec!["i"]),
       Err(_)  => (),
     }
@@ -841,7 +1187,7 @@ mod tests {
 
   #[test]
   fn hb_simple_base_path() {
-    match parse_hb_expression("{{i.j}}") {
+    match parse_hb_expression("{{i.j}}", &Delimiters::default()) {
       Ok((_, ok, _))  => assert_eq!(ok.base, vec!["i", "j"]),
       Err(_)  => (),
     }
@@ -849,7 +1195,7 @@ mod tests {
 
   #[test]
   fn hb_simple_base_esc_path() {
-    match parse_hb_expression("{{[i]}}") {
+    match parse_hb_expression("{{[i]}}", &Delimiters::default()) {
       Ok((_, ok, _))  => assert_eq!(ok.base, vec!["i"]),
       Err(_)  => (),
     }
@@ -857,7 +1203,7 @@ mod tests {
 
   #[test]
   fn hb_simple_this_path() {
-    match parse_hb_expression("{{.}}") {
+    match parse_hb_expression("{{.}}", &Delimiters::default()) {
       Ok((_, ok, _))  => assert_eq!(ok.base, vec!["."]),
       Err(_)  => (),
     }
@@ -865,7 +1211,7 @@ mod tests {
 
   #[test]
   fn hb_this_path() {
-    match parse_hb_expression("{{./p}}") {
+    match parse_hb_expression("{{./p}}", &Delimiters::default()) {
       Ok((_, ok, _))  => assert_eq!(ok.base, vec![".", "p"]),
       Err(_)  => (),
     }
@@ -874,8 +1220,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_string_param() {
-    match parse_hb_expression(r##"{{p "string"}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{p "string"}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["p"]);
         assert_eq!(match params.get(0).unwrap() { &HBValHolder::String(ref s) => s.clone(), _ => "".to_string()}, "string".to_string());
       },
@@ -886,8 +1232,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_prop_path_param() {
-    match parse_hb_expression(r##"{{p some.path}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{p some.path}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["p"]);
         assert_eq!(match params.get(0).unwrap() { &HBValHolder::Path(ref p) => p.clone(), _ => vec![]}, vec!["some", "path"]);
       },
@@ -898,8 +1244,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_2_params() {
-    match parse_hb_expression(r##"{{p some path}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{p some path}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["p"]);
         assert_eq!(match params.get(0).unwrap() { &HBValHolder::Path(ref p) => p.clone(), _ => vec![]}, vec!["some"]);
         assert_eq!(match params.get(1).unwrap() { &HBValHolder::Path(ref p) => p.clone(), _ => vec![]}, vec!["path"]);
@@ -911,8 +1257,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_3_params() {
-    match parse_hb_expression(r##"{{p some.path "with_string" yep}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{p some.path "with_string" yep}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["p"]);
         assert_eq!(match params.get(0).unwrap() { &HBValHolder::Path(ref p) => p.clone(), _ => vec![]}, vec!["some", "path"]);
         assert_eq!(match params.get(1).unwrap() { &HBValHolder::String(ref s) => s.clone(), _ => "".to_string()}, "with_string".to_string());
@@ -925,8 +1271,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_full_feat_param() {
-    match parse_hb_expression(r##"{{t "… param1" well.[that my baby].[1] ~}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{t "… param1" well.[that my baby].[1] ~}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["t"]);
         assert_eq!(match params.get(0).unwrap() { &HBValHolder::String(ref s) => s.clone(), _ => "".to_string()}, "… param1".to_string());
         assert_eq!(match params.get(1).unwrap() { &HBValHolder::Path(ref p) => p.clone(), _ => vec![]}, vec!["well", "that my baby", "1"]);
@@ -939,8 +1285,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_option() {
-    match parse_hb_expression(r##"{{t opt=u ~}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{t opt=u ~}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["t"]);
         assert_eq!(("opt".to_string(), vec!["u".to_string()]), match options.get(0).unwrap() {
           &(ref o, HBValHolder::Path(ref p)) => (o.clone(), p.clone()),
@@ -955,8 +1301,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_mutli_options() {
-    match parse_hb_expression(r##"{{t opt=u opt2="v" ~}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{t opt=u opt2="v" ~}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["t"]);
         assert_eq!(("opt".to_string(), vec!["u".to_string()]), match options.get(0).unwrap() {
           &(ref o, HBValHolder::Path(ref p)) => (o.clone(), p.clone()),
@@ -975,8 +1321,8 @@ mod tests {
   #[allow(unused_variables)]
   #[test]
   fn hb_param_options() {
-    match parse_hb_expression(r##"{{t o.[t}+=] opt="v" ~}}"##) {
-      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block}, _))  => {
+    match parse_hb_expression(r##"{{t o.[t}+=] opt="v" ~}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}, _))  => {
         assert_eq!(base, &vec!["t"]);
         assert_eq!(vec!["o", "t}+="], match params.get(0).unwrap() {
           &HBValHolder::Path(ref p) => p.clone(), _ => vec![]
@@ -991,6 +1337,20 @@ mod tests {
     }
   }
 
+  #[test]
+  fn hb_block_params() {
+    match parse_hb_expression(r##"{{#each users key=k as | user  index |~}}"##, &Delimiters::default()) {
+      Ok((_, HBExpression{ref base, ref params, ref options, ref block_params, ref render_options, ..}, _))  => {
+        assert_eq!(base, &vec!["each"]);
+        assert_eq!(params.len(), 1);
+        assert_eq!(options.len(), 1);
+        assert_eq!(block_params, &vec!["user", "index"]);
+        assert!(render_options.no_trailing_whitespace);
+      },
+      Err(_)  => panic!("should parse"),
+    }
+  }
+
   #[test]
   fn from_str() {
     let template = "t {{u}} v".parse::<Template>();
@@ -1032,7 +1392,7 @@ mod tests {
     assert_eq!(true, match p.entries.get(0) {
       Some(& ref boxed_entry) => {
         match **boxed_entry {
-          HBEntry::Eval(HBExpression {ref base, ref params, ref options, ref render_options, ref block, ref else_block}) => match (block, else_block) { (&Some(_), &Some(_)) => true, _ => false },
+          HBEntry::Eval(HBExpression {ref base, ref params, ref options, ref render_options, ref block, ref else_block, ..}) => match (block, else_block) { (&Some(_), &Some(_)) => true, _ => false },
           _ => false,
         }
       },
@@ -1098,12 +1458,13 @@ mod tests {
 mod bench {
 
   use super::parse_hb_expression;
+  use lexer::Delimiters;
   use test::Bencher;
 
   #[bench]
   fn parse_simple_hb_exp(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression("{{i}}").ok();
+      parse_hb_expression("{{i}}", &Delimiters::default()).ok();
     })
   }
 
@@ -1111,84 +1472,84 @@ mod bench {
   #[bench]
   fn parse_hb_exp_1(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression("{{i.j}}").ok();
+      parse_hb_expression("{{i.j}}", &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_2(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression("{{[i]}}").ok();
+      parse_hb_expression("{{[i]}}", &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_3(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression("{{.}}").ok();
+      parse_hb_expression("{{.}}", &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_4(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression("{{./p}}").ok();
+      parse_hb_expression("{{./p}}", &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_5(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{p "string"}}"##).ok();
+      parse_hb_expression(r##"{{p "string"}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_6(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{p some.path}}"##).ok();
+      parse_hb_expression(r##"{{p some.path}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_7(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{p some path}}"##).ok();
+      parse_hb_expression(r##"{{p some path}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_8(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{p some.path "with_string" yep}}"##).ok();
+      parse_hb_expression(r##"{{p some.path "with_string" yep}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_9(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{t "… param1" well.[that my baby].[1] ~}}"##).ok();
+      parse_hb_expression(r##"{{t "… param1" well.[that my baby].[1] ~}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_10(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{t opt=u ~}}"##).ok();
+      parse_hb_expression(r##"{{t opt=u ~}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_11(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{t opt=u opt2="v" ~}}"##).ok();
+      parse_hb_expression(r##"{{t opt=u opt2="v" ~}}"##, &Delimiters::default()).ok();
     })
   }
 
   #[bench]
   fn parse_hb_exp_12(b: &mut Bencher) {
     b.iter(|| {
-      parse_hb_expression(r##"{{t o.[t}+=] opt="v" ~}}"##).ok();
+      parse_hb_expression(r##"{{t o.[t}+=] opt="v" ~}}"##, &Delimiters::default()).ok();
     })
   }
 }
@@ -0,0 +1,195 @@
+use serialize::{Encodable, Encoder, Decodable, Decoder};
+use serialize::json;
+use serialize::json::Json;
+
+use parse::Template;
+use parse::HBEntry;
+use parse::HBExpression;
+use parse::HBValHolder;
+use parse::RenderOptions;
+use parse::literal_value;
+
+// bumped whenever the encoded layout changes, older precompiled templates are refused
+const PRECOMPILED_VERSION: u32 = 1;
+
+struct Precompiled<'a>(&'a Template);
+
+impl<'a> Encodable for Precompiled<'a> {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_struct("Precompiled", 2, |s| {
+      try!(s.emit_struct_field("version", 0, |s| PRECOMPILED_VERSION.encode(s)));
+      s.emit_struct_field("template", 1, |s| self.0.encode(s))
+    })
+  }
+}
+
+impl Template {
+  /// Encodes the template as JSON, with a format version header.
+  ///
+  /// Use `Template::from_precompiled()` to load it back without parsing.
+  ///
+  /// ```
+  /// use rumblebars::Template;
+  ///
+  /// let template = rumblebars::parse("{{#if a}}{{b}}{{/if}}").unwrap();
+  /// let precompiled = template.precompile().unwrap();
+  ///
+  /// let loaded = Template::from_precompiled(&precompiled).unwrap();
+  /// assert_eq!(loaded.to_string(), "{{#if a}}{{b}}{{/if}}");
+  /// ```
+  pub fn precompile(&self) -> Result<String, json::EncoderError> {
+    json::encode(&Precompiled(self))
+  }
+
+  /// Loads a template encoded by `Template::precompile()`.
+  ///
+  /// Fails for malformed input, and for templates precompiled by another
+  /// version of the encoding.
+  pub fn from_precompiled(s: &str) -> Result<Template, json::DecoderError> {
+    let data = try!(Json::from_str(s).map_err(json::DecoderError::ParseError));
+    let mut decoder = json::Decoder::new(data);
+
+    decoder.read_struct("Precompiled", 2, |d| {
+      let version: u32 = try!(d.read_struct_field("version", 0, Decodable::decode));
+      if version != PRECOMPILED_VERSION {
+        return Err(d.error(&format!("unsupported precompiled template version {}", version)));
+      }
+      d.read_struct_field("template", 1, Decodable::decode)
+    })
+  }
+}
+
+impl Encodable for Template {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_struct("Template", 1, |s| {
+      s.emit_struct_field("entries", 0, |s| self.entries.encode(s))
+    })
+  }
+}
+
+impl Decodable for Template {
+  fn decode<D: Decoder>(d: &mut D) -> Result<Template, D::Error> {
+    d.read_struct("Template", 1, |d| {
+      Ok(Template {
+        entries: try!(d.read_struct_field("entries", 0, Decodable::decode)),
+      })
+    })
+  }
+}
+
+impl Encodable for HBEntry {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_enum("HBEntry", |s| {
+      match *self {
+        HBEntry::Raw(ref raw) => s.emit_enum_variant("Raw", 0, 1, |s| s.emit_enum_variant_arg(0, |s| raw.encode(s))),
+        HBEntry::Eval(ref exp) => s.emit_enum_variant("Eval", 1, 1, |s| s.emit_enum_variant_arg(0, |s| exp.encode(s))),
+        HBEntry::Partial(ref exp) => s.emit_enum_variant("Partial", 2, 1, |s| s.emit_enum_variant_arg(0, |s| exp.encode(s))),
+      }
+    })
+  }
+}
+
+impl Decodable for HBEntry {
+  fn decode<D: Decoder>(d: &mut D) -> Result<HBEntry, D::Error> {
+    d.read_enum("HBEntry", |d| {
+      d.read_enum_variant(&["Raw", "Eval", "Partial"], |d, variant| {
+        match variant {
+          0 => Ok(HBEntry::Raw(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          1 => Ok(HBEntry::Eval(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          2 => Ok(HBEntry::Partial(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          _ => Err(d.error("unknown entry kind")),
+        }
+      })
+    })
+  }
+}
+
+impl Encodable for HBExpression {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_struct("HBExpression", 7, |s| {
+      try!(s.emit_struct_field("base", 0, |s| self.base.encode(s)));
+      try!(s.emit_struct_field("params", 1, |s| self.params.encode(s)));
+      try!(s.emit_struct_field("options", 2, |s| self.options.encode(s)));
+      try!(s.emit_struct_field("block_params", 3, |s| self.block_params.encode(s)));
+      try!(s.emit_struct_field("render_options", 4, |s| self.render_options.encode(s)));
+      try!(s.emit_struct_field("block", 5, |s| self.block.encode(s)));
+      s.emit_struct_field("else_block", 6, |s| self.else_block.encode(s))
+    })
+  }
+}
+
+impl Decodable for HBExpression {
+  fn decode<D: Decoder>(d: &mut D) -> Result<HBExpression, D::Error> {
+    d.read_struct("HBExpression", 7, |d| {
+      Ok(HBExpression {
+        base: try!(d.read_struct_field("base", 0, Decodable::decode)),
+        params: try!(d.read_struct_field("params", 1, Decodable::decode)),
+        options: try!(d.read_struct_field("options", 2, Decodable::decode)),
+        block_params: try!(d.read_struct_field("block_params", 3, Decodable::decode)),
+        render_options: try!(d.read_struct_field("render_options", 4, Decodable::decode)),
+        block: try!(d.read_struct_field("block", 5, Decodable::decode)),
+        else_block: try!(d.read_struct_field("else_block", 6, Decodable::decode)),
+      })
+    })
+  }
+}
+
+impl Encodable for RenderOptions {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_struct("RenderOptions", 7, |s| {
+      try!(s.emit_struct_field("escape", 0, |s| self.escape.encode(s)));
+      try!(s.emit_struct_field("inverse", 1, |s| self.inverse.encode(s)));
+      try!(s.emit_struct_field("indent", 2, |s| self.indent.encode(s)));
+      try!(s.emit_struct_field("no_leading_whitespace", 3, |s| self.no_leading_whitespace.encode(s)));
+      try!(s.emit_struct_field("no_trailing_whitespace", 4, |s| self.no_trailing_whitespace.encode(s)));
+      try!(s.emit_struct_field("raw_block", 5, |s| self.raw_block.encode(s)));
+      s.emit_struct_field("decorator", 6, |s| self.decorator.encode(s))
+    })
+  }
+}
+
+impl Decodable for RenderOptions {
+  fn decode<D: Decoder>(d: &mut D) -> Result<RenderOptions, D::Error> {
+    d.read_struct("RenderOptions", 7, |d| {
+      Ok(RenderOptions {
+        escape: try!(d.read_struct_field("escape", 0, Decodable::decode)),
+        inverse: try!(d.read_struct_field("inverse", 1, Decodable::decode)),
+        indent: try!(d.read_struct_field("indent", 2, Decodable::decode)),
+        no_leading_whitespace: try!(d.read_struct_field("no_leading_whitespace", 3, Decodable::decode)),
+        no_trailing_whitespace: try!(d.read_struct_field("no_trailing_whitespace", 4, Decodable::decode)),
+        raw_block: try!(d.read_struct_field("raw_block", 5, Decodable::decode)),
+        decorator: try!(d.read_struct_field("decorator", 6, Decodable::decode)),
+      })
+    })
+  }
+}
+
+// literals are encoded by their source only, their value is computed back on decoding
+impl Encodable for HBValHolder {
+  fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+    s.emit_enum("HBValHolder", |s| {
+      match *self {
+        HBValHolder::String(ref string) => s.emit_enum_variant("String", 0, 1, |s| s.emit_enum_variant_arg(0, |s| string.encode(s))),
+        HBValHolder::Path(ref path) => s.emit_enum_variant("Path", 1, 1, |s| s.emit_enum_variant_arg(0, |s| path.encode(s))),
+        HBValHolder::Literal(_, ref source) => s.emit_enum_variant("Literal", 2, 1, |s| s.emit_enum_variant_arg(0, |s| source.encode(s))),
+        HBValHolder::SubExpression(ref exp) => s.emit_enum_variant("SubExpression", 3, 1, |s| s.emit_enum_variant_arg(0, |s| exp.encode(s))),
+      }
+    })
+  }
+}
+
+impl Decodable for HBValHolder {
+  fn decode<D: Decoder>(d: &mut D) -> Result<HBValHolder, D::Error> {
+    d.read_enum("HBValHolder", |d| {
+      d.read_enum_variant(&["String", "Path", "Literal", "SubExpression"], |d, variant| {
+        match variant {
+          0 => Ok(HBValHolder::String(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          1 => Ok(HBValHolder::Path(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          2 => Ok(literal_value(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          3 => Ok(HBValHolder::SubExpression(try!(d.read_enum_variant_arg(0, Decodable::decode)))),
+          _ => Err(d.error("unknown value kind")),
+        }
+      })
+    })
+  }
+}
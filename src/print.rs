@@ -0,0 +1,301 @@
+use std::fmt;
+
+use parse::Template;
+use parse::Entries;
+use parse::HBEntry;
+use parse::HBExpression;
+use parse::HBValHolder;
+use lexer::{Token, Delimiters};
+use lexer::Token::{TokSimpleExp, TokNoEscapeExp, TokCommentExp, TokSetDelimiters, TokBlockExp, TokBlockElseCond, TokBlockElseChain, TokBlockEndExp, TokPartialExp, TokRawBlockStart, TokRawBlockEnd, TokRaw, TokEscaped};
+
+// how a tag is handled when alone on its line
+#[derive(Clone,Copy,PartialEq)]
+enum Standalone {
+  Never,
+  // block, else, partial and decorator tags are removed along with their line
+  Trimmed,
+  // indented partials were standalone when parsed, their indentation is printed along
+  Indented,
+}
+
+enum Segment {
+  Text(String),
+  Tag(String, Standalone),
+}
+
+/// Prints a template back to handlebars source, that parses to the same template.
+///
+/// Comments and whitespace removed on parsing are not part of templates, so they
+/// are not printed back.
+///
+/// ```
+/// let template = rumblebars::parse("{{#each list as |item|}}{{{item}}} {{/each}}").unwrap();
+///
+/// assert_eq!(rumblebars::print(&template), "{{#each list as |item|}}{{{item}}} {{/each}}");
+/// assert_eq!(template.to_string(), "{{#each list as |item|}}{{{item}}} {{/each}}");
+/// ```
+pub fn print(template: &Template) -> String {
+  let mut segments = Vec::new();
+  print_entries(&template.entries, &mut segments);
+
+  let mut out = String::new();
+  for (index, segment) in segments.iter().enumerate() {
+    match *segment {
+      Segment::Text(ref s) => out.push_str(s),
+      Segment::Tag(ref s, Standalone::Indented) => {
+        // put the partial back on its own line, the line endings around it are trimmed on parsing
+        if !out.is_empty() && !out.ends_with('\n') {
+          out.push('\n');
+        }
+        out.push_str(s);
+        if index + 1 < segments.len() {
+          out.push('\n');
+        }
+      },
+      Segment::Tag(ref s, standalone) => {
+        let line_start = out.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let alone_before = out[line_start..].chars().all(|c| c == ' ' || c == '\t');
+        let alone_after = ends_line(&segments[index + 1..]);
+        // a standalone tag ending the template on an empty line has no whitespace to lose
+        let trimmed = line_start < out.len() || index + 1 < segments.len();
+
+        out.push_str(s);
+
+        // a tag that was not standalone when parsed must not become one,
+        // another tag on its line keeps its surrounding whitespace
+        if standalone != Standalone::Never && alone_before && alone_after && trimmed {
+          out.push_str("{{!}}");
+        }
+      },
+    }
+  }
+
+  out
+}
+
+// whether the segments that follow a tag start with a line ending, or only hold whitespace up to template end
+fn ends_line(following: &[Segment]) -> bool {
+  match (following.first(), following.len()) {
+    (Some(&Segment::Text(ref next)), len) => {
+      let space = next.len() - next.trim_left_matches(|c| c == ' ' || c == '\t' || c == '\r' || c == '\n').len();
+      next[..space].contains('\n') || (space == next.len() && len == 1)
+    },
+    (Some(&Segment::Tag(..)), _) => false,
+    (None, _) => true,
+  }
+}
+
+impl fmt::Display for Template {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", print(self))
+  }
+}
+
+fn print_entries(entries: &Entries, segments: &mut Vec<Segment>) {
+  for entry in entries.iter() {
+    match **entry {
+      HBEntry::Raw(ref s) => segments.push(Segment::Text(escape_raw(s))),
+      HBEntry::Partial(ref exp) => print_partial(exp, segments),
+      HBEntry::Eval(ref exp) => print_eval(exp, segments),
+    }
+  }
+}
+
+// escape braces that could open an expression, and backslashes that would escape what follows them
+fn escape_raw(s: &str) -> String {
+  let mut escaped = String::new();
+  let mut chars = s.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    let next = chars.peek().map(|&n| n);
+    match (c, next) {
+      ('{', None) => escaped.push_str("\\{"),
+      ('{', Some(n)) if "{ \t\r\n\\".contains(n) => escaped.push_str("\\{"),
+      ('\\', None) => escaped.push_str("\\\\"),
+      ('\\', Some(n)) if "{\r\n\\".contains(n) => escaped.push_str("\\\\"),
+      (c, _) => escaped.push(c),
+    }
+  }
+
+  escaped
+}
+
+fn print_partial(exp: &HBExpression, segments: &mut Vec<Segment>) {
+  let marks = whitespace_marks(exp);
+
+  match exp.block {
+    Some(ref block) => {
+      segments.push(Segment::Tag(format!("{{{{{}#> {}{}}}}}", marks.0, expression(exp), marks.1), Standalone::Trimmed));
+      print_entries(block, segments);
+      segments.push(Segment::Tag(format!("{{{{/{}}}}}", path(&exp.base)), Standalone::Trimmed));
+    },
+    None => {
+      let tag = format!("{{{{{}> {}{}}}}}", marks.0, expression(exp), marks.1);
+      match exp.render_options.indent {
+        Some(ref indent) => segments.push(Segment::Tag(format!("{}{}", indent, tag), Standalone::Indented)),
+        None => segments.push(Segment::Tag(tag, Standalone::Trimmed)),
+      }
+    },
+  }
+}
+
+fn print_eval(exp: &HBExpression, segments: &mut Vec<Segment>) {
+  let marks = whitespace_marks(exp);
+  let options = &exp.render_options;
+
+  match exp.block {
+    Some(ref block) if options.raw_block => {
+      segments.push(Segment::Tag(format!("{{{{{{{{{}}}}}}}}}", expression(exp)), Standalone::Never));
+      for e in block.iter() {
+        if let HBEntry::Raw(ref s) = **e {
+          segments.push(Segment::Text(s.clone()));
+        }
+      }
+      segments.push(Segment::Tag(format!("{{{{{{{{/{}}}}}}}}}", path(&exp.base)), Standalone::Never));
+    },
+    Some(ref block) => {
+      let mark = match (options.decorator, options.inverse) {
+        (true, _) => "#*",
+        (false, true) => "^",
+        (false, false) => "#",
+      };
+      segments.push(Segment::Tag(format!("{{{{{}{}{}{}}}}}", marks.0, mark, expression(exp), marks.1), Standalone::Trimmed));
+      print_entries(block, segments);
+      print_else(exp, segments);
+      segments.push(Segment::Tag(format!("{{{{/{}}}}}", path(&exp.base)), Standalone::Trimmed));
+    },
+    None if options.decorator => {
+      segments.push(Segment::Tag(format!("{{{{{}* {}{}}}}}", marks.0, expression(exp), marks.1), Standalone::Trimmed));
+    },
+    None if !options.escape => {
+      segments.push(Segment::Tag(format!("{{{{{}{{{}}}{}}}}}", marks.0, expression(exp), marks.1), Standalone::Never));
+    },
+    None => {
+      segments.push(Segment::Tag(format!("{{{{{}{}{}}}}}", marks.0, expression(exp), marks.1), Standalone::Never));
+    },
+  }
+}
+
+// else blocks made of a single block are printed as chained blocks (eg `{{else if b}}`)
+fn print_else(exp: &HBExpression, segments: &mut Vec<Segment>) {
+  if let Some(ref else_block) = exp.else_block {
+    match (else_block.first().map(|e| &**e), else_block.len()) {
+      (Some(&HBEntry::Eval(ref chained)), 1) if chained.block.is_some() && !chained.render_options.inverse &&
+        !chained.render_options.raw_block && !chained.render_options.decorator => {
+        segments.push(Segment::Tag(format!("{{{{else {}}}}}", expression(chained)), Standalone::Trimmed));
+        print_entries(chained.block.as_ref().unwrap(), segments);
+        print_else(chained, segments);
+      },
+      _ => {
+        segments.push(Segment::Tag("{{else}}".to_string(), Standalone::Trimmed));
+        print_entries(else_block, segments);
+      },
+    }
+  }
+}
+
+// canonical source of a tag lexed with `delimiters`, given the entry parsed from it, comments and set
+// delimiters tags are printed as is
+pub fn print_tag(tok: &Token, entry: &HBEntry, delimiters: &Delimiters) -> String {
+  let exp = match *entry { HBEntry::Eval(ref exp) | HBEntry::Partial(ref exp) => exp, HBEntry::Raw(ref s) => return escape_raw(s) };
+  let marks = whitespace_marks(exp);
+  let options = &exp.render_options;
+
+  let tag = match (tok, entry) {
+    (&TokCommentExp(ref s), _) | (&TokSetDelimiters(ref s), _) => return s.trim().to_string(),
+    (&TokSimpleExp(_), _) if options.decorator => format!("{{{{{}* {}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokSimpleExp(_), _) => format!("{{{{{}{}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokNoEscapeExp(_), _) => format!("{{{{{}{{{}}}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokPartialExp(_), _) => format!("{{{{{}> {}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokBlockExp(..), &HBEntry::Partial(_)) => format!("{{{{{}#> {}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokBlockExp(..), _) => {
+      let mark = match (options.decorator, options.inverse) {
+        (true, _) => "#*",
+        (false, true) => "^",
+        (false, false) => "#",
+      };
+      format!("{{{{{}{}{}{}}}}}", marks.0, mark, expression(exp), marks.1)
+    },
+    (&TokBlockElseCond(_), _) => format!("{{{{{}else{}}}}}", marks.0, marks.1),
+    (&TokBlockElseChain(_), _) => format!("{{{{{}else {}{}}}}}", marks.0, expression(exp), marks.1),
+    (&TokBlockEndExp(_), _) => format!("{{{{{}/{}{}}}}}", marks.0, path(&exp.base), marks.1),
+    (&TokRawBlockStart(_), _) => format!("{{{{{{{{{}}}}}}}}}", expression(exp)),
+    (&TokRawBlockEnd(_), _) => format!("{{{{{{{{/{}}}}}}}}}", path(&exp.base)),
+    (&TokRaw(ref s), _) | (&TokEscaped(ref s), _) => return s.clone(),
+  };
+
+  // tags are printed with default delimiters, that are swapped for the lexing ones
+  format!("{}{}{}", delimiters.open, &tag[2..tag.len() - 2], delimiters.close)
+}
+
+fn whitespace_marks(exp: &HBExpression) -> (&'static str, &'static str) {
+  (
+    if exp.render_options.no_leading_whitespace { "~" } else { "" },
+    if exp.render_options.no_trailing_whitespace { "~" } else { "" },
+  )
+}
+
+// expression content, without delimiters nor markers
+fn expression(exp: &HBExpression) -> String {
+  let mut parts = Vec::new();
+
+  // partials with computed names have no base, their name is the first param
+  if !exp.base.is_empty() {
+    parts.push(path(&exp.base));
+  }
+
+  for p in exp.params.iter() {
+    parts.push(value(p));
+  }
+
+  for &(ref name, ref v) in exp.options.iter() {
+    parts.push(format!("{}={}", name, value(v)));
+  }
+
+  if !exp.block_params.is_empty() {
+    parts.push(format!("as |{}|", exp.block_params.join(" ")));
+  }
+
+  parts.join(" ")
+}
+
+fn value(v: &HBValHolder) -> String {
+  match *v {
+    HBValHolder::String(ref s) if s.contains('"') && !s.contains('\'') => format!("'{}'", s.replace("\\", "\\\\")),
+    HBValHolder::String(ref s) => format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"")),
+    HBValHolder::Path(ref p) => path(p),
+    HBValHolder::Literal(_, ref s) => s.clone(),
+    HBValHolder::SubExpression(ref exp) => format!("({})", expression(exp)),
+  }
+}
+
+fn path(base: &[String]) -> String {
+  let mut r = String::new();
+
+  for (index, segment) in base.iter().enumerate() {
+    if index > 0 {
+      r.push(match &base[index - 1][..] { "." | ".." => '/', _ => '.' });
+    }
+    r.push_str(&path_segment(segment, index == 0));
+  }
+
+  r
+}
+
+// segments that would not be lexed as a single identifier are bracketed
+fn path_segment(segment: &str, first: bool) -> String {
+  let is_identifier = segment.chars().enumerate().all(|(i, c)| {
+    !"!\"#%&'()*+,./;<=>[\\]^`{|}~ \t".contains(c) && (c != '@' || i == 0)
+  });
+  let is_keyword = match segment {
+    "this" | "else" | "true" | "false" | "null" | "undefined" => true,
+    _ => first && segment.trim_left_matches('-').chars().next().map(|c| c.is_digit(10)).unwrap_or(false),
+  };
+
+  match segment {
+    "." | ".." => segment.to_string(),
+    _ if is_identifier && !is_keyword && !segment.is_empty() => segment.to_string(),
+    _ if !segment.contains(']') => format!("[{}]", segment),
+    _ => format!("\"{}\"", segment.replace("\"", "\\\"")),
+  }
+}
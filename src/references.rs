@@ -0,0 +1,142 @@
+use parse::Template;
+use parse::HBExpression;
+use parse::HBValHolder;
+use visit::Visitor;
+
+/// A data path, helper or partial name used by a template.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Reference {
+  /// path or name, as written in the template
+  pub path: Vec<String>,
+  /// blocks the reference is rendered in, outermost first (else blocks render
+  /// in their enclosing context, they are not part of it)
+  pub blocks: Vec<String>,
+  /// block params (`as |item|`) in scope
+  pub block_params: Vec<String>,
+}
+
+#[derive(Default)]
+struct Collector {
+  paths: Vec<Reference>,
+  helpers: Vec<Reference>,
+  partials: Vec<Reference>,
+  blocks: Vec<String>,
+  block_params: Vec<String>,
+}
+
+impl Collector {
+  fn reference(&self, path: &[String]) -> Reference {
+    Reference {
+      path: path.to_vec(),
+      blocks: self.blocks.clone(),
+      block_params: self.block_params.clone(),
+    }
+  }
+
+  fn collect(template: &Template) -> Collector {
+    let mut collector = Collector::default();
+    collector.visit_template(template);
+    collector
+  }
+
+  // params are evaluated outside of the block, its content within
+  fn visit_expression(&mut self, exp: &HBExpression) {
+    for param in exp.params.iter() {
+      self.visit_value(param);
+    }
+
+    for &(_, ref value) in exp.options.iter() {
+      self.visit_value(value);
+    }
+
+    if let Some(ref block) = exp.block {
+      self.blocks.push(exp.base.join("/"));
+      let params_count = self.block_params.len();
+      self.block_params.extend(exp.block_params.iter().cloned());
+
+      self.visit_block(block);
+
+      self.block_params.truncate(params_count);
+      self.blocks.pop();
+    }
+
+    if let Some(ref else_block) = exp.else_block {
+      self.visit_else_block(else_block);
+    }
+  }
+}
+
+impl Visitor for Collector {
+  fn visit_eval(&mut self, exp: &HBExpression) {
+    // expressions without params may be helpers too, they are reported as paths
+    let reference = self.reference(&exp.base);
+    match (exp.render_options.decorator, exp.params.is_empty() && exp.options.is_empty()) {
+      (true, _) => (),
+      (false, true) => self.paths.push(reference),
+      (false, false) => self.helpers.push(reference),
+    }
+
+    self.visit_expression(exp);
+  }
+
+  fn visit_partial(&mut self, exp: &HBExpression) {
+    // computed names have no base, `@partial-block` is not a registered partial
+    match exp.base.last() {
+      None => (),
+      Some(name) if name == "@partial-block" => (),
+      Some(_) => {
+        let reference = self.reference(&exp.base);
+        self.partials.push(reference);
+      },
+    }
+
+    self.visit_expression(exp);
+  }
+
+  fn visit_value(&mut self, value: &HBValHolder) {
+    match *value {
+      HBValHolder::Path(ref path) => {
+        let reference = self.reference(path);
+        self.paths.push(reference);
+      },
+      HBValHolder::SubExpression(ref exp) => self.visit_sub_expression(exp),
+      _ => (),
+    }
+  }
+
+  fn visit_sub_expression(&mut self, exp: &HBExpression) {
+    let reference = self.reference(&exp.base);
+    self.helpers.push(reference);
+
+    self.visit_expression(exp);
+  }
+}
+
+impl Template {
+  /// Data paths used by the template, in template order.
+  ///
+  /// Expressions without params are reported as paths, even if they end up
+  /// calling a helper registered with that name.
+  ///
+  /// ```
+  /// let template = rumblebars::parse("{{title}}{{#each items as |item|}}{{item.name}}{{/each}}").unwrap();
+  /// let paths = template.paths();
+  ///
+  /// assert_eq!(paths[2].path, vec!["item", "name"]);
+  /// assert_eq!(paths[2].blocks, vec!["each"]);
+  /// assert_eq!(paths[2].block_params, vec!["item"]);
+  /// ```
+  pub fn paths(&self) -> Vec<Reference> {
+    Collector::collect(self).paths
+  }
+
+  /// Helpers called by the template : expressions with params and sub expressions.
+  pub fn helpers(&self) -> Vec<Reference> {
+    Collector::collect(self).helpers
+  }
+
+  /// Partials rendered by the template, partials with computed names are left out.
+  pub fn partials(&self) -> Vec<Reference> {
+    Collector::collect(self).partials
+  }
+}
@@ -0,0 +1,183 @@
+use parse::Template;
+use parse::Entries;
+use parse::HBEntry;
+use parse::HBExpression;
+use parse::HBValHolder;
+
+/// Walks a template, each method defaults to visiting the node children.
+///
+/// Override the methods for the nodes of interest, and call the matching `walk_*`
+/// function to keep visiting their children.
+///
+/// ```
+/// use rumblebars::{Visitor, HBExpression};
+///
+/// struct Paths(Vec<String>);
+///
+/// impl Visitor for Paths {
+///   fn visit_eval(&mut self, exp: &HBExpression) {
+///     self.0.push(exp.path());
+///     rumblebars::walk_expression(self, exp);
+///   }
+/// }
+///
+/// let template = rumblebars::parse("{{#if a}}{{b}}{{else}}{{c (d)}}{{/if}}").unwrap();
+/// let mut paths = Paths(vec![]);
+/// paths.visit_template(&template);
+///
+/// assert_eq!(paths.0, vec!["if", "b", "c"]);
+/// ```
+pub trait Visitor {
+  fn visit_template(&mut self, template: &Template) {
+    self.visit_entries(&template.entries);
+  }
+
+  fn visit_entries(&mut self, entries: &Entries) {
+    for entry in entries.iter() {
+      self.visit_entry(entry);
+    }
+  }
+
+  fn visit_entry(&mut self, entry: &HBEntry) {
+    match *entry {
+      HBEntry::Raw(ref raw) => self.visit_raw(raw),
+      HBEntry::Eval(ref exp) => self.visit_eval(exp),
+      HBEntry::Partial(ref exp) => self.visit_partial(exp),
+    }
+  }
+
+  fn visit_raw(&mut self, _raw: &str) {}
+
+  fn visit_eval(&mut self, exp: &HBExpression) {
+    walk_expression(self, exp);
+  }
+
+  fn visit_partial(&mut self, exp: &HBExpression) {
+    walk_expression(self, exp);
+  }
+
+  fn visit_block(&mut self, block: &Entries) {
+    self.visit_entries(block);
+  }
+
+  fn visit_else_block(&mut self, else_block: &Entries) {
+    self.visit_entries(else_block);
+  }
+
+  fn visit_value(&mut self, value: &HBValHolder) {
+    if let HBValHolder::SubExpression(ref exp) = *value {
+      self.visit_sub_expression(exp);
+    }
+  }
+
+  fn visit_sub_expression(&mut self, exp: &HBExpression) {
+    walk_expression(self, exp);
+  }
+}
+
+/// Visits expression params, options values, block and else block.
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, exp: &HBExpression) {
+  for param in exp.params.iter() {
+    visitor.visit_value(param);
+  }
+
+  for &(_, ref value) in exp.options.iter() {
+    visitor.visit_value(value);
+  }
+
+  if let Some(ref block) = exp.block {
+    visitor.visit_block(block);
+  }
+
+  if let Some(ref else_block) = exp.else_block {
+    visitor.visit_else_block(else_block);
+  }
+}
+
+/// Same as `Visitor`, with mutable access to the nodes, for rewriting templates.
+///
+/// ```
+/// use rumblebars::{VisitorMut, HBExpression};
+///
+/// struct Rename;
+///
+/// impl VisitorMut for Rename {
+///   fn visit_partial_mut(&mut self, exp: &mut HBExpression) {
+///     if exp.base == vec!["old"] {
+///       exp.base = vec!["new".to_string()];
+///     }
+///     rumblebars::walk_expression_mut(self, exp);
+///   }
+/// }
+///
+/// let mut template = rumblebars::parse("{{#a}}{{> old}}{{/a}}").unwrap();
+/// Rename.visit_template_mut(&mut template);
+///
+/// assert_eq!(template.to_string(), "{{#a}}{{> new}}{{/a}}");
+/// ```
+pub trait VisitorMut {
+  fn visit_template_mut(&mut self, template: &mut Template) {
+    self.visit_entries_mut(&mut template.entries);
+  }
+
+  fn visit_entries_mut(&mut self, entries: &mut Entries) {
+    for entry in entries.iter_mut() {
+      self.visit_entry_mut(entry);
+    }
+  }
+
+  fn visit_entry_mut(&mut self, entry: &mut HBEntry) {
+    match *entry {
+      HBEntry::Raw(ref mut raw) => self.visit_raw_mut(raw),
+      HBEntry::Eval(ref mut exp) => self.visit_eval_mut(exp),
+      HBEntry::Partial(ref mut exp) => self.visit_partial_mut(exp),
+    }
+  }
+
+  fn visit_raw_mut(&mut self, _raw: &mut String) {}
+
+  fn visit_eval_mut(&mut self, exp: &mut HBExpression) {
+    walk_expression_mut(self, exp);
+  }
+
+  fn visit_partial_mut(&mut self, exp: &mut HBExpression) {
+    walk_expression_mut(self, exp);
+  }
+
+  fn visit_block_mut(&mut self, block: &mut Entries) {
+    self.visit_entries_mut(block);
+  }
+
+  fn visit_else_block_mut(&mut self, else_block: &mut Entries) {
+    self.visit_entries_mut(else_block);
+  }
+
+  fn visit_value_mut(&mut self, value: &mut HBValHolder) {
+    if let HBValHolder::SubExpression(ref mut exp) = *value {
+      self.visit_sub_expression_mut(exp);
+    }
+  }
+
+  fn visit_sub_expression_mut(&mut self, exp: &mut HBExpression) {
+    walk_expression_mut(self, exp);
+  }
+}
+
+/// Mutable counterpart of `walk_expression`.
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exp: &mut HBExpression) {
+  for param in exp.params.iter_mut() {
+    visitor.visit_value_mut(param);
+  }
+
+  for &mut (_, ref mut value) in exp.options.iter_mut() {
+    visitor.visit_value_mut(value);
+  }
+
+  if let Some(ref mut block) = exp.block {
+    visitor.visit_block_mut(block);
+  }
+
+  if let Some(ref mut else_block) = exp.else_block {
+    visitor.visit_else_block_mut(else_block);
+  }
+}
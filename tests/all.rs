@@ -4,6 +4,7 @@ extern crate rustc_serialize as serialize;
 #[cfg(feature = "stream_test")] extern crate rand;
 #[cfg(feature = "stream_test")] extern crate time;
 
+mod codegen;
 mod helpers;
 mod parse;
 
@@ -140,6 +141,134 @@ mod eval {
 
   }
 
+  #[test]
+  fn dynamic_partial_name() {
+    let json = Json::from_str(r##"{"items": [{"type": "a", "v": 1}, {"type": "b", "v": 2}, {"type": "c", "v": 3}], "p": {"v": 4}, "kind": "b"}"##).ok().unwrap();
+    let tmpl = parse(r##"{{#each items}}{{> (type)}}{{/each}} {{> (lookup . "kind") p}}"##).ok().unwrap();
+    let mut eval_ctxt: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_ctxt.register_partial("a".to_string(), parse("a{{v}}").ok().unwrap());
+    eval_ctxt.register_partial("b".to_string(), parse("b{{v}}").ok().unwrap());
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "a1b2 b4");
+  }
+
+  #[test]
+  fn path_like_partial_names() {
+    let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+    let tmpl = parse(r##"{{> shared/item.row}} {{#> layouts/main}}{{a}}{{/layouts/main}} {{> [missing].name}}"##).ok().unwrap();
+    let mut eval_ctxt: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_ctxt.register_partial("shared/item.row".to_string(), parse("row {{a}}").ok().unwrap());
+    eval_ctxt.register_partial("layouts/main".to_string(), parse("<{{> @partial-block}}>").ok().unwrap());
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "row A <A> ");
+  }
+
+  #[test]
+  fn inline_partial() {
+    let json = Json::from_str(r##"{"rows": [{"name": "a"}, {"name": "b"}]}"##).ok().unwrap();
+    let tmpl = parse(r##"{{#*inline "row"}}
+<li>{{name}}</li>
+{{/inline}}
+{{#each rows}}{{> row}}{{/each}}{{> footer}}"##).ok().unwrap();
+    let mut eval_ctxt: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_ctxt.register_partial("row".to_string(), parse("overridden").ok().unwrap());
+    eval_ctxt.register_partial("footer".to_string(), parse("{{> row}}").ok().unwrap());
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "<li>a</li>\n<li>b</li>\n<li></li>\n");
+  }
+
+  #[test]
+  fn inline_partial_scope() {
+    let json = Json::from_str(r##"{"a": true}"##).ok().unwrap();
+    let tmpl = parse(r##"{{#*inline "p"}}outer{{/inline}}{{> p}} {{#a}}{{> p}}{{#*inline "p"}}inner{{/inline}}{{/a}} {{> p}} {{> q}}{{#a}}{{#*inline "q"}}q{{/inline}}{{/a}}"##).ok().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval(&tmpl, &json, &mut buf, &Default::default()).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "outer inner outer ");
+  }
+
+  #[test]
+  fn partial_block_layout() {
+    let json = Json::from_str(r##"{"title": "T", "page": {"body": "B"}}"##).ok().unwrap();
+    let tmpl = parse(r##"{{#> layout page}}
+{{body}}
+{{/layout}}
+{{#> missing}}default {{title}}{{/missing}}"##).ok().unwrap();
+    let layout = parse(r##"<h1>{{../title}}</h1>
+{{> @partial-block}}
+{{#if body}}{{> @partial-block}}{{/if}}
+"##).ok().unwrap();
+    let mut eval_ctxt: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_ctxt.register_partial("layout".to_string(), layout);
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "<h1>T</h1>\nB\nB\n\ndefault T");
+  }
+
+  #[test]
+  fn partial_block_inline_partials() {
+    let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+    let tmpl = parse(r##"{{#> layout}}{{#*inline "content"}}c{{a}}{{/inline}}{{#> layout}}nested{{/layout}}{{/layout}}"##).ok().unwrap();
+    let layout = parse(r##"[{{> content}}|{{> @partial-block}}]"##).ok().unwrap();
+    let mut eval_ctxt: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_ctxt.register_partial("layout".to_string(), layout);
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "[cA|[cA|nested]]");
+  }
+
+  #[test]
+  fn set_delimiters() {
+    let json = Json::from_str(r##"{"a": "A", "l": [1, 2]}"##).ok().unwrap();
+    let tmpl = parse("{{a}}\n{{=<% %>=}}\n{{a}} \\<%a%> <%#l%>{<%.%>}<%/l%>\n<%={{ }}=%>\n{{a}}").ok().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval(&tmpl, &json, &mut buf, &Default::default()).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "A\n{{a}} \\A {1}{2}\nA");
+  }
+
+  #[test]
+  fn default_delimiters() {
+    let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+    let tmpl = ::rumblebars::parse_with_delimiters("\\frac{1}{2} (( a )) {{a}}", "((", "))").ok().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval(&tmpl, &json, &mut buf, &Default::default()).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "\\frac{1}{2} A {{a}}");
+  }
+
+  #[test]
+  fn custom_delimiters_tags() {
+    let json = Json::from_str(r##"{"a": "<A>", "l": [1, 2]}"##).ok().unwrap();
+    let tmpl = ::rumblebars::parse_with_delimiters("<%a%> <%{a}%> <%~&a~%> <%! c %>{<%#l%><%.%><%else%>-<%/l%>} <%{{raw}}%> <%a%> <%{{/raw}}%> a < b", "<%", "%>").ok().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval(&tmpl, &json, &mut buf, &Default::default()).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "&lt;A&gt; <A><A>{12}  <%a%>  a < b");
+  }
+
   #[test]
   fn leading_whitespace() {
     let json = Json::from_str(r##"{"p": {}}"##).ok().unwrap();
@@ -285,7 +414,7 @@ mod eval {
 
       eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
 
-      let expected = "\n        1)\n          o\n\n\n      ";
+      let expected = "\n        1)\n          o\n\n      ";
 
       assert_eq!(String::from_utf8(buf).unwrap(), expected);
     }
@@ -0,0 +1,158 @@
+#[allow(unused_variables, unused_mut)]
+pub fn render(data: &::rumblebars::HBData, out: &mut ::std::io::Write, eval_context: &::rumblebars::EvalContext) -> ::rumblebars::HBEvalResult {
+  use ::rumblebars::codegen_runtime as rt;
+  let mut html_safe = ::rumblebars::HTMLSafeWriter::new(out);
+  let out = &mut ::rumblebars::SafeWriting::Safe(&mut html_safe);
+  let g0 = rt::globals(data, &eval_context.level);
+  let p0 = rt::block_params();
+  let s0 = vec![data];
+  let c0 = data;
+  try!(rt::write_raw(out, "<h1>"));
+  try!(rt::write_value(out, rt::lookup(c0, &["title"], &s0, &g0, &p0, false), true));
+  try!(rt::write_raw(out, "</h1>\n"));
+  let v1 = rt::param(rt::lookup(c0, &["items"], &s0, &g0, &p0, false));
+  match rt::each(c0, Some(v1)) {
+    rt::Each::Items(items) => for i2 in items.iter() {
+      let c3 = i2.context;
+      let g4 = i2.globals(&g0);
+      let p5 = i2.block_params(&p0, &["item", "i"]);
+      let c6 = rt::lookup(c3, &["item"], &s0, &g4, &p5, false).unwrap_or(c3);
+      let mut l7 = true;
+      {
+        try!(rt::write_indented_raw(out, "<li>", "  ", &mut l7));
+        try!(rt::write_indented_value(out, rt::lookup(c6, &["@index"], &s0, &g4, &p0, false), true, "  ", &mut l7));
+        try!(rt::write_indented_raw(out, " ", "  ", &mut l7));
+        try!(rt::write_indented_value(out, rt::lookup(c6, &["name"], &s0, &g4, &p0, false), true, "  ", &mut l7));
+        try!(rt::write_indented_raw(out, "</li>\n<p>", "  ", &mut l7));
+        {
+          try!(rt::write_indented_value(out, rt::lookup(c6, &["..", "title"], &s0, &g4, &p0, false), true, "  ", &mut l7));
+          try!(rt::write_indented_raw(out, "\n", "  ", &mut l7));
+          try!(rt::write_indented_value(out, rt::lookup(c6, &["name"], &s0, &g4, &p0, false), true, "  ", &mut l7));
+        }
+        try!(rt::write_indented_raw(out, "</p>\n", "  ", &mut l7));
+      }
+    },
+    rt::Each::Else => {
+      try!(rt::write_raw(out, "  none\n"));
+    },
+    rt::Each::Once => {
+      let c8 = rt::lookup(c0, &["item"], &s0, &g0, &p0, false).unwrap_or(c0);
+      let mut l9 = true;
+      {
+        try!(rt::write_indented_raw(out, "<li>", "  ", &mut l9));
+        try!(rt::write_indented_value(out, rt::lookup(c8, &["@index"], &s0, &g0, &p0, false), true, "  ", &mut l9));
+        try!(rt::write_indented_raw(out, " ", "  ", &mut l9));
+        try!(rt::write_indented_value(out, rt::lookup(c8, &["name"], &s0, &g0, &p0, false), true, "  ", &mut l9));
+        try!(rt::write_indented_raw(out, "</li>\n<p>", "  ", &mut l9));
+        {
+          try!(rt::write_indented_value(out, rt::lookup(c8, &["..", "title"], &s0, &g0, &p0, false), true, "  ", &mut l9));
+          try!(rt::write_indented_raw(out, "\n", "  ", &mut l9));
+          try!(rt::write_indented_value(out, rt::lookup(c8, &["name"], &s0, &g0, &p0, false), true, "  ", &mut l9));
+        }
+        try!(rt::write_indented_raw(out, "</p>\n", "  ", &mut l9));
+      }
+    },
+  }
+  if rt::truthy(rt::lookup(c0, &["flag"], &s0, &g0, &p0, false)) {
+    try!(rt::write_raw(out, "yes"));
+  } else {
+    try!(rt::write_raw(out, "no"));
+  }
+  try!(rt::write_raw(out, " "));
+  thread_local!(static E10: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["unless".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["flag".to_string()])], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: true, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: Some(Box::new(vec![Box::new(::rumblebars::HBEntry::Raw("!".to_string()))])), else_block: None }))]);
+  try!(E10.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  try!(rt::write_raw(out, " "));
+  let v11 = rt::param(rt::lookup(c0, &["owner"], &s0, &g0, &p0, false));
+  if rt::truthy(Some(v11)) {
+    let p12 = rt::bind(&p0, &["o"], &[v11]);
+    try!(rt::write_value(out, rt::lookup(v11, &["o", "name"], &s0, &g0, &p12, false), true));
+    try!(rt::write_raw(out, " "));
+    try!(rt::write_value(out, rt::lookup(v11, &["..", "title"], &s0, &g0, &p12, false), true));
+  }
+  try!(rt::write_raw(out, "\n"));
+  match rt::section(rt::lookup(c0, &["owner"], &s0, &g0, &p0, false), false) {
+    rt::Section::Block(contexts) => {
+      let s14 = rt::push(&s0, c0);
+      for c13 in contexts {
+        try!(rt::write_value(out, rt::lookup(c13, &["name"], &s14, &g0, &p0, false), true));
+        try!(rt::write_raw(out, " "));
+        try!(rt::write_value(out, rt::lookup(c13, &["@root", "title"], &s14, &g0, &p0, false), true));
+      }
+    },
+    rt::Section::Else => (),
+  }
+  match rt::section(rt::lookup(c0, &["missing"], &s0, &g0, &p0, false), true) {
+    rt::Section::Block(contexts) => {
+      let s16 = rt::push(&s0, c0);
+      for c15 in contexts {
+        try!(rt::write_raw(out, "-"));
+      }
+    },
+    rt::Section::Else => (),
+  }
+  try!(rt::write_raw(out, "\n"));
+  let v17 = rt::param(rt::lookup(c0, &["owner"], &s0, &g0, &p0, false));
+  match rt::each(c0, Some(v17)) {
+    rt::Each::Items(items) => for i18 in items.iter() {
+      let c19 = i18.context;
+      let g20 = i18.globals(&g0);
+      try!(rt::write_value(out, rt::lookup(c19, &["@key"], &s0, &g20, &p0, false), true));
+      try!(rt::write_raw(out, "="));
+      try!(rt::write_value(out, rt::lookup(c19, &["."], &s0, &g20, &p0, false), true));
+      if rt::truthy(rt::lookup(c19, &["@last"], &s0, &g20, &p0, false)) {
+        try!(rt::write_raw(out, "."));
+      }
+    },
+    rt::Each::Else => (),
+    rt::Each::Once => {
+      try!(rt::write_value(out, rt::lookup(c0, &["@key"], &s0, &g0, &p0, false), true));
+      try!(rt::write_raw(out, "="));
+      try!(rt::write_value(out, rt::lookup(c0, &["."], &s0, &g0, &p0, false), true));
+      if rt::truthy(rt::lookup(c0, &["@last"], &s0, &g0, &p0, false)) {
+        try!(rt::write_raw(out, "."));
+      }
+    },
+  }
+  try!(rt::write_raw(out, "\n"));
+  try!(rt::write_raw(out, "{{not}}"));
+  try!(rt::write_raw(out, " "));
+  try!(rt::write_value(out, rt::lookup(c0, &["html"], &s0, &g0, &p0, false), false));
+  try!(rt::write_raw(out, " "));
+  try!(rt::write_value(out, rt::lookup(c0, &["html"], &s0, &g0, &p0, false), true));
+  try!(rt::write_raw(out, ".\n"));
+  let mut l21 = true;
+  {
+    try!(rt::write_indented_value(out, rt::lookup(c0, &["..", "title"], &s0, &g0, &p0, false), true, "  ", &mut l21));
+    try!(rt::write_indented_raw(out, "\n", "  ", &mut l21));
+    try!(rt::write_indented_value(out, rt::lookup(c0, &["name"], &s0, &g0, &p0, false), true, "  ", &mut l21));
+  }
+  thread_local!(static E22: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["upper".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["name".to_string()])], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None }))]);
+  try!(E22.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  try!(rt::write_raw(out, " "));
+  thread_local!(static V23: ::rumblebars::HBExpression = ::rumblebars::HBExpression { base: vec!["upper".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["title".to_string()])], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None });
+  let v23_value = try!(V23.with(|x| rt::sub_expression(x, c0, &s0, &g0, &p0, eval_context)));
+  let v23: &::rumblebars::HBData = &v23_value;
+  if rt::truthy(Some(v23)) {
+    thread_local!(static E24: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["upper".to_string()], params: vec![::rumblebars::HBValHolder::SubExpression(::rumblebars::HBExpression { base: vec!["lookup".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["owner".to_string()]), ::rumblebars::HBValHolder::String("name".to_string())], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None })], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None }))]);
+    try!(E24.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  }
+  try!(rt::write_raw(out, " "));
+  thread_local!(static E25: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["unless".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["flag".to_string()])], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: Some(Box::new(vec![Box::new(::rumblebars::HBEntry::Raw("not".to_string()))])), else_block: None }))]);
+  try!(E25.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  try!(rt::write_raw(out, " "));
+  try!(rt::write_value(out, rt::lookup(c0, &["@level"], &s0, &g0, &p0, false), true));
+  try!(rt::write_raw(out, "\n"));
+  let v26 = rt::param(rt::lookup(c0, &["owner"], &s0, &g0, &p0, false));
+  if rt::truthy(Some(v26)) {
+    thread_local!(static E27: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["inline".to_string()], params: vec![::rumblebars::HBValHolder::String("tag".to_string())], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: true }, block: Some(Box::new(vec![Box::new(::rumblebars::HBEntry::Raw("<".to_string())), Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec![".".to_string()], params: vec![], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None })), Box::new(::rumblebars::HBEntry::Raw(">".to_string()))])), else_block: None })), Box::new(::rumblebars::HBEntry::Partial(::rumblebars::HBExpression { base: vec!["tag".to_string()], params: vec![::rumblebars::HBValHolder::Path(vec!["name".to_string()])], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None }))]);
+    try!(E27.with(|e| rt::eval(e, v26, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  }
+  try!(rt::write_raw(out, " "));
+  thread_local!(static E28: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Partial(::rumblebars::HBExpression { base: vec!["frame".to_string()], params: vec![], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: Some(Box::new(vec![Box::new(::rumblebars::HBEntry::Eval(::rumblebars::HBExpression { base: vec!["title".to_string()], params: vec![], options: vec![], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None }))])), else_block: None }))]);
+  try!(E28.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  try!(rt::write_raw(out, " "));
+  thread_local!(static E29: ::rumblebars::Entries = vec![Box::new(::rumblebars::HBEntry::Partial(::rumblebars::HBExpression { base: vec!["cell".to_string()], params: vec![], options: vec![("name".to_string(), ::rumblebars::HBValHolder::Path(vec!["title".to_string()]))], block_params: vec![], render_options: ::rumblebars::RenderOptions { escape: true, inverse: false, indent: None, no_leading_whitespace: false, no_trailing_whitespace: false, raw_block: false, decorator: false }, block: None, else_block: None }))]);
+  try!(E29.with(|e| rt::eval(e, c0, &s0, &g0, &p0, out, eval_context, None, &mut true)));
+  try!(rt::write_raw(out, "\nend\n"));
+  Ok(())
+}
@@ -0,0 +1,90 @@
+use serialize::json::Json;
+use std::io::Write;
+
+use rumblebars::{EvalContext, Template, CodegenError, compile_to_rust, parse};
+
+include!("generated.rs");
+
+static TEMPLATE: &'static str = r##"<h1>{{title}}</h1>
+{{#each items as |item i|}}
+  {{> row item}}
+{{else}}
+  none
+{{/each}}
+{{#if flag}}yes{{else}}no{{/if}} {{^unless flag}}!{{/unless}} {{#with owner as |o|}}{{o.name}} {{../title}}{{/with}}
+{{#owner}}{{name}} {{@root.title}}{{/owner}}{{^missing}}-{{/missing}}
+{{#each owner}}{{@key}}={{.}}{{#if @last}}.{{/if}}{{/each}}
+{{{{raw}}}}{{not}}{{{{/raw}}}} {{{html}}} {{html}}.
+  {{> cell}}
+{{upper name}} {{#if (upper title)}}{{upper (lookup owner "name")}}{{/if}} {{#unless flag}}not{{/unless}} {{@level}}
+{{#with owner}}{{#*inline "tag"}}<{{.}}>{{/inline}}{{> tag name}}{{/with}} {{#> frame}}{{title}}{{/frame}} {{> cell name=title}}
+end
+"##;
+
+fn eval_context() -> EvalContext {
+  let mut context = EvalContext::new();
+  context.register_partial("row".to_string(), parse("<li>{{@index}} {{name}}</li>\n<p>{{> cell}}</p>\n").unwrap());
+  context.register_partial("cell".to_string(), parse("{{../title}}\n{{name}}").unwrap());
+  context.register_partial("frame".to_string(), parse("[{{> @partial-block}}]").unwrap());
+  context.register_helper("upper".to_string(), Box::new(|params, options, out, _| {
+    let value = params.first().and_then(|p| p.json_value().as_string().map(|s| s.to_uppercase())).unwrap_or(String::new());
+    if options.is_sub_expression() {
+      options.return_value(Json::String(value));
+      Ok(())
+    } else {
+      write!(out, "{}", value)
+    }
+  }));
+  context.register_helper("unless".to_string(), Box::new(|_, _, out, _| write!(out, "overridden")));
+  context.level = "debug".to_string();
+  context
+}
+
+#[test]
+fn generated_code_is_up_to_date() {
+  let template = parse(TEMPLATE).unwrap();
+  let code = compile_to_rust(&template, "render", &eval_context()).unwrap();
+
+  assert_eq!(code, include_str!("generated.rs"));
+}
+
+#[test]
+fn generated_code_renders_as_eval() {
+  let template = parse(TEMPLATE).unwrap();
+  let data_sets = [
+    r##"{"title": "T", "items": [{"name": "a"}, {"name": "b<"}], "flag": true, "owner": {"name": "o"}, "name": "n\nm", "html": "<b>"}"##,
+    r##"{"title": "T", "items": [], "flag": false, "owner": false}"##,
+    r##"{}"##,
+  ];
+
+  for data in data_sets.iter() {
+    let data = Json::from_str(data).unwrap();
+    let (mut expected, mut generated) = (vec![], vec![]);
+
+    template.eval(&data, &mut expected, &eval_context()).unwrap();
+    render(&data, &mut generated, &eval_context()).unwrap();
+
+    assert_eq!(String::from_utf8(generated).unwrap(), String::from_utf8(expected).unwrap());
+  }
+}
+
+#[test]
+fn runtime_rendered_expressions() {
+  let template = parse(TEMPLATE).unwrap();
+  let code = compile_to_rust(&template, "render", &eval_context()).unwrap();
+
+  // helpers other than non overridden builtins, decorators and partial blocks are evaluated at runtime
+  assert!(code.contains("\"upper\""));
+  assert!(code.contains("\"unless\""));
+  assert!(code.contains("\"inline\""));
+  assert!(code.contains("\"frame\""));
+  assert!(!code.contains("\"info\""));
+}
+
+#[test]
+fn recursive_partial() {
+  let mut context = EvalContext::new();
+  context.register_partial("loop".to_string(), parse("{{> loop}}").unwrap());
+
+  assert_eq!(compile_to_rust(&"{{> loop}}".parse::<Template>().unwrap(), "f", &context), Err(CodegenError::RecursivePartial("loop".to_string())));
+}
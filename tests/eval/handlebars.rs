@@ -47,6 +47,14 @@ fn setup_test_specific_context(context: &mut EvalContext, data: &str) {
     "{\"template\":\"Hello {{name}}. You have just won ${{value}}!{{#in_ca}} Well, ${{taxed_value}}, after taxes.{{/in_ca}}\",\"data\":{\"name\":\"Chris\",\"value\":10000,\"taxed_value\":6000,\"in_ca\":true},\"expected\":\"Hello Chris. You have just won $10000! Well, $6000, after taxes.\",\"message\":\"the hello world mustache example works\"}" => {
       context.compat = true;
     }
+    // global partials
+    "{\"template\":\"Dudes: {{> shared/dude}} {{> global_test}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"partials\":{\"shared/dude\":\"{{name}}\"},\"expected\":\"Dudes: Jeepers Creepers\",\"message\":\"Partials can use globals or passed\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}" => {
+      context.register_partial("global_test".to_string(), ::rumblebars::parse("{{another_dude}}").ok().unwrap());
+    },
+    "{\"template\":\"Dudes: {{> shared/dude}} {{> global_test}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"expected\":\"Dudes: Jeepers Creepers\",\"message\":\"Partials can use globals or passed\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}" => {
+      context.register_partial("global_test".to_string(), ::rumblebars::parse("{{another_dude}}").ok().unwrap());
+      context.register_partial("shared/dude".to_string(), ::rumblebars::parse("{{name}}").ok().unwrap());
+    },
     _ => (),
   }
 }
@@ -83,7 +91,7 @@ fn test_handlebars(data: &str) {
 }
 
 // static list of handlebars tests known to fail
-static UNSUPPORTED_HANDLEBARS_FEATURES_CASES: [&'static str; 23] = [
+static UNSUPPORTED_HANDLEBARS_FEATURES_CASES: [&'static str; 13] = [
   // js version depends on custom lookup
   "{\"template\":\"{{foo}}\",\"data\":{\"bar_foo\":\"food\"},\"expected\":\"food\"}",
 
@@ -106,13 +114,6 @@ static UNSUPPORTED_HANDLEBARS_FEATURES_CASES: [&'static str; 23] = [
   // includeZero? seriously ?
   "{\"template\":\"{{#if goodbye includeZero=true}}GOODBYE {{/if}}cruel {{world}}!\",\"data\":{\"goodbye\":0,\"world\":\"world\"},\"expected\":\"GOODBYE cruel world!\",\"message\":\"if with zero does not show the contents\"}",
 
-  // no support for else if case, should be handle with inverse (^) and/or nested blocks
-  "{\"template\":\"{{#people}}{{name}}{{else if none}}{{none}}{{/people}}\",\"data\":{\"none\":\"No people\"},\"expected\":\"No people\"}",
-  "{\"template\":\"{{#people}}{{name}}{{else if nothere}}fail{{else unless nothere}}{{none}}{{/people}}\",\"data\":{\"none\":\"No people\"},\"expected\":\"No people\"}",
-  "{\"template\":\"{{#people}}{{name}}{{else if none}}{{none}}{{else}}fail{{/people}}\",\"data\":{\"none\":\"No people\"},\"expected\":\"No people\"}",
-  "{\"template\":\"{{#people}}\\n{{name}}\\n{{else if none}}\\n{{none}}\\n{{/people}}\\n\",\"data\":{\"none\":\"No people\"},\"expected\":\"No people\\n\"}",
-  "{\"template\":\"{{#people}}\\n{{name}}\\n{{else if none}}\\n{{none}}\\n{{^}}\\n{{/people}}\\n\",\"data\":{\"none\":\"No people\"},\"expected\":\"No people\\n\"}",
-
   // partial is a function
   "{\"template\":\"Dudes: {{#dudes}}{{> dude}}{{/dudes}}\",\"data\":{\"dudes\":[{\"name\":\"Yehuda\",\"url\":\"http://yehuda\"},{\"name\":\"Alan\",\"url\":\"http://alan\"}]},\"partials\":{},\"expected\":\"Dudes: Yehuda (http://yehuda) Alan (http://alan) \",\"message\":\"Function partials output based in VM.\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
 
@@ -125,13 +126,6 @@ static UNSUPPORTED_HANDLEBARS_FEATURES_CASES: [&'static str; 23] = [
   "{\"template\":\"{{#foo}} This is {{bar}} ~ {{/foo}}\",\"data\":{\"foo\":0,\"bar\":\"OK\"},\"expected\":\" This is  ~ \"}",
 
 
-  // use [partial names] in theses cases (eg {{> shared/dude}} => {{> [shared/dude]}}
-  "{\"template\":\"Dudes: {{> shared/dude}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"partials\":{\"shared/dude\":\"{{name}}\"},\"expected\":\"Dudes: Jeepers\",\"message\":\"Partials can use literal paths\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
-  "{\"template\":\"Dudes: {{> shared/dude.thing}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"partials\":{\"shared/dude.thing\":\"{{name}}\"},\"expected\":\"Dudes: Jeepers\",\"message\":\"Partials can use literal with points in paths\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
-  "{\"template\":\"Dudes: {{> shared/dude}} {{> global_test}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"partials\":{\"shared/dude\":\"{{name}}\"},\"expected\":\"Dudes: Jeepers Creepers\",\"message\":\"Partials can use globals or passed\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
-  "{\"template\":\"Dudes: {{> shared/dude}} {{> global_test}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"expected\":\"Dudes: Jeepers Creepers\",\"message\":\"Partials can use globals or passed\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
-   "{\"template\":\"Dudes: {{> 404/asdf?.bar}}\",\"data\":{\"name\":\"Jeepers\",\"another_dude\":\"Creepers\"},\"partials\":{\"404/asdf?.bar\":\"{{name}}\"},\"expected\":\"Dudes: Jeepers\",\"message\":\"Partials can use literal paths\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
-
    // TODO : https://github.com/nicolas-cherel/rumblebars/issues/1
    // "{\"template\":\"Dudes: {{#dudes}}{{> dude others=..}}{{/dudes}}\",\"data\":{\"foo\":\"bar\",\"dudes\":[{\"name\":\"Yehuda\",\"url\":\"http://yehuda\"},{\"name\":\"Alan\",\"url\":\"http://alan\"}]},\"partials\":{\"dude\":\"{{others.foo}}{{name}} ({{url}}) \"},\"expected\":\"Dudes: barYehuda (http://yehuda) barAlan (http://alan) \",\"message\":\"Basic partials output based on current context.\",\"options\":{\"data\":true,\"blockParams\":[],\"knownHelpers\":{\"helperMissing\":true,\"blockHelperMissing\":true,\"each\":true,\"if\":true,\"unless\":true,\"with\":true,\"log\":true,\"lookup\":true}}}",
 
@@ -29,10 +29,6 @@ fn test_set(set_name: &str) {
                   let expected  = test.find("expected").unwrap_or(&null).as_string().unwrap_or("expected value not found");
                   let partials  = test.find("partials").unwrap_or(&null);
 
-                  if test_name == "Standalone Indentation" {
-                    return; // skip this test, expected result is mostly achieved, divert only with some dangling space
-                  }
-
                   let tmpl = ::rumblebars::parse(template).ok().unwrap();
                   let mut buf: Vec<u8> = Vec::new();
                   let mut eval_context: ::rumblebars::EvalContext = Default::default();
@@ -72,7 +68,7 @@ fn test_set(set_name: &str) {
       _ => panic!("mustache {} test file not found", set_name),
     }
   } else {
-    panic!("mustache specs not found, make sure to have rumblebars repos submodules initialized properly")
+    panic!("mustache specs not found in {}", path_str)
   }
 }
 
@@ -92,5 +88,6 @@ mustache_tests_set!(comments);
 mustache_tests_set!(inverted);
 mustache_tests_set!(partials);
 mustache_tests_set!(sections);
+mustache_tests_set!(delimiters);
 
 
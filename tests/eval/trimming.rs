@@ -175,3 +175,42 @@ mod blocks {
 
 }
 
+
+mod standalone {
+  use super::test_eq_expected;
+  use serialize::json::Json;
+  use rumblebars::{eval, parse, EvalContext};
+
+  #[test]
+  fn block_lines() {
+    test_eq_expected("|\n  {{#a}}\n  b\n  {{else}}\n  c\n  {{/a}}\n|", r##"{"a": true}"##, "|\n  b\n|");
+  }
+
+  #[test]
+  fn last_line() {
+    test_eq_expected("|\n  {{#a}}b{{/a}}\n  {{! c }}", r##"{"a": true}"##, "|\n  b\n");
+  }
+
+  #[test]
+  fn not_alone() {
+    test_eq_expected("| {{#a}}\n{{/a}} |", r##"{"a": true}"##, "| \n |");
+  }
+
+  #[test]
+  fn trimmed_line() {
+    test_eq_expected("|\n  {{~#a}}\n  b\n  {{/a}}\n|", r##"{"a": true}"##, "|  b\n|");
+  }
+
+  #[test]
+  fn partial_indent() {
+    let json = Json::from_str(r##"{"content": "<\n->"}"##).ok().unwrap();
+    let tmpl = parse("\\\n {{>partial}}\n/\n").ok().unwrap();
+    let mut eval_context: EvalContext = Default::default();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval_context.register_partial("partial".to_string(), parse("|\n{{{content}}}\n|\n").ok().unwrap());
+    eval(&tmpl, &json, &mut buf, &eval_context).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), "\\\n |\n <\n->\n |\n/\n");
+  }
+}
@@ -11,6 +11,7 @@ use rumblebars::HelperOptions;
 use rumblebars::HBEvalResult;
 use rumblebars::SafeWriting;
 use rumblebars::HBData;
+use rumblebars::BlockEnvironment;
 
 #[test]
 fn if_true() {
@@ -369,6 +370,27 @@ fn helper_globals() {
   assert_eq!(String::from_utf8(buf).unwrap(), "value : stored value");
 }
 
+fn plain_globs(_: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
+  let val = "stored value".to_string();
+  let mut vars = HashMap::new();
+  vars.insert("val", &val as &HBData);
+  options.render_fn_with_globals(out, &vars)
+}
+
+#[test]
+fn helper_globals_dont_shadow_context() {
+  let json = Json::from_str(r##"{"val": "context value"}"##).ok().unwrap();
+  let tmpl = parse(r##"value : {{#plain_globs}}{{val}}{{/plain_globs}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("plain_globs".to_string(), Box::new(plain_globs));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "value : context value");
+}
+
 fn for_root_check(params: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
   match (params.first(), params.len()) {
     (Some(&p), 1) => options.render_fn_with_context(p, out),
@@ -451,4 +473,325 @@ fn helper_quickdoc_closure() {
   eval(&tmpl, &json, &mut buf, &eval_ctxt).ok();
 
   assert_eq!(String::from_utf8(buf).unwrap(), "pouet∂ pouet");
-}
\ No newline at end of file
+}
+
+fn concat(params: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
+  let sep = options.option_by_name(&"sep".to_string());
+
+  for (i, p) in params.iter().enumerate() {
+    if let (Some(s), true) = (sep, i > 0) {
+      try!(s.write_value(out));
+    }
+    try!(p.write_value(out));
+  }
+
+  Ok(())
+}
+
+#[test]
+fn helper_sub_expression() {
+  let json = Json::from_str(r##"{"a": "A", "b": "B", "c": {"d": "D"}}"##).ok().unwrap();
+  let tmpl = parse(r##"{{concat (concat a "-" b) c.d (concat (concat c.d) ) sep=(concat "<" ">") }}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("concat".to_string(), Box::new(concat));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "A-B&lt;&gt;D&lt;&gt;D");
+}
+
+#[test]
+fn helper_literals() {
+  let json = Json::from_str(r##"{"a": "A", "b": "B"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{{concat 1 -2 1.5 true false null undefined 'it\'s' "say \"hi\"" '' a sep='|'}}} {{concat a b sep=-0.5}} {{#if 0}}ko{{else}}ok{{/if}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("concat".to_string(), Box::new(concat));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), r##"1|-2|1.5|true|false|||it's|say "hi"||A A-0.5B ok"##);
+}
+
+#[test]
+fn helper_sub_expression_condition() {
+  let json = Json::from_str(r##"{"full": "f", "empty": ""}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#if (v full)}}ok{{else}}ko{{/if}} {{#if (v empty)}}ok{{else}}ko{{/if}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("v".to_string(), Box::new(v));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "ok ko");
+}
+
+#[test]
+fn helper_sub_expression_escaping() {
+  let json = Json::from_str(r##"{"html": "<b>"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{v (v html)}} {{{v (v html)}}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("v".to_string(), Box::new(v));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "&lt;b&gt; <b>");
+}
+
+// returns its first param, with its type, when called as a sub expression
+fn first(params: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
+  match params.first() {
+    Some(p) if options.is_sub_expression() => { options.return_value(p.json_value()); Ok(()) },
+    Some(p) => p.write_value(out),
+    None => Ok(()),
+  }
+}
+
+#[test]
+fn helper_sub_expression_falsy_value() {
+  let json = Json::from_str(r##"{"k": false, "a": false, "z": 0, "t": true}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#if (lookup . "k")}}ko{{/if}}{{#if (first a)}}ko{{/if}}{{#unless (first z)}}ok{{/unless}} {{#unless (lookup . "t")}}ko{{else}}ok{{/unless}} {{first (first z)}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("first".to_string(), Box::new(first));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "ok ok 0");
+}
+
+#[test]
+fn helper_sub_expression_object_value() {
+  let json = Json::from_str(r##"{"o": {"a": 1, "b": [2, 3]}, "name": "o"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#with (lookup . name)}}{{a}}{{/with}} {{#each (first o)}}{{@key}}={{.}};{{/each}} {{#each (lookup o "b")}}{{@index}}:{{.}};{{/each}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("first".to_string(), Box::new(first));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "1 a=1;b=2,3; 0:2;1:3;");
+}
+
+#[test]
+fn partial_sub_expression_option() {
+  let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{> p name=(v a)}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("v".to_string(), Box::new(v));
+  eval_ctxt.register_partial("p".to_string(), parse("hello {{name}}").ok().unwrap());
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "hello A");
+}
+
+#[test]
+fn if_else_chain() {
+  let tmpl = parse(r##"{{#if a}}A{{else if b}}B{{else unless c}}C{{else}}D{{/if}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+
+  for &(data, expected) in [
+    (r##"{"a": true, "b": true}"##, "A"),
+    (r##"{"b": true}"##, "B"),
+    (r##"{"c": false}"##, "C"),
+    (r##"{"c": true}"##, "D"),
+  ].iter() {
+    let json = Json::from_str(data).ok().unwrap();
+    let mut buf: Vec<u8> = Vec::new();
+
+    eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+  }
+}
+
+#[test]
+fn each_else_chain() {
+  let json = Json::from_str(r##"{"items": [], "fallback": "none"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#each items}}{{.}}{{else with fallback}}{{.}}{{/each}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "none");
+}
+
+#[test]
+fn each_block_params() {
+  let json = Json::from_str(r##"{"users": [{"name": "a", "tags": ["x", "y"]}, {"name": "b", "tags": ["z"]}]}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#each users as |user i|}}{{#each tags as |tag|}}{{i}}:{{user.name}}:{{tag}} {{/each}}{{/each}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "0:a:x 0:a:y 1:b:z ");
+}
+
+#[test]
+fn each_object_block_params() {
+  let json = Json::from_str(r##"{"a": "A", "b": "B"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#each . as |value key|}}{{key}}={{value}} {{/each}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "a=A b=B ");
+}
+
+#[test]
+fn with_block_params() {
+  let json = Json::from_str(r##"{"person": {"name": "n"}, "name": "root"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#with person as |p|}}{{name}} {{p.name}}{{/with}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "n n");
+}
+
+#[test]
+fn block_params_standalone() {
+  let json = Json::from_str(r##"{"person": {"name": "n"}}"##).ok().unwrap();
+  let tmpl = parse("{{#with person as |p| }}\n    {{p.name}}\n{{/with}}\n").ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "    n\n");
+}
+
+#[test]
+fn block_params_scope() {
+  let json = Json::from_str(r##"{"xs": [{"item": "own1"}, {"item": "own2"}], "item": "root", "ys": ["y"]}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#each xs as |item|}}{{> p}}|{{> q item}}|{{#with .}}{{item.item}}{{/with}} {{/each}}{{item}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_partial("p".to_string(), parse("{{item}}").ok().unwrap());
+  eval_ctxt.register_partial("q".to_string(), parse("{{#each @root.ys as |y|}}{{y}}{{item}}{{/each}}").ok().unwrap());
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "own1|y|own1 own2|y|own2 root");
+}
+
+fn pair(_: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
+  let (left, right) = ("left".to_string(), "right".to_string());
+  let mut vars = HashMap::new();
+  options.bind_block_params(&[&left, &right], &mut vars);
+  options.render_fn_with_block_params(out, &vars)
+}
+
+#[test]
+fn helper_block_params() {
+  let json = Json::from_str(r##"{"left": "shadowed"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#pair as |left right|}}{{left}}/{{right}}{{/pair}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("pair".to_string(), Box::new(pair));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "left/right");
+}
+
+#[test]
+fn raw_block() {
+  let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{a}} {{{{raw}}}}{{a}} {{#if a}}\{{/if}}{{{{/raw}}}} {{a}}"##).ok().unwrap();
+  let eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), r##"A {{a}} {{#if a}}\{{/if}} A"##);
+}
+
+fn shout(_: &[&HBData], options: &HelperOptions, out: &mut SafeWriting, _: &EvalContext) -> HBEvalResult {
+  let mut buf: Vec<u8> = Vec::new();
+  try!(options.render_fn(&mut SafeWriting::Unsafe(&mut buf)));
+  out.into_unsafe().write_all(String::from_utf8_lossy(&buf).to_uppercase().as_bytes())
+}
+
+#[test]
+fn helper_raw_block() {
+  let json = Json::from_str(r##"{"a": "A"}"##).ok().unwrap();
+  let tmpl = parse(r##"{{{{shout}}}}<{{a}}>{{{{/shout}}}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_helper("shout".to_string(), Box::new(shout));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "<{{A}}>");
+}
+
+fn title(params: &[&HBData], _: &HelperOptions, environment: &mut BlockEnvironment, _: &EvalContext) -> HBEvalResult {
+  let mut buf: Vec<u8> = Vec::new();
+  if let Some(p) = params.first() {
+    try!(p.write_value(&mut SafeWriting::Unsafe(&mut buf)));
+  }
+  environment.insert_data("title".to_string(), Box::new(String::from_utf8_lossy(&buf).into_owned()));
+  Ok(())
+}
+
+#[test]
+fn decorator_data() {
+  let json = Json::from_str(r##"{"l": [1, 2]}"##).ok().unwrap();
+  let tmpl = parse("{{* title \"T\"}}\n{{@title}}{{#each l}} {{* title .}}{{@title}}{{/each}} {{@title}}").ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_decorator("title".to_string(), Box::new(title));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "T 1 2 T");
+}
+
+#[test]
+fn decorator_partials() {
+  let json = Json::from_str(r##"{"a": {"x": "X", "src": "<{{x}}>"}}"##).ok().unwrap();
+  let tmpl = parse(r##"{{#a}}{{* template "p" src}}{{> p}}{{#if x}}{{> p}}{{/if}}{{/a}}{{> p}}"##).ok().unwrap();
+  let mut eval_ctxt: EvalContext = Default::default();
+  let mut buf: Vec<u8> = Vec::new();
+
+  eval_ctxt.register_partial("p".to_string(), parse("p").ok().unwrap());
+  eval_ctxt.register_decorator("template".to_string(), Box::new(
+    |params, _, environment, _| {
+      let mut names = params.iter().map(|p| {
+        let mut buf: Vec<u8> = Vec::new();
+        p.write_value(&mut SafeWriting::Unsafe(&mut buf)).map(|_| String::from_utf8_lossy(&buf).into_owned())
+      });
+
+      match (names.next(), names.next()) {
+        (Some(name), Some(src)) => {
+          environment.register_partial(try!(name), parse(&try!(src)).ok().unwrap());
+          Ok(())
+        },
+        _ => Ok(()),
+      }
+  }));
+
+  eval(&tmpl, &json, &mut buf, &eval_ctxt).unwrap();
+
+  assert_eq!(String::from_utf8(buf).unwrap(), "<X><X>p");
+}
@@ -2,7 +2,10 @@ extern crate rumblebars;
 
 use rumblebars::Template;
 use rumblebars::ParseError;
+use rumblebars::ParseErrorKind;
+use rumblebars::Span;
 use rumblebars::parse;
+use rumblebars::parse_recovering;
 
 static BIG: &'static str = r##"
     conten content
@@ -34,7 +37,7 @@ static BIG: &'static str = r##"
 fn big_no_err() {
   let t = BIG.parse::<Template>();
 
-  assert!((match t { Ok(_) => true, Err((_, mesg)) => { println!("{}", mesg.unwrap_or("".to_string())); false }}))
+  assert!((match t { Ok(_) => true, Err(e) => { println!("{}", e); false }}))
 }
 
 #[test]
@@ -58,15 +61,642 @@ fn medium() {
 </ul>
 "##.parse::<Template>();
 
-  assert!((match t { Ok(_) => true, Err((_, mesg)) => { println!("{}", mesg.unwrap_or("".to_string())); false }}))
+  assert!((match t { Ok(_) => true, Err(e) => { println!("{}", e); false }}))
 }
 
 #[test]
 fn fail_block() {
-  assert!(match parse("{{#o}}{{/t}}") { Err((ParseError::UnmatchedBlock, _)) => true, Err(_) => false, Ok(_) => false })
+  assert!(match parse("{{#o}}{{/t}}") { Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, .. }) => true, Err(_) => false, Ok(_) => false })
+}
+
+#[test]
+fn fail_sub_expression_base() {
+  assert!(match parse("{{(helper a)}}") { Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => true, Err(_) => false, Ok(_) => false })
 }
 
 #[test]
 fn fail_nested_block() {
-  assert!(match parse("{{#o}}{{/i}}{{/o}}") { Err((ParseError::UnmatchedBlock, _)) => true, Err(_) => false, Ok(_) => false })
+  assert!(match parse("{{#o}}{{/i}}{{/o}}") { Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, .. }) => true, Err(_) => false, Ok(_) => false })
+}
+
+#[test]
+fn fail_block_span() {
+  match parse("{{#o}}\n  {{/t}}") {
+    Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, span, .. }) => assert_eq!(span, Span { start: 9, end: 15, line: 2, column: 3 }),
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn fail_block_close_span() {
+  match parse("{{a}}\n{{b}} {{/c}} {{d}}") {
+    Err(ParseError { kind: ParseErrorKind::UnexpectedBlockClose, span, .. }) => {
+      assert_eq!((span.start, span.end, span.line, span.column), (12, 18, 2, 7));
+    },
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn fail_block_message() {
+  match parse("{{#each a}}\n  {{#if b}}\n  {{/each}}\n{{/if}}") {
+    Err(e) => {
+      assert_eq!(e.expected, Some("if".to_string()));
+      assert_eq!(e.found, Some("each".to_string()));
+      assert_eq!(e.opened_at.map(|s| (s.line, s.column)), Some((2, 3)));
+      assert_eq!(format!("{}", e), "line 3, column 3: ‘each’ does not match ‘if’ opened at line 2, column 3\n  {{/each}}\n  ^^^^^^^^^");
+    },
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn span_after_escapes() {
+  match parse("\\{{a}} \\\\ {{/b}}") {
+    Err(ParseError { kind: ParseErrorKind::UnexpectedBlockClose, span, .. }) => assert_eq!((span.start, span.end, span.column), (10, 16, 11)),
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn error_with_template_name() {
+  match Template::new_with_name("{{/a}}", "index.hbs") {
+    Err(e) => {
+      assert_eq!(e.template_name, Some("index.hbs".to_string()));
+      assert_eq!(format!("{}", e), "index.hbs, line 1, column 1: ‘a’ does not close any block\n{{/a}}\n^^^^^^");
+    },
+    Ok(_) => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn from_reader() {
+  let t = Template::from_reader(::std::io::Cursor::new("a {{#b}}c{{/b}}")).unwrap();
+  assert_eq!(t.to_string(), "a {{#b}}c{{/b}}");
+}
+
+#[test]
+fn from_reader_invalid_utf8() {
+  match Template::from_reader(&b"a {{b}} \xff\xfe"[..]) {
+    Err(ParseError { kind: ParseErrorKind::ReadError(ref e), .. }) => assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidData),
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn from_file() {
+  use std::io::Write;
+
+  let path = ::std::env::temp_dir().join("rumblebars_from_file.hbs");
+  ::std::fs::File::create(&path).unwrap().write_all(b"{{#a}}\n{{/b}}").unwrap();
+
+  match Template::from_file(&path) {
+    Err(e) => {
+      assert_eq!(e.kind, ParseErrorKind::UnmatchedBlock);
+      assert_eq!(e.template_name, Some(path.display().to_string()));
+    },
+    Ok(_) => panic!("should not parse"),
+  }
+
+  ::std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn from_missing_file() {
+  match Template::from_file("does/not/exist.hbs") {
+    Err(e) => {
+      use std::error::Error;
+
+      let source = e.source().and_then(|s| s.downcast_ref::<::std::io::Error>()).map(|s| s.kind());
+      assert_eq!(source, Some(::std::io::ErrorKind::NotFound));
+      assert!(format!("{}", e).starts_with("does/not/exist.hbs, could not read template: "));
+    },
+    Ok(_) => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn error_chaining() {
+  fn load(template: &str) -> Result<Template, Box<::std::error::Error>> {
+    let t = try!(template.parse::<Template>());
+    Ok(t)
+  }
+
+  assert!(load("{{#a}}{{/a}}").is_ok());
+  assert!(load("{{#a}}{{/b}}").is_err());
+}
+
+#[test]
+fn fail_unclosed_block() {
+  match parse("{{#a}}\n{{#b}}{{/b}}") {
+    Err(ParseError { kind: ParseErrorKind::UnclosedBlock, expected, span, .. }) => {
+      assert_eq!(expected, Some("a".to_string()));
+      assert_eq!((span.line, span.column), (1, 1));
+    },
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn recover_all_errors() {
+  let (_, errors) = parse_recovering("{{/a}}\n{{#b}}{{/c}}\n{{#d}}");
+  let found: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.span.line)).collect();
+
+  assert_eq!(found, vec![
+    (ParseErrorKind::UnexpectedBlockClose, 1),
+    (ParseErrorKind::UnmatchedBlock, 2),
+    (ParseErrorKind::UnclosedBlock, 3),
+    (ParseErrorKind::UnclosedBlock, 2),
+  ]);
+}
+
+#[test]
+fn recover_closes_left_open_blocks() {
+  let (recovered, errors) = parse_recovering("{{#a}}{{#b}}{{#c}}x{{/a}}y");
+  let expected = parse("{{#a}}{{#b}}{{#c}}x{{/c}}{{/b}}{{/a}}y").unwrap();
+
+  assert_eq!(errors.iter().map(|e| (e.kind.clone(), e.expected.clone())).collect::<Vec<_>>(), vec![
+    (ParseErrorKind::UnmatchedBlock, Some("c".to_string())),
+    (ParseErrorKind::UnclosedBlock, Some("b".to_string())),
+  ]);
+  assert_eq!(format!("{:?}", recovered.entries), format!("{:?}", expected.entries));
+}
+
+#[test]
+fn recover_valid_template() {
+  let (recovered, errors) = parse_recovering(BIG);
+
+  assert!(errors.is_empty());
+  assert_eq!(format!("{:?}", recovered.entries), format!("{:?}", parse(BIG).unwrap().entries));
+}
+
+#[test]
+fn fail_unclosed_sub_expression() {
+  match parse("{{a (b c}}") {
+    Err(ParseError { kind: ParseErrorKind::InvalidExpression, found, .. }) => assert_eq!(found, Some("{{a (b c}}".to_string())),
+    _ => panic!("should not parse"),
+  }
+  assert!(parse("{{a b)}}").is_err());
+}
+
+#[test]
+fn fail_unexpected_else() {
+  for template in ["a{{else}}b", "{{#a}}{{else}}{{else}}{{/a}}", "{{#a}}{{^}}{{else if b}}{{/a}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::UnexpectedElse, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn fail_option_without_value() {
+  for template in ["{{x a=}}", "{{x a= }}", "{{#x a=}}{{/x}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn fail_unterminated_string() {
+  for template in ["{{x \"unterminated}}", "{{x 'unterminated}}", "{{x a=\"b}}", "{{x \"b\\\"}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn fail_misplaced_block_params() {
+  for template in ["{{x as |a|}}", "{{{x as |a|}}}", "{{> p as |a|}}", "{{#> p as |a|}}{{/p}}", "{{#each a}}{{/each as |b|}}", "{{{{raw as |a|}}}}{{{{/raw}}}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn fail_empty_block_params() {
+  for template in ["{{#each a as | |}}{{/each}}", "{{#each a as ||}}{{/each}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn fail_else_chain_without_condition() {
+  for template in ["{{#if a}}x{{else if}}y{{/if}}", "{{#if a}}x{{else unless ~}}y{{/if}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidExpression, .. }) => (),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+}
+
+#[test]
+fn else_chain_closed_by_block() {
+  let (_, errors) = parse_recovering("{{#a}}{{else if b}}{{else if c}}{{else}}{{/a}}");
+  assert!(errors.is_empty());
+
+  match parse("{{#a}}{{else if b}}{{/if}}") {
+    Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, expected, found, .. }) => {
+      assert_eq!((expected, found), (Some("a".to_string()), Some("if".to_string())));
+    },
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn raw_block() {
+  let t = parse("{{{{raw}}}} {{a}} {{{{b}}}}{{{{/b}}}} {{{{/raw}}}}").unwrap();
+  let entries = format!("{:?}", t.entries);
+
+  assert!(entries.contains(r#"block: Some([Raw(" {{a}} {{{{b}}}}{{{{/b}}}} ")])"#));
+  assert!(entries.contains("raw_block: true"));
+
+  match parse("{{{{raw}}}} {{a}} {{{{/other}}}}") {
+    Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, .. }) => (),
+    _ => panic!("should not parse"),
+  }
+  match parse("{{{{raw}}}} {{a}}") {
+    Err(ParseError { kind: ParseErrorKind::UnclosedBlock, .. }) => (),
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn fail_invalid_delimiters() {
+  for template in ["{{=<%=}}", "{{=<% % %>=}}", "{{= =}}"].iter() {
+    match parse(template) {
+      Err(ParseError { kind: ParseErrorKind::InvalidDelimiters, span, .. }) => assert_eq!((span.start, span.end), (0, template.len())),
+      _ => panic!("{} should not parse", template),
+    }
+  }
+  assert!(::rumblebars::parse_with_delimiters("{{a}}", "<%", "").is_err());
+}
+
+#[test]
+fn custom_delimiters_error_span() {
+  match parse("{{=<% %>=}}\n<%#a%>{{b}}<%/c%>") {
+    Err(ParseError { kind: ParseErrorKind::UnmatchedBlock, span, .. }) => {
+      assert_eq!((span.line, span.column, span.start, span.end), (2, 12, 23, 29));
+    },
+    _ => panic!("should not parse"),
+  }
+}
+
+#[test]
+fn custom_delimiters_entry_points() {
+  let (_, errors) = ::rumblebars::parse_recovering_with_delimiters("<%#a%> <%=x=%> <%/b%>", "<%", "%>");
+  assert_eq!(errors.iter().map(|e| (e.kind.clone(), e.span.start, e.span.end)).collect::<Vec<_>>(), vec![
+    (ParseErrorKind::InvalidDelimiters, 7, 14), (ParseErrorKind::UnmatchedBlock, 15, 21), (ParseErrorKind::UnclosedBlock, 0, 6),
+  ]);
+
+  match ::rumblebars::parse_with_name_and_delimiters("a\n<%#b%>", "page", "<%", "%>") {
+    Err(ParseError { kind: ParseErrorKind::UnclosedBlock, template_name: Some(ref name), span, .. }) => {
+      assert_eq!((name.as_ref(), span.start, span.end), ("page", 2, 8));
+    },
+    _ => panic!("should not parse"),
+  }
+
+  let t = Template::from_reader_with_delimiters(::std::io::Cursor::new("{{a}} <%#b%>c<%/b%>"), "<%", "%>").unwrap();
+  assert_eq!(t.entries.len(), 2);
+}
+
+#[test]
+fn print_round_trip() {
+  let templates = [
+    BIG,
+    "{{#a b c=d}}x{{else if e}}y{{else}}z{{/a}}",
+    "{{^a}}x{{else}}y{{/a}} {{#a}}{{^}}b{{/a}}",
+    "{{{{raw}}}} {{a}} \\{{b}} {{{{/raw}}}}",
+    "a {{~b~}} {{{c}}} {{&d}} {{~{e}~}}",
+    "{{> p}} a  {{~> p}} {{> (f a) b x=\"y\"}} {{> shared/p.x}} {{#> layout}}{{> @partial-block}}{{/layout}}",
+    "{{* deco a}}{{#*inline \"p\"}}in{{/inline}}",
+    "{{h 1 -2.5 true false null undefined \"it's\" 'say \"hi\"' (s a=1)}}",
+    "{{h \"a\\\\b\" 'c\\\\\"' \"d\\\"'\"}}",
+    "\\{{a}} \\\\{{b}} \\\\\\{{c}} { } {\n{ {{d}}\\",
+    "a\n  {{#b}}\n  c\n  {{/b}}\n  {{> p}}\n{{#d}} {{/d}} \n{{#e}}\n  {{/e}}",
+    "{{#a}}\n {{! gone }}\n{{/a}}\n {{#b}}{{/b}}",
+    "{{../a}} {{./b}} {{a.[b c].[1]}} {{[this]}} {{[1a]}} {{@index}} {{[a]b]}} {{#each l as |v k|}}{{/each}}",
+  ];
+
+  for template in templates.iter() {
+    let t = parse(template).unwrap();
+    let printed = ::rumblebars::print(&t);
+
+    match parse(&printed) {
+      Ok(reparsed) => assert_eq!(format!("{:?}", reparsed.entries), format!("{:?}", t.entries)),
+      Err(e) => panic!("{:?} printed as {:?} does not parse: {}", template, printed, e),
+    }
+  }
+}
+
+#[test]
+fn print_keeps_tags_standalone() {
+  let t = parse("a\n  {{#b}}\n  c {{d}}\n  {{/b}}\n").unwrap();
+  assert_eq!(::rumblebars::print(&t), "a\n{{#b}}  c {{d}}\n{{/b}}");
+
+  let t = parse("{{! c }}{{#b}} \n{{/b}}").unwrap();
+  assert_eq!(t.to_string(), "{{#b}}{{!}} \n{{/b}}");
+}
+
+#[test]
+fn format_blocks() {
+  let template = "{{#if a}}\n    <b>\n  {{ c  d=e }}\n   \n{{#each l}}{{#b}}\n\tf\n{{/b}}{{/each}}\n  {{else if g}}\nh\n    {{/if}}";
+
+  assert_eq!(::rumblebars::format(template).unwrap(),
+    "{{#if a}}\n      <b>\n    {{c d=e}}\n\n  {{#each l}}{{#b}}\n    f\n  {{/b}}{{/each}}\n{{else if g}}\n  h\n{{/if}}");
+}
+
+#[test]
+fn format_keeps_verbatim() {
+  let template = "  <p>\n{{#a}}\n{{{{raw}}}}\n {{ b }}\n{{{{/raw}}}}\n{{!-- c\n   d --}}\n{{/a}}";
+
+  assert_eq!(::rumblebars::format(template).unwrap(),
+    "  <p>\n{{#a}}\n  {{{{raw}}}}\n {{ b }}\n{{{{/raw}}}}\n  {{!-- c\n   d --}}\n{{/a}}");
+}
+
+#[test]
+fn format_custom_delimiters() {
+  let template = "{{#a}}\n{{=<% %>=}}\n<%#b%>\n<% c %> {{d}}\n<%{ e }%><%{{raw}}%> <% f %> <%{{/raw}}%>\n<%/b%>\n<%= {{ }} =%>\n{{/a}}";
+
+  assert_eq!(::rumblebars::format(template).unwrap(),
+    "{{#a}}\n  {{=<% %>=}}\n  <%#b%>\n    <%c%> {{d}}\n    <%{e}%><%{{raw}}%> <% f %> <%{{/raw}}%>\n  <%/b%>\n  <%= {{ }} =%>\n{{/a}}");
+}
+
+#[test]
+fn format_is_stable() {
+  let formatted = ::rumblebars::format("{{#a}}\n{{#b}}\n c\n  {{^}}\n{{> d}}\n {{/b}}\n{{/a}}").unwrap();
+
+  assert_eq!(formatted, "{{#a}}\n  {{#b}}\n     c\n  {{else}}\n    {{> d}}\n  {{/b}}\n{{/a}}");
+  assert_eq!(::rumblebars::format(&formatted).unwrap(), formatted);
+  assert!(::rumblebars::format("{{#a}}{{/b}}").is_err());
+}
+
+#[test]
+fn format_tabs() {
+  let template = "{{#if a}}\n\t\t<b>\n\t{{x}}\n{{/if}}";
+  assert_eq!(::rumblebars::format(template).unwrap(), template);
+
+  assert_eq!(::rumblebars::format("\t{{#if a}}\n<b>\n  {{x}}\n{{/if}}").unwrap(), "\t{{#if a}}\n\t\t<b>\n\t\t  {{x}}\n\t{{/if}}");
+}
+
+#[test]
+fn format_keeps_output() {
+  let templates = [
+    "{{#each  items }}\n  <li>{{ name }}</li>\n    {{^}}\n  none\n   {{/each}}\n",
+    "<p>\n{{#if  a}}\n  {{#with  b as |c| }}\n    {{c.d}} {{! note }}\n  {{/with}}\n {{else  if e}}\n  e\n{{/if }}\n</p>",
+  ];
+  let data = [
+    r##"{"items": [{"name": "x"}, {"name": "y"}], "a": true, "b": {"d": "d"}}"##,
+    r##"{"items": [], "a": false, "e": true}"##,
+  ];
+
+  for template in templates.iter() {
+    let formatted = ::rumblebars::format(template).unwrap();
+    assert!(&formatted != template);
+
+    for data in data.iter() {
+      let data = ::serialize::json::Json::from_str(data).unwrap();
+      let (mut expected, mut rendered) = (vec![], vec![]);
+      parse(template).unwrap().eval(&data, &mut expected, &Default::default()).unwrap();
+      parse(&formatted).unwrap().eval(&data, &mut rendered, &Default::default()).unwrap();
+
+      assert_eq!(String::from_utf8(rendered).unwrap(), String::from_utf8(expected).unwrap());
+    }
+  }
+}
+
+#[test]
+fn partial_names() {
+  use rumblebars::HBEntry;
+
+  let cases = [
+    ("{{> a]b/c}}", "a]b/c"),
+    ("{{~> shared/dude.thing x~}}", "shared/dude.thing"),
+    ("{{>@partial-block}}", "@partial-block"),
+    ("{{> [a b] x}}", "a b"),
+    ("{{> \"a b\"}}", "a b"),
+    ("{{#> lay/out}}{{/lay/out}}", "lay/out"),
+  ];
+
+  for &(template, name) in cases.iter() {
+    let t = parse(template).unwrap();
+    match *t.entries[0] {
+      HBEntry::Partial(ref exp) => assert_eq!((template, &exp.base[..]), (template, &[name.to_string()][..])),
+      _ => panic!("{} should parse as a partial", template),
+    }
+  }
+}
+
+#[test]
+fn visitor_default_recursion() {
+  use rumblebars::{Visitor, HBExpression};
+
+  #[derive(Default)]
+  struct Count { raw: usize, eval: usize, partial: usize, sub_expression: usize, else_block: usize }
+
+  impl Visitor for Count {
+    fn visit_raw(&mut self, _: &str) { self.raw += 1 }
+    fn visit_eval(&mut self, exp: &HBExpression) { self.eval += 1; ::rumblebars::walk_expression(self, exp) }
+    fn visit_partial(&mut self, exp: &HBExpression) { self.partial += 1; ::rumblebars::walk_expression(self, exp) }
+    fn visit_sub_expression(&mut self, exp: &HBExpression) { self.sub_expression += 1; ::rumblebars::walk_expression(self, exp) }
+    fn visit_else_block(&mut self, _: &::rumblebars::Entries) { self.else_block += 1 }
+  }
+
+  let t = parse("a{{#each l}}b{{> p (h (i))}}{{#> q}}c{{/q}}{{else}}{{d}}{{/each}}").unwrap();
+  let mut count = Count::default();
+  count.visit_template(&t);
+
+  assert_eq!((count.raw, count.eval, count.partial, count.sub_expression, count.else_block), (3, 1, 2, 2, 1));
+}
+
+#[test]
+fn template_references() {
+  let t = parse(r#"{{a.b}}{{#if c}}{{#each d as |e|}}{{> row e x=f}}{{h (i j)}}{{else}}{{k}}{{/each}}{{/if}}{{> (l)}}{{#> m}}{{> @partial-block}}{{/m}}"#).unwrap();
+
+  let names = |refs: Vec<::rumblebars::Reference>| refs.iter().map(|r| r.path.join(".")).collect::<Vec<_>>();
+  assert_eq!(names(t.paths()), vec!["a.b", "c", "d", "e", "f", "j", "k"]);
+  assert_eq!(names(t.helpers()), vec!["if", "each", "h", "i", "l"]);
+  assert_eq!(names(t.partials()), vec!["row", "m"]);
+
+  let h = &t.helpers()[2];
+  assert_eq!((h.blocks.clone(), h.block_params.clone()), (vec!["if".to_string(), "each".to_string()], vec!["e".to_string()]));
+  let k = &t.paths()[6];
+  assert_eq!((k.blocks.clone(), k.block_params.clone()), (vec!["if".to_string()], vec![]));
+}
+
+#[test]
+fn precompiled_round_trip() {
+  let t = parse(BIG).unwrap();
+  let loaded = Template::from_precompiled(&t.precompile().unwrap()).unwrap();
+  assert_eq!(format!("{:?}", loaded.entries), format!("{:?}", t.entries));
+
+  let t = parse("{{h 1 -0.5 true null undefined 'a' (s x=false)}} {{> p a}}").unwrap();
+  let loaded = Template::from_precompiled(&t.precompile().unwrap()).unwrap();
+  assert_eq!(format!("{:?}", loaded.entries), format!("{:?}", t.entries));
+}
+
+#[test]
+fn precompiled_version_mismatch() {
+  let precompiled = parse("{{a}}").unwrap().precompile().unwrap().replace(r#""version":1"#, r#""version":0"#);
+
+  match Template::from_precompiled(&precompiled) {
+    Err(::serialize::json::DecoderError::ApplicationError(message)) => assert!(message.contains("version 0")),
+    _ => panic!("version 0 should not load"),
+  }
+  assert!(Template::from_precompiled("{").is_err());
+}
+
+// tokens kind along with their source text
+fn tokens(template: &str) -> Vec<(::rumblebars::TokenKind, &str)> {
+  ::rumblebars::tokenize(template).map(|(kind, span)| (kind, &template[span.start..span.end])).collect()
+}
+
+#[test]
+fn tokenize_blocks() {
+  use rumblebars::TokenKind::{self, Mark, Expression};
+  use rumblebars::TagMark::{Block, BlockEnd};
+  use rumblebars::Token::{TokBlockExp, TokSimpleExp, TokBlockEndExp};
+  use rumblebars::HBToken::{TokPathEntry, TokParamStart, TokParamSep, TokBlockParams};
+
+  assert_eq!(tokens("{{#each a as |b|}}{{b}}{{/each}}"), vec![
+    (TokenKind::Template(TokBlockExp("{{#each a as |b|}}".to_string(), false)), "{{#each a as |b|}}"),
+    (Mark(Block), "#"),
+    (Expression(TokPathEntry("each".to_string())), "each"),
+    (Expression(TokParamStart), " "),
+    (Expression(TokPathEntry("a".to_string())), "a"),
+    (Expression(TokParamSep), " "),
+    (Expression(TokBlockParams("as |b|".to_string())), "as |b|"),
+    (TokenKind::Template(TokSimpleExp("{{b}}".to_string())), "{{b}}"),
+    (Expression(TokPathEntry("b".to_string())), "b"),
+    (TokenKind::Template(TokBlockEndExp("{{/each}}".to_string())), "{{/each}}"),
+    (Mark(BlockEnd), "/"),
+    (Expression(TokPathEntry("each".to_string())), "each"),
+  ]);
+}
+
+#[test]
+fn tokenize_else_chain() {
+  use rumblebars::TokenKind::{self, Mark, Expression};
+  use rumblebars::TagMark::{Else, InverseBlock};
+  use rumblebars::Token::{TokBlockExp, TokBlockElseChain, TokBlockElseCond, TokRaw};
+  use rumblebars::HBToken::{TokPathEntry, TokParamStart, TokNoWhiteSpaceAfter};
+
+  let kinds: Vec<_> = tokens("{{^a}}x{{else if b}}y{{else~}}z{{^}}").into_iter().skip(3).collect();
+  assert_eq!(kinds, vec![
+    (TokenKind::Template(TokRaw("x".to_string())), "x"),
+    (TokenKind::Template(TokBlockElseChain("{{else if b}}".to_string())), "{{else if b}}"),
+    (Mark(Else), "else"),
+    (Expression(TokPathEntry("if".to_string())), "if"),
+    (Expression(TokParamStart), " "),
+    (Expression(TokPathEntry("b".to_string())), "b"),
+    (TokenKind::Template(TokRaw("y".to_string())), "y"),
+    (TokenKind::Template(TokBlockElseCond("{{else~}}".to_string())), "{{else~}}"),
+    (Mark(Else), "else"),
+    (Expression(TokNoWhiteSpaceAfter), "~"),
+    (TokenKind::Template(TokRaw("z".to_string())), "z"),
+    (TokenKind::Template(TokBlockElseCond("{{^}}".to_string())), "{{^}}"),
+    (Mark(Else), "^"),
+  ]);
+  assert_eq!(tokens("{{^a}}")[..2], [
+    (TokenKind::Template(TokBlockExp("{{^a}}".to_string(), true)), "{{^a}}"),
+    (Mark(InverseBlock), "^"),
+  ]);
+}
+
+#[test]
+fn tokenize_raw_block() {
+  use rumblebars::TokenKind::{self, Mark, Expression};
+  use rumblebars::TagMark::BlockEnd;
+  use rumblebars::Token::{TokRawBlockStart, TokRawBlockEnd, TokRaw};
+  use rumblebars::HBToken::{TokPathEntry, TokParamStart};
+
+  assert_eq!(tokens("{{{{raw a}}}}{{b}}{{{{/raw}}}}\n"), vec![
+    (TokenKind::Template(TokRawBlockStart("{{{{raw a}}}}".to_string())), "{{{{raw a}}}}"),
+    (Expression(TokPathEntry("raw".to_string())), "raw"),
+    (Expression(TokParamStart), " "),
+    (Expression(TokPathEntry("a".to_string())), "a"),
+    (TokenKind::Template(TokRaw("{{".to_string())), "{{"),
+    (TokenKind::Template(TokRaw("b}}".to_string())), "b}}"),
+    (TokenKind::Template(TokRawBlockEnd("{{{{/raw}}}}".to_string())), "{{{{/raw}}}}"),
+    (Mark(BlockEnd), "/"),
+    (Expression(TokPathEntry("raw".to_string())), "raw"),
+    (TokenKind::Template(TokRaw("\n".to_string())), "\n"),
+  ]);
+}
+
+#[test]
+fn tokenize_decorators_and_partials() {
+  use rumblebars::TokenKind::{self, Mark, Expression};
+  use rumblebars::TagMark::{Block, Decorator, Partial};
+  use rumblebars::Token::{TokBlockExp, TokSimpleExp, TokPartialExp};
+  use rumblebars::HBToken::{TokPathEntry, TokParamStart, TokStringParam, TokNoWhiteSpaceBefore};
+
+  assert_eq!(tokens("{{#*inline \"p\"}}{{~*d}}{{#> layout}}{{> p}}"), vec![
+    (TokenKind::Template(TokBlockExp("{{#*inline \"p\"}}".to_string(), false)), "{{#*inline \"p\"}}"),
+    (Mark(Block), "#"),
+    (Mark(Decorator), "*"),
+    (Expression(TokPathEntry("inline".to_string())), "inline"),
+    (Expression(TokParamStart), " "),
+    (Expression(TokStringParam("p".to_string())), "\"p\""),
+    (TokenKind::Template(TokSimpleExp("{{~*d}}".to_string())), "{{~*d}}"),
+    (Expression(TokNoWhiteSpaceBefore), "~"),
+    (Mark(Decorator), "*"),
+    (Expression(TokPathEntry("d".to_string())), "d"),
+    (TokenKind::Template(TokBlockExp("{{#> layout}}".to_string(), false)), "{{#> layout}}"),
+    (Mark(Block), "#"),
+    (Mark(Partial), ">"),
+    (Expression(TokPathEntry("layout".to_string())), "layout"),
+    (TokenKind::Template(TokPartialExp("{{> p}}".to_string())), "{{> p}}"),
+    (Mark(Partial), ">"),
+    (Expression(TokPathEntry("p".to_string())), "p"),
+  ]);
+
+  // partial names are literal, as they are parsed
+  assert_eq!(tokens("{{> a/b.c d}}{{#> e/f}}{{/e/f}}")[..7], [
+    (TokenKind::Template(TokPartialExp("{{> a/b.c d}}".to_string())), "{{> a/b.c d}}"),
+    (Mark(Partial), ">"),
+    (Expression(TokPathEntry("a/b.c".to_string())), "a/b.c"),
+    (Expression(TokParamStart), " "),
+    (Expression(TokPathEntry("d".to_string())), "d"),
+    (TokenKind::Template(TokBlockExp("{{#> e/f}}".to_string(), false)), "{{#> e/f}}"),
+    (Mark(Block), "#"),
+  ]);
+  assert_eq!(tokens("{{#> e/f}}")[3], (Expression(TokPathEntry("e/f".to_string())), "e/f"));
+}
+
+#[test]
+fn tokenize_set_delimiters() {
+  use rumblebars::TokenKind::{self, Mark, Expression};
+  use rumblebars::TagMark::Partial;
+  use rumblebars::Token::{TokSimpleExp, TokSetDelimiters, TokPartialExp, TokRaw};
+  use rumblebars::HBToken::{TokPathEntry, TokTrailingWhiteSpace};
+
+  let template = "{{a}}\n{{=<% %>=}}\n<%b%>\n<%> c/d%>{{e}}<%={{ }}=%>{{f}}";
+  let tokens: Vec<_> = ::rumblebars::tokenize(template).map(|(kind, span)| (kind, &template[span.start..span.end], span.line)).collect();
+
+  assert_eq!(tokens, vec![
+    (TokenKind::Template(TokSimpleExp("{{a}}\n".to_string())), "{{a}}\n", 1),
+    (Expression(TokPathEntry("a".to_string())), "a", 1),
+    (Expression(TokTrailingWhiteSpace("\n".to_string())), "\n", 1),
+    (TokenKind::Template(TokSetDelimiters("{{=<% %>=}}\n".to_string())), "{{=<% %>=}}\n", 2),
+    (TokenKind::Template(TokSimpleExp("<%b%>\n".to_string())), "<%b%>\n", 3),
+    (Expression(TokPathEntry("b".to_string())), "b", 3),
+    (Expression(TokTrailingWhiteSpace("\n".to_string())), "\n", 3),
+    (TokenKind::Template(TokPartialExp("<%> c/d%>".to_string())), "<%> c/d%>", 4),
+    (Mark(Partial), ">", 4),
+    (Expression(TokPathEntry("c/d".to_string())), "c/d", 4),
+    (TokenKind::Template(TokRaw("{{e}}".to_string())), "{{e}}", 4),
+    (TokenKind::Template(TokSetDelimiters("<%={{ }}=%>".to_string())), "<%={{ }}=%>", 4),
+    (TokenKind::Template(TokSimpleExp("{{f}}".to_string())), "{{f}}", 4),
+    (Expression(TokPathEntry("f".to_string())), "f", 4),
+  ]);
 }